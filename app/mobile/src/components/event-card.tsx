@@ -23,7 +23,7 @@ const EventComponent: React.FC<EventComponentProps> = ({ event, fx }) => {
                 <Text style={styles.eventName}>{event.name}</Text>
                 <Text style={styles.eventInfo}>{event.location}</Text>
                 <Text style={styles.eventInfo}>Tickets: {event.available} / {event.capacity}</Text>
-                <Text style={styles.eventPrice}>${event.price}</Text>
+                <Text style={styles.eventPrice}>{event.price.display}</Text>
                 <Button title="Buy Tickets" onPress={() => fx(event.id)} />
             </View>
         </View>
@@ -17,12 +17,12 @@ export async function fetchEvents(): Promise<Event[]> {
 
         const events: Event[] = rawEvents.map((e: any) => ({
             id: e.id,
-            name: e.name,
+            name: e.title,
             location: e.location,
             capacity: e.capacity,
             available: e.available,
-            price: 0,
-            cardImageUrl: e.card_image_url || null
+            price: e.price,
+            cardImageUrl: e.image_url || null
         }));
 
         return events;
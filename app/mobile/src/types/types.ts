@@ -1,8 +1,14 @@
+export interface Money {
+    cents: number;
+    currency: string;
+    display: string;
+}
+
 export interface Ticket {
     id: number;
     eventId: number;
     holderName: string;
-    price: number;
+    price: Money;
 }
 
 export interface Event {
@@ -11,7 +17,7 @@ export interface Event {
     location: String;
     capacity: number;
     available: number;
-    price: number;
+    price: Money;
     cardImageUrl: string; 
 }
 
@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OrderId(pub u64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+    pub id: OrderId,
+    pub owner: i32,
+    pub side: Side,
+    pub price_cents: u64,
+    pub quantity: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    pub maker_order_id: OrderId,
+    pub taker_order_id: OrderId,
+    pub buyer: i32,
+    pub seller: i32,
+    pub price_cents: u64,
+    pub quantity: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Level {
+    pub price_cents: u64,
+    pub quantity: u64,
+    pub orders: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Depth {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    ZeroPrice,
+    ZeroQuantity,
+    DuplicateOrder(OrderId),
+    UnknownOrder(OrderId),
+}
+
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::ZeroPrice => write!(f, "Order price must be greater than 0"),
+            OrderError::ZeroQuantity => write!(f, "Order quantity must be greater than 0"),
+            OrderError::DuplicateOrder(id) => write!(f, "Order {} is already on the book", id.0),
+            OrderError::UnknownOrder(id) => write!(f, "Order {} is not resting on the book", id.0),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+/// Result of placing an order: the fills it produced and, if anything was
+/// left over, the part that now rests on the book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub order_id: OrderId,
+    pub fills: Vec<Fill>,
+    pub resting: Option<Order>,
+}
+
+/// Limit order book for a single event and ticket tier.
+///
+/// Orders match at the best price first and, within a price level, in the
+/// order they arrived. Trades execute at the resting (maker) order's price.
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    bids: BTreeMap<u64, VecDeque<Order>>,
+    asks: BTreeMap<u64, VecDeque<Order>>,
+    index: HashMap<OrderId, (Side, u64)>,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn place(&mut self, mut taker: Order) -> Result<Placement, OrderError> {
+        if taker.price_cents == 0 {
+            return Err(OrderError::ZeroPrice);
+        }
+        if taker.quantity == 0 {
+            return Err(OrderError::ZeroQuantity);
+        }
+        if self.index.contains_key(&taker.id) {
+            return Err(OrderError::DuplicateOrder(taker.id));
+        }
+
+        let fills = self.match_order(&mut taker);
+
+        let resting = if taker.quantity > 0 {
+            self.index.insert(taker.id, (taker.side, taker.price_cents));
+            self.side_mut(taker.side)
+                .entry(taker.price_cents)
+                .or_default()
+                .push_back(taker.clone());
+            Some(taker.clone())
+        } else {
+            None
+        };
+
+        Ok(Placement {
+            order_id: taker.id,
+            fills,
+            resting,
+        })
+    }
+
+    pub fn cancel(&mut self, order_id: OrderId) -> Result<Order, OrderError> {
+        let (side, price) = self
+            .index
+            .remove(&order_id)
+            .ok_or(OrderError::UnknownOrder(order_id))?;
+
+        let levels = self.side_mut(side);
+        let queue = levels
+            .get_mut(&price)
+            .expect("indexed order must have a price level");
+        let position = queue
+            .iter()
+            .position(|o| o.id == order_id)
+            .expect("indexed order must be queued at its price level");
+        let order = queue.remove(position).expect("position is in bounds");
+        if queue.is_empty() {
+            levels.remove(&price);
+        }
+
+        Ok(order)
+    }
+
+    pub fn order(&self, order_id: OrderId) -> Option<&Order> {
+        let (side, price) = self.index.get(&order_id)?;
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        levels.get(price)?.iter().find(|o| o.id == order_id)
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids
+            .iter()
+            .next_back()
+            .map(|(price, queue)| level(*price, queue))
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks
+            .iter()
+            .next()
+            .map(|(price, queue)| level(*price, queue))
+    }
+
+    /// Aggregated quantity at the best `levels` prices on each side.
+    pub fn depth(&self, levels: usize) -> Depth {
+        Depth {
+            bids: self
+                .bids
+                .iter()
+                .rev()
+                .take(levels)
+                .map(|(price, queue)| level(*price, queue))
+                .collect(),
+            asks: self
+                .asks
+                .iter()
+                .take(levels)
+                .map(|(price, queue)| level(*price, queue))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<u64, VecDeque<Order>> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+
+    fn match_order(&mut self, taker: &mut Order) -> Vec<Fill> {
+        let mut fills = Vec::new();
+
+        while taker.quantity > 0 {
+            let opposite = match taker.side {
+                Side::Bid => &mut self.asks,
+                Side::Ask => &mut self.bids,
+            };
+            let best = match taker.side {
+                Side::Bid => opposite.first_entry(),
+                Side::Ask => opposite.last_entry(),
+            };
+            let Some(mut entry) = best else {
+                break;
+            };
+
+            let price = *entry.key();
+            let crosses = match taker.side {
+                Side::Bid => price <= taker.price_cents,
+                Side::Ask => price >= taker.price_cents,
+            };
+            if !crosses {
+                break;
+            }
+
+            let queue = entry.get_mut();
+            while taker.quantity > 0 {
+                let Some(maker) = queue.front_mut() else {
+                    break;
+                };
+
+                let quantity = taker.quantity.min(maker.quantity);
+                let (buyer, seller) = match taker.side {
+                    Side::Bid => (taker.owner, maker.owner),
+                    Side::Ask => (maker.owner, taker.owner),
+                };
+                fills.push(Fill {
+                    maker_order_id: maker.id,
+                    taker_order_id: taker.id,
+                    buyer,
+                    seller,
+                    price_cents: price,
+                    quantity,
+                });
+
+                taker.quantity -= quantity;
+                maker.quantity -= quantity;
+                if maker.quantity == 0 {
+                    let filled = queue.pop_front().expect("front was just inspected");
+                    self.index.remove(&filled.id);
+                }
+            }
+
+            if queue.is_empty() {
+                entry.remove();
+            }
+        }
+
+        fills
+    }
+}
+
+fn level(price_cents: u64, queue: &VecDeque<Order>) -> Level {
+    Level {
+        price_cents,
+        quantity: queue.iter().map(|o| o.quantity).sum(),
+        orders: queue.len(),
+    }
+}
@@ -0,0 +1,5 @@
+pub mod book;
+pub mod market;
+
+pub use book::{Depth, Fill, Level, Order, OrderBook, OrderError, OrderId, Placement, Side};
+pub use market::{BookKey, Market};
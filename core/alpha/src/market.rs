@@ -0,0 +1,109 @@
+use crate::book::{Depth, Level, Order, OrderBook, OrderError, OrderId, Placement, Side};
+use std::collections::HashMap;
+
+/// Identifies a book: resale orders only match within one event and tier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BookKey {
+    pub event_id: i32,
+    pub tier: String,
+}
+
+impl BookKey {
+    pub fn new(event_id: i32, tier: impl Into<String>) -> Self {
+        BookKey {
+            event_id,
+            tier: tier.into(),
+        }
+    }
+}
+
+/// All order books of the secondary market. Order ids are assigned here so
+/// they are unique across books and increase with arrival time.
+#[derive(Debug, Default)]
+pub struct Market {
+    books: HashMap<BookKey, OrderBook>,
+    orders: HashMap<OrderId, BookKey>,
+    next_id: u64,
+}
+
+impl Market {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn place(
+        &mut self,
+        key: BookKey,
+        owner: i32,
+        side: Side,
+        price_cents: u64,
+        quantity: u64,
+    ) -> Result<Placement, OrderError> {
+        let order = Order {
+            id: OrderId(self.next_id + 1),
+            owner,
+            side,
+            price_cents,
+            quantity,
+        };
+
+        let book = self.books.entry(key.clone()).or_default();
+        let placement = match book.place(order) {
+            Ok(placement) => placement,
+            Err(e) => {
+                if book.is_empty() {
+                    self.books.remove(&key);
+                }
+                return Err(e);
+            }
+        };
+        self.next_id += 1;
+
+        for fill in &placement.fills {
+            if book.order(fill.maker_order_id).is_none() {
+                self.orders.remove(&fill.maker_order_id);
+            }
+        }
+        if book.is_empty() {
+            self.books.remove(&key);
+        } else if placement.resting.is_some() {
+            self.orders.insert(placement.order_id, key);
+        }
+
+        Ok(placement)
+    }
+
+    pub fn cancel(&mut self, order_id: OrderId) -> Result<Order, OrderError> {
+        let key = self
+            .orders
+            .remove(&order_id)
+            .ok_or(OrderError::UnknownOrder(order_id))?;
+        let book = self
+            .books
+            .get_mut(&key)
+            .expect("every tracked order has a book");
+        let order = book.cancel(order_id)?;
+        if book.is_empty() {
+            self.books.remove(&key);
+        }
+        Ok(order)
+    }
+
+    pub fn book(&self, key: &BookKey) -> Option<&OrderBook> {
+        self.books.get(key)
+    }
+
+    pub fn best_bid(&self, key: &BookKey) -> Option<Level> {
+        self.book(key).and_then(OrderBook::best_bid)
+    }
+
+    pub fn best_ask(&self, key: &BookKey) -> Option<Level> {
+        self.book(key).and_then(OrderBook::best_ask)
+    }
+
+    pub fn depth(&self, key: &BookKey, levels: usize) -> Depth {
+        self.book(key)
+            .map(|book| book.depth(levels))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,208 @@
+use alpha::{BookKey, Fill, Level, Market, OrderError, OrderId, Side};
+
+fn key() -> BookKey {
+    BookKey::new(1, "GA")
+}
+
+#[test]
+fn non_crossing_orders_rest_on_the_book() {
+    let mut market = Market::new();
+    let bid = market.place(key(), 10, Side::Bid, 4_000, 2).unwrap();
+    let ask = market.place(key(), 20, Side::Ask, 5_000, 1).unwrap();
+
+    assert!(bid.fills.is_empty());
+    assert!(ask.fills.is_empty());
+    assert_eq!(
+        market.best_bid(&key()),
+        Some(Level {
+            price_cents: 4_000,
+            quantity: 2,
+            orders: 1
+        })
+    );
+    assert_eq!(
+        market.best_ask(&key()),
+        Some(Level {
+            price_cents: 5_000,
+            quantity: 1,
+            orders: 1
+        })
+    );
+}
+
+#[test]
+fn crossing_bid_fills_at_the_resting_ask_price() {
+    let mut market = Market::new();
+    let ask = market.place(key(), 20, Side::Ask, 5_000, 1).unwrap();
+    let bid = market.place(key(), 10, Side::Bid, 6_000, 1).unwrap();
+
+    assert_eq!(
+        bid.fills,
+        vec![Fill {
+            maker_order_id: ask.order_id,
+            taker_order_id: bid.order_id,
+            buyer: 10,
+            seller: 20,
+            price_cents: 5_000,
+            quantity: 1,
+        }]
+    );
+    assert!(bid.resting.is_none());
+    assert!(market.book(&key()).is_none());
+}
+
+#[test]
+fn better_prices_match_first_then_earlier_orders() {
+    let mut market = Market::new();
+    let late_cheap = market.place(key(), 1, Side::Ask, 4_500, 1).unwrap();
+    let first = market.place(key(), 2, Side::Ask, 5_000, 1).unwrap();
+    let second = market.place(key(), 3, Side::Ask, 5_000, 1).unwrap();
+
+    let bid = market.place(key(), 9, Side::Bid, 5_000, 2).unwrap();
+    let makers: Vec<OrderId> = bid.fills.iter().map(|f| f.maker_order_id).collect();
+    assert_eq!(makers, vec![late_cheap.order_id, first.order_id]);
+    assert_eq!(bid.fills[0].price_cents, 4_500);
+    assert_eq!(bid.fills[1].price_cents, 5_000);
+
+    assert_eq!(
+        market
+            .book(&key())
+            .unwrap()
+            .order(second.order_id)
+            .unwrap()
+            .quantity,
+        1
+    );
+}
+
+#[test]
+fn partial_fill_leaves_the_remainder_resting() {
+    let mut market = Market::new();
+    market.place(key(), 20, Side::Ask, 5_000, 1).unwrap();
+    let bid = market.place(key(), 10, Side::Bid, 5_000, 3).unwrap();
+
+    assert_eq!(bid.fills.len(), 1);
+    let resting = bid.resting.unwrap();
+    assert_eq!(resting.quantity, 2);
+    assert_eq!(market.best_ask(&key()), None);
+    assert_eq!(market.best_bid(&key()).unwrap().quantity, 2);
+}
+
+#[test]
+fn ask_sweeps_multiple_bid_levels() {
+    let mut market = Market::new();
+    market.place(key(), 1, Side::Bid, 7_000, 1).unwrap();
+    market.place(key(), 2, Side::Bid, 6_000, 2).unwrap();
+    market.place(key(), 3, Side::Bid, 5_000, 1).unwrap();
+
+    let ask = market.place(key(), 9, Side::Ask, 6_000, 4).unwrap();
+    let fills: Vec<(i32, u64, u64)> = ask
+        .fills
+        .iter()
+        .map(|f| (f.buyer, f.price_cents, f.quantity))
+        .collect();
+    assert_eq!(fills, vec![(1, 7_000, 1), (2, 6_000, 2)]);
+    assert_eq!(ask.resting.unwrap().quantity, 1);
+    assert_eq!(market.best_bid(&key()).unwrap().price_cents, 5_000);
+    assert_eq!(market.best_ask(&key()).unwrap().price_cents, 6_000);
+}
+
+#[test]
+fn cancel_removes_the_order_and_empty_levels() {
+    let mut market = Market::new();
+    let first = market.place(key(), 1, Side::Bid, 5_000, 1).unwrap();
+    let second = market.place(key(), 2, Side::Bid, 4_000, 1).unwrap();
+
+    let cancelled = market.cancel(first.order_id).unwrap();
+    assert_eq!(cancelled.owner, 1);
+    assert_eq!(market.best_bid(&key()).unwrap().price_cents, 4_000);
+
+    assert_eq!(
+        market.cancel(first.order_id),
+        Err(OrderError::UnknownOrder(first.order_id))
+    );
+
+    market.cancel(second.order_id).unwrap();
+    assert!(market.book(&key()).is_none());
+}
+
+#[test]
+fn filled_orders_can_no_longer_be_cancelled() {
+    let mut market = Market::new();
+    let ask = market.place(key(), 1, Side::Ask, 5_000, 1).unwrap();
+    market.place(key(), 2, Side::Bid, 5_000, 1).unwrap();
+
+    assert_eq!(
+        market.cancel(ask.order_id),
+        Err(OrderError::UnknownOrder(ask.order_id))
+    );
+}
+
+#[test]
+fn books_are_isolated_per_event_and_tier() {
+    let mut market = Market::new();
+    let vip = BookKey::new(1, "VIP");
+    let other_event = BookKey::new(2, "GA");
+
+    market.place(key(), 1, Side::Ask, 5_000, 1).unwrap();
+    let vip_bid = market.place(vip.clone(), 2, Side::Bid, 9_000, 1).unwrap();
+    let other_bid = market
+        .place(other_event.clone(), 3, Side::Bid, 9_000, 1)
+        .unwrap();
+
+    assert!(vip_bid.fills.is_empty());
+    assert!(other_bid.fills.is_empty());
+    assert_eq!(market.best_ask(&key()).unwrap().quantity, 1);
+    assert_eq!(market.best_bid(&vip).unwrap().price_cents, 9_000);
+}
+
+#[test]
+fn depth_aggregates_levels_in_price_order() {
+    let mut market = Market::new();
+    market.place(key(), 1, Side::Bid, 4_000, 1).unwrap();
+    market.place(key(), 2, Side::Bid, 4_500, 2).unwrap();
+    market.place(key(), 3, Side::Bid, 4_500, 1).unwrap();
+    market.place(key(), 4, Side::Ask, 6_000, 1).unwrap();
+    market.place(key(), 5, Side::Ask, 5_500, 3).unwrap();
+
+    let depth = market.depth(&key(), 1);
+    assert_eq!(
+        depth.bids,
+        vec![Level {
+            price_cents: 4_500,
+            quantity: 3,
+            orders: 2
+        }]
+    );
+    assert_eq!(
+        depth.asks,
+        vec![Level {
+            price_cents: 5_500,
+            quantity: 3,
+            orders: 1
+        }]
+    );
+
+    let full = market.depth(&key(), 10);
+    assert_eq!(full.bids.len(), 2);
+    assert_eq!(full.asks.len(), 2);
+    assert_eq!(full.bids[1].price_cents, 4_000);
+    assert_eq!(full.asks[1].price_cents, 6_000);
+}
+
+#[test]
+fn invalid_orders_are_rejected_without_consuming_ids() {
+    let mut market = Market::new();
+    assert_eq!(
+        market.place(key(), 1, Side::Bid, 0, 1),
+        Err(OrderError::ZeroPrice)
+    );
+    assert_eq!(
+        market.place(key(), 1, Side::Bid, 100, 0),
+        Err(OrderError::ZeroQuantity)
+    );
+    assert!(market.book(&key()).is_none());
+
+    let placed = market.place(key(), 1, Side::Bid, 100, 1).unwrap();
+    assert_eq!(placed.order_id, OrderId(1));
+}
@@ -0,0 +1,108 @@
+use anyhow::{anyhow, bail, Context, Result};
+use omicron::checkin::{Manifest, Scan, ScanOutcome, SyncRequest};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: String,
+    refresh_token: String,
+}
+
+/// Talks to omicron as the event's organizer. Access tokens are short
+/// lived, so the client holds a refresh token and rotates it as needed;
+/// callers should persist `refresh_token()` whenever it changes.
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    event_id: i32,
+    access_token: Option<String>,
+    refresh_token: String,
+}
+
+impl Client {
+    pub fn new(base_url: impl Into<String>, event_id: i32, refresh_token: String) -> Self {
+        Client {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            event_id,
+            access_token: None,
+            refresh_token,
+        }
+    }
+
+    pub fn refresh_token(&self) -> &str {
+        &self.refresh_token
+    }
+
+    pub async fn manifest(&mut self) -> Result<Manifest> {
+        let url = format!("{}/events/{}/manifest", self.base_url, self.event_id);
+        let response = self.send(|http| http.get(&url)).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Hands `scans` to omicron and returns its verdict for each, in order.
+    pub async fn sync(&mut self, door: &str, scans: Vec<Scan>) -> Result<Vec<ScanOutcome>> {
+        let url = format!("{}/events/{}/checkins", self.base_url, self.event_id);
+        let request = SyncRequest {
+            door: door.to_string(),
+            scans,
+        };
+        let response = self.send(|http| http.post(&url).json(&request)).await?;
+        Ok(response.json().await?)
+    }
+
+    async fn authenticate(&mut self) -> Result<String> {
+        let response = self
+            .http
+            .post(format!("{}/token/refresh", self.base_url))
+            .json(&serde_json::json!({ "refresh_token": self.refresh_token }))
+            .send()
+            .await
+            .context("failed to reach omicron")?;
+
+        if !response.status().is_success() {
+            bail!(
+                "omicron refused the door's session ({}): {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            );
+        }
+
+        let tokens: TokenResponse = response.json().await?;
+        self.refresh_token = tokens.refresh_token;
+        self.access_token = Some(tokens.token.clone());
+        Ok(tokens.token)
+    }
+
+    /// Sends an authenticated request, refreshing the access token once if
+    /// omicron says it has expired.
+    async fn send<F>(&mut self, build: F) -> Result<Response>
+    where
+        F: Fn(&reqwest::Client) -> RequestBuilder,
+    {
+        for attempt in 0..2 {
+            let token = match (&self.access_token, attempt) {
+                (Some(token), 0) => token.clone(),
+                _ => self.authenticate().await?,
+            };
+
+            let response = build(&self.http)
+                .bearer_auth(token)
+                .send()
+                .await
+                .context("failed to reach omicron")?;
+
+            match response.status() {
+                StatusCode::UNAUTHORIZED => self.access_token = None,
+                status if status.is_success() => return Ok(response),
+                status => bail!(
+                    "omicron returned {}: {}",
+                    status,
+                    response.text().await.unwrap_or_default()
+                ),
+            }
+        }
+        Err(anyhow!("omicron rejected a freshly refreshed token"))
+    }
+}
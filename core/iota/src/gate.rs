@@ -0,0 +1,179 @@
+use crate::store::{LocalCheckIn, Store};
+use chrono::{DateTime, Utc};
+use omicron::checkin::{Manifest, ManifestTicket, Scan, ScanOutcome};
+use omicron::credentials::{CredentialError, Verifier};
+use omicron::models::ticket::TicketStatus;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+
+/// What the door shows staff after a scan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "result")]
+pub enum ScanResult {
+    /// `unlisted` tickets were sold after the last sync; their signature
+    /// is valid but omicron has the final say when the scan is reconciled.
+    Admitted {
+        ticket_id: i32,
+        holder_id: i32,
+        unlisted: bool,
+    },
+    AlreadyCheckedIn {
+        ticket_id: i32,
+        door: Option<String>,
+        checked_in_at: DateTime<Utc>,
+    },
+    Rejected {
+        reason: String,
+    },
+}
+
+/// One door's view of an event: the last synced manifest plus whatever it
+/// has admitted since. Scans never need a connection.
+pub struct Gate {
+    door: String,
+    verifier: Verifier,
+    tickets: HashMap<i32, ManifestTicket>,
+    synced_at: DateTime<Utc>,
+    store: Store,
+}
+
+impl Gate {
+    pub fn new(door: String, manifest: Manifest, store: Store) -> Result<Self, CredentialError> {
+        let verifier = Verifier::from_public_key(manifest.event_id, &manifest.public_key)?;
+        let mut gate = Gate {
+            door,
+            verifier,
+            tickets: HashMap::new(),
+            synced_at: manifest.generated_at,
+            store,
+        };
+        gate.load(manifest);
+        Ok(gate)
+    }
+
+    fn load(&mut self, manifest: Manifest) {
+        self.synced_at = manifest.generated_at;
+        self.tickets = manifest
+            .tickets
+            .into_iter()
+            .map(|ticket| (ticket.ticket_id, ticket))
+            .collect();
+    }
+
+    pub fn event_id(&self) -> i32 {
+        self.verifier.event_id()
+    }
+
+    pub fn door(&self) -> &str {
+        &self.door
+    }
+
+    pub fn synced_at(&self) -> DateTime<Utc> {
+        self.synced_at
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Replaces the manifest with a fresher one and caches it on disk.
+    pub fn update_manifest(&mut self, manifest: Manifest) -> io::Result<()> {
+        if manifest.event_id != self.event_id() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "manifest is for event {}, not {}",
+                    manifest.event_id,
+                    self.event_id()
+                ),
+            ));
+        }
+        self.store.save_manifest(&manifest)?;
+        self.load(manifest);
+        Ok(())
+    }
+
+    /// Checks `token` against the event's public key and what this door
+    /// knows, and records the admission before returning.
+    pub fn scan(&mut self, token: &str, now: DateTime<Utc>) -> io::Result<ScanResult> {
+        let credential = match self.verifier.verify(token) {
+            Ok(credential) => credential,
+            Err(e) => {
+                return Ok(ScanResult::Rejected {
+                    reason: e.to_string(),
+                })
+            }
+        };
+        let ticket_id = credential.ticket_id;
+
+        if let Some(check_in) = self.store.check_in(ticket_id) {
+            return Ok(ScanResult::AlreadyCheckedIn {
+                ticket_id,
+                door: Some(self.door.clone()),
+                checked_in_at: check_in.scanned_at,
+            });
+        }
+
+        let listed = self.tickets.get(&ticket_id);
+        if let Some(ticket) = listed {
+            if ticket.holder_id != credential.holder_id || ticket.nonce != credential.nonce {
+                return Ok(ScanResult::Rejected {
+                    reason: format!("Credential for ticket {} is no longer valid", ticket_id),
+                });
+            }
+            if let Some(checked_in_at) = ticket.checked_in_at {
+                return Ok(ScanResult::AlreadyCheckedIn {
+                    ticket_id,
+                    door: ticket.checked_in_door.clone(),
+                    checked_in_at,
+                });
+            }
+            if let Err(reason) = ticket.status.check_in() {
+                return Ok(ScanResult::Rejected { reason });
+            }
+        }
+
+        self.store.record(LocalCheckIn {
+            ticket_id,
+            holder_id: credential.holder_id,
+            credential: token.trim().to_string(),
+            scanned_at: now,
+        })?;
+
+        Ok(ScanResult::Admitted {
+            ticket_id,
+            holder_id: credential.holder_id,
+            unlisted: listed.is_none(),
+        })
+    }
+
+    /// Scans omicron has not seen yet, oldest first.
+    pub fn pending(&self) -> Vec<(i32, Scan)> {
+        self.store
+            .pending()
+            .into_iter()
+            .map(|check_in| {
+                (
+                    check_in.ticket_id,
+                    Scan {
+                        credential: check_in.credential,
+                        scanned_at: check_in.scanned_at,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Records omicron's answer for a scan from `pending`.
+    pub fn reconciled(&mut self, ticket_id: i32, outcome: ScanOutcome) -> io::Result<()> {
+        if let Some(ticket) = self.tickets.get_mut(&ticket_id) {
+            if let ScanOutcome::Admitted(check_in) = &outcome {
+                ticket.status = TicketStatus::CheckedIn;
+                ticket.checked_in_at = Some(check_in.checked_in_at);
+                ticket.checked_in_door = check_in.door.clone();
+            }
+        }
+        self.store.reconciled(ticket_id, outcome)
+    }
+}
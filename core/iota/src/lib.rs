@@ -0,0 +1,188 @@
+pub mod client;
+pub mod gate;
+pub mod store;
+
+use crate::client::Client;
+use crate::gate::Gate;
+use crate::store::Store;
+use anyhow::{Context, Result};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use colored::*;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use simplelog::{ColorChoice, LevelFilter, TermLogger, TerminalMode};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+type SharedGate = Arc<Mutex<Gate>>;
+
+fn required(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| panic!("{} is not set", name))
+}
+
+/// Door check-in service. Scanners post credentials to it; it admits
+/// people from the last synced manifest and syncs with omicron in the
+/// background whenever it can reach it.
+#[tokio::main]
+pub async fn run() -> Result<()> {
+    dotenv().ok();
+    println!("starting {} ...", "IOTA".cyan().bold());
+
+    let _ = TermLogger::init(
+        LevelFilter::Info,
+        simplelog::Config::default(),
+        TerminalMode::Mixed,
+        ColorChoice::Auto,
+    );
+
+    let event_id: i32 = required("IOTA_EVENT_ID")
+        .parse()
+        .context("IOTA_EVENT_ID must be an event id")?;
+    let door = required("IOTA_DOOR");
+    let omicron_url = required("OMICRON_URL");
+    let address = env::var("IOTA_ADDRESS").unwrap_or_else(|_| "127.0.0.1:4000".into());
+    let store_dir = env::var("IOTA_STORE_DIR").unwrap_or_else(|_| "iota-data".into());
+    let sync_interval = env::var("IOTA_SYNC_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(15);
+
+    let store = Store::open(format!("{}/event-{}", store_dir, event_id))?;
+    let refresh_token = match store.load_session()? {
+        Some(token) => token,
+        None => required("IOTA_REFRESH_TOKEN"),
+    };
+    let mut client = Client::new(omicron_url, event_id, refresh_token);
+
+    // Start from a fresh manifest if omicron is reachable, otherwise from
+    // whatever was cached by the last run.
+    let manifest = match client.manifest().await {
+        Ok(manifest) => {
+            store.save_manifest(&manifest)?;
+            store.save_session(client.refresh_token())?;
+            manifest
+        }
+        Err(e) => {
+            log::warn!("starting offline: {:#}", e);
+            store
+                .load_manifest()?
+                .context("omicron is unreachable and no manifest has been cached yet")?
+        }
+    };
+
+    let gate = Arc::new(Mutex::new(Gate::new(door, manifest, store)?));
+    tokio::spawn(sync_loop(
+        gate.clone(),
+        client,
+        Duration::from_secs(sync_interval),
+    ));
+
+    let app = Router::new()
+        .route("/scan", post(scan))
+        .route("/status", get(status))
+        .with_state(gate);
+
+    let listener = TcpListener::bind(&address)
+        .await
+        .expect("error creating TCP listener...");
+    axum::serve(listener, app)
+        .await
+        .expect("error serving app...");
+    Ok(())
+}
+
+/// Pushes pending scans and pulls a fresh manifest. Failures are expected
+/// while offline and only logged; the next tick tries again.
+pub async fn sync(gate: &Mutex<Gate>, client: &mut Client) -> Result<()> {
+    let (door, pending) = {
+        let gate = gate.lock().expect("gate lock poisoned");
+        (gate.door().to_string(), gate.pending())
+    };
+
+    if !pending.is_empty() {
+        let (ticket_ids, scans): (Vec<i32>, Vec<_>) = pending.into_iter().unzip();
+        let outcomes = client.sync(&door, scans).await?;
+
+        let mut gate = gate.lock().expect("gate lock poisoned");
+        for (ticket_id, outcome) in ticket_ids.into_iter().zip(outcomes) {
+            gate.reconciled(ticket_id, outcome)?;
+        }
+    }
+
+    let manifest = client.manifest().await?;
+    gate.lock()
+        .expect("gate lock poisoned")
+        .update_manifest(manifest)?;
+    Ok(())
+}
+
+async fn sync_loop(gate: SharedGate, mut client: Client, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut session = client.refresh_token().to_string();
+
+    loop {
+        ticker.tick().await;
+        if let Err(e) = sync(&gate, &mut client).await {
+            log::warn!("sync with omicron failed: {:#}", e);
+        }
+
+        if client.refresh_token() != session {
+            session = client.refresh_token().to_string();
+            let gate = gate.lock().expect("gate lock poisoned");
+            if let Err(e) = gate.store().save_session(&session) {
+                log::error!("failed to save rotated session: {}", e);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ScanRequest {
+    pub credential: String,
+}
+
+async fn scan(State(gate): State<SharedGate>, Json(req): Json<ScanRequest>) -> impl IntoResponse {
+    let result = gate
+        .lock()
+        .expect("gate lock poisoned")
+        .scan(&req.credential, Utc::now());
+
+    match result {
+        Ok(result) => Ok(Json(result)),
+        Err(e) => {
+            log::error!("failed to record check-in: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    event_id: i32,
+    door: String,
+    synced_at: chrono::DateTime<Utc>,
+    admitted: usize,
+    pending: usize,
+    issues: Vec<omicron::checkin::ScanOutcome>,
+}
+
+async fn status(State(gate): State<SharedGate>) -> impl IntoResponse {
+    let gate = gate.lock().expect("gate lock poisoned");
+    Json(StatusResponse {
+        event_id: gate.event_id(),
+        door: gate.door().to_string(),
+        synced_at: gate.synced_at(),
+        admitted: gate.store().admitted(),
+        pending: gate.store().pending().len(),
+        issues: gate.store().issues().to_vec(),
+    })
+}
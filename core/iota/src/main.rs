@@ -1,3 +1,3 @@
 fn main() {
-    println!("Hello, world!");
+    iota::run().expect("iota failed");
 }
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Utc};
+use omicron::checkin::{Manifest, ScanOutcome};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const JOURNAL: &str = "checkins.jsonl";
+const MANIFEST: &str = "manifest.json";
+const SESSION: &str = "session";
+
+/// A ticket this door let in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalCheckIn {
+    pub ticket_id: i32,
+    pub holder_id: i32,
+    pub credential: String,
+    pub scanned_at: DateTime<Utc>,
+}
+
+/// One line of the journal. Scans are appended as they happen and marked
+/// reconciled once omicron has answered for them.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind")]
+enum Entry {
+    Scanned(LocalCheckIn),
+    Reconciled {
+        ticket_id: i32,
+        outcome: ScanOutcome,
+    },
+}
+
+/// Everything a door keeps on disk so it can restart without a
+/// connection: an append-only journal of check-ins, the last manifest it
+/// synced and the session it syncs with.
+pub struct Store {
+    dir: PathBuf,
+    journal: File,
+    check_ins: HashMap<i32, LocalCheckIn>,
+    /// Ticket ids in scan order that omicron has not answered for yet.
+    pending: Vec<i32>,
+    issues: Vec<ScanOutcome>,
+}
+
+impl Store {
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Store> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join(JOURNAL);
+        let mut check_ins = HashMap::new();
+        let mut pending = Vec::new();
+        let mut issues = Vec::new();
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        for line in contents.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A torn final line from a power cut is the only thing that
+            // should fail to parse; everything before it is intact.
+            let Ok(entry) = serde_json::from_str::<Entry>(line) else {
+                log::warn!("skipping unreadable journal line in {}", path.display());
+                continue;
+            };
+            match entry {
+                Entry::Scanned(check_in) => {
+                    pending.push(check_in.ticket_id);
+                    check_ins.insert(check_in.ticket_id, check_in);
+                }
+                Entry::Reconciled { ticket_id, outcome } => {
+                    pending.retain(|id| *id != ticket_id);
+                    if !matches!(outcome, ScanOutcome::Admitted(_)) {
+                        issues.push(outcome);
+                    }
+                }
+            }
+        }
+
+        let mut journal = OpenOptions::new().create(true).append(true).open(&path)?;
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            // Keep the next entry off the torn line.
+            journal.write_all(b"\n")?;
+        }
+        Ok(Store {
+            dir,
+            journal,
+            check_ins,
+            pending,
+            issues,
+        })
+    }
+
+    fn append(&mut self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.journal.write_all(&line)?;
+        self.journal.sync_data()
+    }
+
+    /// Durably records an admission before the door opens.
+    pub fn record(&mut self, check_in: LocalCheckIn) -> io::Result<()> {
+        self.append(&Entry::Scanned(check_in.clone()))?;
+        self.pending.push(check_in.ticket_id);
+        self.check_ins.insert(check_in.ticket_id, check_in);
+        Ok(())
+    }
+
+    pub fn reconciled(&mut self, ticket_id: i32, outcome: ScanOutcome) -> io::Result<()> {
+        self.append(&Entry::Reconciled {
+            ticket_id,
+            outcome: outcome.clone(),
+        })?;
+        self.pending.retain(|id| *id != ticket_id);
+        if !matches!(outcome, ScanOutcome::Admitted(_)) {
+            self.issues.push(outcome);
+        }
+        Ok(())
+    }
+
+    pub fn check_in(&self, ticket_id: i32) -> Option<&LocalCheckIn> {
+        self.check_ins.get(&ticket_id)
+    }
+
+    pub fn admitted(&self) -> usize {
+        self.check_ins.len()
+    }
+
+    /// Check-ins omicron has not seen yet, oldest first.
+    pub fn pending(&self) -> Vec<LocalCheckIn> {
+        self.pending
+            .iter()
+            .filter_map(|id| self.check_ins.get(id))
+            .cloned()
+            .collect()
+    }
+
+    /// Conflicts and rejections omicron reported for this door's scans.
+    pub fn issues(&self) -> &[ScanOutcome] {
+        &self.issues
+    }
+
+    pub fn load_manifest(&self) -> io::Result<Option<Manifest>> {
+        match fs::read(self.dir.join(MANIFEST)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save_manifest(&self, manifest: &Manifest) -> io::Result<()> {
+        self.replace(MANIFEST, &serde_json::to_vec(manifest)?)
+    }
+
+    /// The refresh token from the last rotation, which supersedes the one
+    /// the door was configured with.
+    pub fn load_session(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.dir.join(SESSION)) {
+            Ok(token) => Ok(Some(token.trim().to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save_session(&self, refresh_token: &str) -> io::Result<()> {
+        self.replace(SESSION, refresh_token.as_bytes())
+    }
+
+    /// Writes `name` via a temporary file so a crash never leaves it half
+    /// written.
+    fn replace(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp", name));
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(tmp, self.dir.join(name))
+    }
+}
@@ -0,0 +1,200 @@
+use chrono::Utc;
+use iota::gate::{Gate, ScanResult};
+use iota::store::Store;
+use omicron::checkin::{CheckIn, Manifest, ManifestTicket, ScanOutcome};
+use omicron::credentials::{self, Credential};
+use omicron::models::ticket::TicketStatus;
+use std::sync::Once;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const EVENT_ID: i32 = 42;
+
+static SECRET: Once = Once::new();
+
+/// Doors never hold omicron's signing secret, but the tests need it to
+/// mint credentials.
+fn secret() {
+    SECRET.call_once(|| std::env::set_var("TICKET_SIGNING_SECRET", "test-ticket-secret"));
+}
+
+fn store_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("iota-test-")
+        .tempdir()
+        .unwrap()
+}
+
+fn credential(ticket_id: i32, holder_id: i32, nonce: Uuid) -> String {
+    secret();
+    Credential {
+        ticket_id,
+        event_id: EVENT_ID,
+        holder_id,
+        nonce,
+    }
+    .sign(&credentials::signing_key(EVENT_ID))
+}
+
+fn manifest(tickets: Vec<ManifestTicket>) -> Manifest {
+    secret();
+    Manifest {
+        event_id: EVENT_ID,
+        public_key: credentials::encode_public_key(
+            &credentials::signing_key(EVENT_ID).verifying_key(),
+        ),
+        generated_at: Utc::now(),
+        tickets,
+    }
+}
+
+fn sold(ticket_id: i32, holder_id: i32, nonce: Uuid) -> ManifestTicket {
+    ManifestTicket {
+        ticket_id,
+        holder_id,
+        nonce,
+        status: TicketStatus::Sold,
+        checked_in_at: None,
+        checked_in_door: None,
+    }
+}
+
+#[test]
+fn gate_admits_offline_and_catches_duplicates_and_resales() {
+    let dir = store_dir();
+    let nonce = Uuid::new_v4();
+    let resold_nonce = Uuid::new_v4();
+    let mut gate = Gate::new(
+        "north".to_string(),
+        manifest(vec![sold(1, 10, nonce), sold(2, 20, resold_nonce)]),
+        Store::open(dir.path()).unwrap(),
+    )
+    .unwrap();
+
+    let token = credential(1, 10, nonce);
+    assert_eq!(
+        gate.scan(&token, Utc::now()).unwrap(),
+        ScanResult::Admitted {
+            ticket_id: 1,
+            holder_id: 10,
+            unlisted: false
+        }
+    );
+    assert!(matches!(
+        gate.scan(&token, Utc::now()).unwrap(),
+        ScanResult::AlreadyCheckedIn { ticket_id: 1, .. }
+    ));
+
+    // The manifest says ticket 2 now belongs to someone else.
+    assert!(matches!(
+        gate.scan(&credential(2, 99, resold_nonce), Utc::now())
+            .unwrap(),
+        ScanResult::Rejected { .. }
+    ));
+
+    // Sold after the last sync: the signature is enough to get in.
+    assert!(matches!(
+        gate.scan(&credential(3, 30, Uuid::new_v4()), Utc::now())
+            .unwrap(),
+        ScanResult::Admitted { unlisted: true, .. }
+    ));
+
+    let forged = Credential {
+        ticket_id: 4,
+        event_id: EVENT_ID,
+        holder_id: 40,
+        nonce: Uuid::new_v4(),
+    }
+    .sign(&credentials::signing_key(EVENT_ID + 1));
+    assert!(matches!(
+        gate.scan(&forged, Utc::now()).unwrap(),
+        ScanResult::Rejected { .. }
+    ));
+
+    assert_eq!(gate.pending().len(), 2);
+}
+
+#[test]
+fn store_survives_restart_until_scans_are_reconciled() {
+    let dir = store_dir();
+    let nonce = Uuid::new_v4();
+    let token = credential(1, 10, nonce);
+    let scanned_at = Utc::now();
+
+    {
+        let mut gate = Gate::new(
+            "north".to_string(),
+            manifest(vec![sold(1, 10, nonce), sold(2, 20, nonce)]),
+            Store::open(dir.path()).unwrap(),
+        )
+        .unwrap();
+        gate.scan(&token, scanned_at).unwrap();
+        gate.scan(&credential(2, 20, nonce), scanned_at).unwrap();
+    }
+
+    let mut gate = Gate::new(
+        "north".to_string(),
+        manifest(vec![sold(1, 10, nonce), sold(2, 20, nonce)]),
+        Store::open(dir.path()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(gate.pending().len(), 2);
+    assert!(matches!(
+        gate.scan(&token, Utc::now()).unwrap(),
+        ScanResult::AlreadyCheckedIn { ticket_id: 1, .. }
+    ));
+
+    gate.reconciled(
+        1,
+        ScanOutcome::Admitted(CheckIn {
+            ticket_id: 1,
+            holder_id: 10,
+            door: Some("north".to_string()),
+            checked_in_at: scanned_at,
+        }),
+    )
+    .unwrap();
+    gate.reconciled(
+        2,
+        ScanOutcome::Conflict {
+            ticket_id: 2,
+            door: Some("south".to_string()),
+            checked_in_at: scanned_at,
+        },
+    )
+    .unwrap();
+    drop(gate);
+
+    let store = Store::open(dir.path()).unwrap();
+    assert!(store.pending().is_empty());
+    assert_eq!(store.admitted(), 2);
+    assert!(matches!(
+        store.issues(),
+        [ScanOutcome::Conflict { ticket_id: 2, .. }]
+    ));
+}
+
+#[test]
+fn gate_turns_away_tickets_refunded_since_it_last_synced() {
+    let dir = store_dir();
+    let nonce = Uuid::new_v4();
+    let mut gate = Gate::new(
+        "north".to_string(),
+        manifest(vec![sold(1, 10, nonce)]),
+        Store::open(dir.path()).unwrap(),
+    )
+    .unwrap();
+
+    // The refund leaves the credential's signature intact; only the
+    // next manifest says the ticket is gone.
+    gate.update_manifest(manifest(vec![ManifestTicket {
+        status: TicketStatus::Cancelled,
+        ..sold(1, 10, nonce)
+    }]))
+    .unwrap();
+    assert!(matches!(
+        gate.scan(&credential(1, 10, nonce), Utc::now()).unwrap(),
+        ScanResult::Rejected { .. }
+    ));
+    assert!(gate.pending().is_empty());
+}
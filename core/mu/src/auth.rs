@@ -0,0 +1,82 @@
+use omicron::auth::jwt::decode_token;
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{header, HeaderValue, StatusCode},
+};
+
+/// Subprotocol browsers use to smuggle the token, since they cannot set an
+/// Authorization header on a WebSocket: `Sec-WebSocket-Protocol: bearer, <token>`.
+const BEARER_PROTOCOL: &str = "bearer";
+
+enum TokenSource<'a> {
+    Query(String),
+    Header(&'a str),
+    Protocol(&'a str),
+}
+
+fn find_token(req: &Request) -> Option<TokenSource<'_>> {
+    if let Some(query) = req.uri().query() {
+        if let Some((_, token)) =
+            url::form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "token")
+        {
+            return Some(TokenSource::Query(token.into_owned()));
+        }
+    }
+
+    if let Some(token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(TokenSource::Header(token));
+    }
+
+    let protocols = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())?;
+    let mut parts = protocols.split(',').map(str::trim);
+    match (parts.next(), parts.next()) {
+        (Some(BEARER_PROTOCOL), Some(token)) => Some(TokenSource::Protocol(token)),
+        _ => None,
+    }
+}
+
+fn reject(message: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(message.to_string()));
+    *response.status_mut() = StatusCode::UNAUTHORIZED;
+    response
+}
+
+/// Handshake callback that validates the omicron JWT and records the
+/// caller's user id. Upgrades without a valid token are refused with 401.
+// The error type is dictated by tungstenite's `Callback` trait.
+#[allow(clippy::result_large_err)]
+pub fn authenticate<'a>(
+    user_id: &'a mut Option<i32>,
+) -> impl FnOnce(&Request, Response) -> Result<Response, ErrorResponse> + 'a {
+    move |req, mut response| {
+        let source = find_token(req).ok_or_else(|| reject("missing access token"))?;
+
+        let token = match &source {
+            TokenSource::Query(token) => token.as_str(),
+            TokenSource::Header(token) | TokenSource::Protocol(token) => token,
+        };
+
+        let id = decode_token(token)
+            .ok()
+            .and_then(|claims| claims.user_id())
+            .ok_or_else(|| reject("invalid or expired token"))?;
+
+        if let TokenSource::Protocol(_) = source {
+            response.headers_mut().insert(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static(BEARER_PROTOCOL),
+            );
+        }
+
+        *user_id = Some(id);
+        Ok(response)
+    }
+}
@@ -1,41 +1,229 @@
+pub mod auth;
 pub mod matching;
+pub mod notifications;
+pub mod offers;
+pub mod protocol;
+pub mod subscriptions;
 
 use colored::*;
 use futures_util::{SinkExt, StreamExt};
-use serde::Deserialize;
+use offers::{Connections, Outbox};
+use omicron::error::ApiError;
+use protocol::{ClientMessage, ClientRequest, ServerMessage};
+use std::collections::HashSet;
 use std::net::SocketAddr;
+use subscriptions::Subscriptions;
 use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 use tokio_postgres::Error;
-use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
-
-#[derive(Debug, Deserialize)]
-#[serde(tag = "action", rename_all = "camelCase")]
-pub enum ClientMessage {
-    BuyTicket {
-        #[serde(rename = "eventId")]
-        event_id: i32,
-        qty: i64,
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        protocol::Message,
     },
+};
+use uuid::Uuid;
+
+/// One client's connection.
+struct Session {
+    user_id: i32,
+    outbox: Outbox,
+    subscriptions: Subscriptions,
+    /// Holds taken on this connection, released if the client leaves
+    /// without confirming them. Waitlist offers are not among them; they
+    /// stay with the user until they lapse.
+    holds: HashSet<Uuid>,
+}
+
+impl Session {
+    /// Queues a message; a closed outbox means the client is already gone.
+    fn send(&self, message: &ServerMessage) {
+        let _ = self.outbox.send(message.to_message());
+    }
+}
+
+/// Carries out one request and says how it went.
+async fn handle(
+    session: &mut Session,
+    request_id: Option<String>,
+    message: ClientMessage,
+) -> Result<ServerMessage, ApiError> {
+    let user_id = session.user_id;
+    match message {
+        ClientMessage::BuyTicket {
+            event_id,
+            tier_id,
+            qty,
+            promo_code,
+            idempotency_key,
+        } => {
+            let ticket = matching::BuyTicket {
+                user_id,
+                event_id,
+                tier_id,
+                amount: qty,
+                promo_code,
+            };
+            let purchase = matching::buy_ticket(ticket, idempotency_key.as_deref()).await?;
+            Ok(ServerMessage::purchase(request_id, purchase))
+        }
+        ClientMessage::BuyListing { listing_id } => {
+            let transfer = matching::buy_listing(user_id, listing_id).await?;
+            Ok(ServerMessage::ListingPurchased {
+                request_id,
+                listing_id,
+                ticket_id: transfer.ticket_id,
+            })
+        }
+        ClientMessage::ReserveTickets {
+            event_id,
+            tier_id,
+            qty,
+            promo_code,
+        } => {
+            let ticket = matching::BuyTicket {
+                user_id,
+                event_id,
+                tier_id,
+                amount: qty,
+                promo_code,
+            };
+            let hold = matching::reserve_tickets(ticket).await?;
+            session.holds.insert(hold.hold_id);
+            Ok(ServerMessage::reservation(request_id, hold))
+        }
+        ClientMessage::ConfirmReservation { hold_id } => {
+            session.holds.remove(&hold_id);
+            let purchase = matching::confirm_reservation(user_id, hold_id).await?;
+            Ok(ServerMessage::purchase(request_id, purchase))
+        }
+        ClientMessage::ReleaseReservation { hold_id } => {
+            session.holds.remove(&hold_id);
+            matching::release_reservation(user_id, hold_id).await?;
+            Ok(ServerMessage::ack(
+                request_id,
+                format!("Released hold {}", hold_id),
+            ))
+        }
+        ClientMessage::JoinWaitlist {
+            event_id,
+            tier_id,
+            qty,
+        } => {
+            // Tickets on sale right now come back as an offer of their own.
+            matching::join_waitlist(user_id, event_id, tier_id, qty).await?;
+            Ok(ServerMessage::ack(
+                request_id,
+                format!("Joined the waitlist for event {}", event_id),
+            ))
+        }
+        ClientMessage::LeaveWaitlist { event_id } => {
+            matching::leave_waitlist(user_id, event_id).await?;
+            Ok(ServerMessage::ack(
+                request_id,
+                format!("Left the waitlist for event {}", event_id),
+            ))
+        }
+        ClientMessage::Subscribe { event_id } => {
+            subscriptions::check_access(user_id, event_id).await?;
+            let current = subscriptions::snapshot(event_id).await?;
+            session
+                .subscriptions
+                .subscribe(event_id, session.outbox.clone(), &current);
+            session.send(&ServerMessage::ack(
+                request_id,
+                format!("Subscribed to event {}", event_id),
+            ));
+            Ok(ServerMessage::from(&current))
+        }
+        ClientMessage::Unsubscribe { event_id } => {
+            if !session.subscriptions.unsubscribe(event_id, &session.outbox) {
+                return Err(ApiError::NotFound(format!(
+                    "Not subscribed to event {}",
+                    event_id
+                )));
+            }
+            Ok(ServerMessage::ack(
+                request_id,
+                format!("Unsubscribed from event {}", event_id),
+            ))
+        }
+    }
+}
+
+/// Handshake callback that settles the protocol version, then
+/// authenticates the caller.
+// The error type is dictated by tungstenite's `Callback` trait.
+#[allow(clippy::result_large_err)]
+fn handshake<'a>(
+    user_id: &'a mut Option<i32>,
+    version: &'a mut Option<u32>,
+) -> impl FnOnce(&Request, Response) -> Result<Response, ErrorResponse> + 'a {
+    move |req, response| {
+        *version = Some(protocol::negotiate(req)?);
+        auth::authenticate(user_id)(req, response)
+    }
 }
+
 #[tokio::main]
 pub async fn run() -> Result<(), Error> {
-    println!("{} {} {}", "starting", "MU".red().bold(), "...");
+    println!("starting {} ...", "MU".red().bold());
+    omicron::check_secrets();
     let addr = "127.0.0.1:8080".parse::<SocketAddr>().unwrap();
     let listener = TcpListener::bind(&addr).await.expect("Failed to bind");
 
+    let connections = Connections::default();
+    let subscriptions = Subscriptions::default();
+    tokio::spawn(notifications::listen(
+        connections.clone(),
+        subscriptions.clone(),
+    ));
+
     while let Ok((stream, _)) = listener.accept().await {
+        let connections = connections.clone();
+        let subscriptions = subscriptions.clone();
         tokio::spawn(async move {
-            let ws_stream = accept_async(stream).await.expect("Failed to accept");
-            println!("New WebSocket connection!");
+            let mut user_id = None;
+            let mut version = None;
+            let ws_stream =
+                match accept_hdr_async(stream, handshake(&mut user_id, &mut version)).await {
+                    Ok(ws_stream) => ws_stream,
+                    Err(e) => {
+                        println!("Rejected WebSocket handshake: {}", e);
+                        return;
+                    }
+                };
+            let user_id = user_id.expect("handshake succeeded without a user id");
+            let protocol_version = version.expect("handshake succeeded without a version");
+            println!(
+                "New WebSocket connection for user {} (protocol v{})!",
+                user_id, protocol_version
+            );
 
             let (mut write, mut read) = ws_stream.split();
+            // Replies and pushed messages share one writer.
+            let (outbox, mut queued) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Some(message) = queued.recv().await {
+                    if write.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            let mut session = Session {
+                user_id,
+                outbox,
+                subscriptions,
+                holds: HashSet::new(),
+            };
 
-            write
-                .send(Message::Text(String::from(
-                    "Welcome to the WebSocket server!",
-                )))
-                .await
-                .expect("Failed to send message");
+            session.send(&ServerMessage::Welcome {
+                protocol_version,
+                user_id,
+            });
+            connections.register(user_id, session.outbox.clone());
+            offers::deliver_pending(user_id, &session.outbox).await;
 
             while let Some(message) = read.next().await {
                 match message {
@@ -43,35 +231,26 @@ pub async fn run() -> Result<(), Error> {
                         Message::Text(text) => {
                             println!("Received text message: {}", text);
 
-                            match serde_json::from_str::<ClientMessage>(&text) {
-                                Ok(ClientMessage::BuyTicket { event_id, qty }) => {
-                                    let ticket = matching::BuyTicket {
-                                        event_id,
-                                        amount: qty,
-                                    };
-                                    let result = matching::buy_ticket(ticket).await;
-                                    write
-                                        .send(Message::Text(
-                                            result.unwrap_or("error buying ticket".to_string()),
-                                        ))
+                            let reply = match ClientRequest::parse(&text) {
+                                Ok(request) => {
+                                    let request_id = request.request_id;
+                                    handle(&mut session, request_id.clone(), request.message)
                                         .await
-                                        .expect("Failed to send message");
+                                        .unwrap_or_else(|e| ServerMessage::error(request_id, &e))
                                 }
-                                Err(e) => {
-                                    println!("Failed to parse JSON: {}", e);
-                                    write
-                                        .send(Message::Text("invalid message format".to_string()))
-                                        .await
-                                        .expect("Failed to send message");
+                                Err(invalid) => {
+                                    println!("Failed to parse JSON: {}", invalid.reason);
+                                    ServerMessage::from(invalid)
                                 }
-                            }
+                            };
+                            session.send(&reply);
                         }
                         Message::Binary(_) => {
                             println!("Binary message received – ignoring.");
                         }
                         Message::Ping(ping) => {
                             println!("Ping received – responding with Pong.");
-                            write.send(Message::Pong(ping)).await.ok();
+                            let _ = session.outbox.send(Message::Pong(ping));
                         }
                         Message::Pong(_) => {
                             println!("Pong received.");
@@ -89,6 +268,14 @@ pub async fn run() -> Result<(), Error> {
                     }
                 }
             }
+
+            connections.unregister(user_id, &session.outbox);
+            session.subscriptions.unsubscribe_all(&session.outbox);
+            for hold_id in session.holds {
+                if let Err(e) = matching::release_reservation(user_id, hold_id).await {
+                    println!("Failed to release hold {}: {}", hold_id, e);
+                }
+            }
         });
     }
     Ok(())
@@ -1,53 +1,214 @@
-use omicron::internal::TicketPurchaseRequest;
+use omicron::checkout::Hold;
+use omicron::error::ApiError;
+use omicron::idempotency::{self, PurchaseIntent};
+use omicron::internal::{PurchaseError, TicketPurchaseResponse};
+use omicron::models::event::{self, EventStatus};
+use omicron::models::tier;
+use omicron::pricing::{self, Quote, SystemClock};
+use omicron::resale::Transfer;
+use omicron::waitlist::WaitlistEntry;
+use uuid::Uuid;
+
+const DEFAULT_HOLD_TTL_SECONDS: i64 = 600;
+
+/// How long a reservation keeps tickets off sale, from `HOLD_TTL_SECONDS`.
+pub fn hold_ttl() -> chrono::Duration {
+    let secs = std::env::var("HOLD_TTL_SECONDS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_HOLD_TTL_SECONDS);
+    chrono::Duration::seconds(secs)
+}
 
 pub struct BuyTicket {
+    pub user_id: i32,
     pub event_id: i32,
+    pub tier_id: Option<i32>,
     pub amount: i64,
+    pub promo_code: Option<String>,
 }
 
-pub async fn buy_ticket(buy_ticket: BuyTicket) -> Result<String, String> {
+/// Points buyers who find an event sold out at its waitlist.
+fn purchase_error(e: PurchaseError) -> ApiError {
+    match e {
+        PurchaseError::InsufficientSupply { .. } => ApiError::InsufficientSupply(format!(
+            "{}. Join the waitlist to be offered tickets that come back.",
+            e
+        )),
+        e => e.into(),
+    }
+}
+
+/// Buys outright: holds the tickets and immediately pays for the hold.
+/// A retry carrying the same `idempotency_key` returns the first purchase.
+pub async fn buy_ticket(
+    buy_ticket: BuyTicket,
+    idempotency_key: Option<&str>,
+) -> Result<TicketPurchaseResponse, ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+    let intent = PurchaseIntent {
+        event_id: buy_ticket.event_id,
+        tier_id: buy_ticket.tier_id,
+        quantity: buy_ticket.amount,
+        promo_code: buy_ticket.promo_code.clone(),
+    };
+
+    idempotency::purchase_once(
+        pg_pool,
+        buy_ticket.user_id,
+        idempotency_key,
+        &intent,
+        async {
+            let quote = preflight(&buy_ticket).await.map_err(purchase_error)?;
+            let hold = reserve(&buy_ticket, &quote).await?;
+            confirm_reservation(buy_ticket.user_id, hold.hold_id).await
+        },
+    )
+    .await
+}
+
+/// Preflights and then holds tickets for the connection's user at the
+/// price quoted during preflight.
+pub async fn reserve_tickets(buy_ticket: BuyTicket) -> Result<Hold, ApiError> {
+    let quote = preflight(&buy_ticket).await.map_err(purchase_error)?;
+    reserve(&buy_ticket, &quote).await
+}
+
+/// Checks the purchase can go ahead and quotes its price.
+async fn preflight(buy_ticket: &BuyTicket) -> Result<Quote, PurchaseError> {
     println!(
         "Preflighting ticket checkout for event: {}",
         buy_ticket.event_id
     );
 
-    let event = match omicron::event(buy_ticket.event_id).await {
-        Ok(e) => e,
-        Err(e) => return Err(format!("Failed to fetch event: {}", e)),
-    };
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    let event = event::store::fetch(pg_pool, buy_ticket.event_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => PurchaseError::EventNotFound(buy_ticket.event_id),
+            e => e.into(),
+        })?;
 
     // Preflight check
+    if event.status != EventStatus::Published {
+        return Err(PurchaseError::EventNotOnSale(event.id));
+    }
     if event.available.unwrap_or(0) < buy_ticket.amount {
-        return Err(format!(
-            "Insufficient tickets supply. Available: {}, Requested: {}",
-            event.available.unwrap_or(0),
-            buy_ticket.amount
-        ));
+        return Err(PurchaseError::InsufficientSupply {
+            available: event.available.unwrap_or(0),
+            requested: buy_ticket.amount,
+        });
     }
 
-    let request = TicketPurchaseRequest {
-        user_id: 1,
-        event_id: buy_ticket.event_id,
-        quantity: buy_ticket.amount,
-    };
-
-    match omicron::internal::purchase_ticket(axum::Json(request)).await {
-        Ok(_) => {
-            println!(
-                "Successfully purchased {} ticket(s) for event {}",
-                buy_ticket.amount, buy_ticket.event_id
-            );
-            Ok(format!(
-                "Successfully purchased {} ticket(s) for event {}",
-                buy_ticket.amount, buy_ticket.event_id
-            ))
-        }
-        Err((status, json)) => {
-            let error_message = format!(
-                "Failed to purchase ticket - Status: {:?}, Error: {:?}",
-                status, json
-            );
-            Err(error_message)
+    if let Some(tier_id) = buy_ticket.tier_id {
+        let tier = tier::store::fetch(pg_pool, tier_id)
+            .await
+            .ok()
+            .filter(|tier| tier.event_id == event.id)
+            .ok_or(PurchaseError::TierNotFound(tier_id))?;
+        tier.check_sale_window(chrono::Utc::now())
+            .map_err(PurchaseError::TierNotOnSale)?;
+        tier.check_order_size(buy_ticket.amount)
+            .map_err(PurchaseError::OrderLimit)?;
+        if tier.available < buy_ticket.amount {
+            return Err(PurchaseError::InsufficientSupply {
+                available: tier.available,
+                requested: buy_ticket.amount,
+            });
         }
     }
+
+    let quote = pricing::quote(
+        pg_pool,
+        &SystemClock,
+        buy_ticket.event_id,
+        buy_ticket.tier_id,
+    )
+    .await?;
+    println!(
+        "Quoted {} per ticket for event {} until {}",
+        quote.price.unit_price, buy_ticket.event_id, quote.expires_at
+    );
+    Ok(quote)
+}
+
+async fn reserve(buy_ticket: &BuyTicket, quote: &Quote) -> Result<Hold, ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    let hold = omicron::checkout::reserve_quoted(
+        pg_pool,
+        buy_ticket.user_id,
+        quote,
+        buy_ticket.amount,
+        buy_ticket.promo_code.as_deref(),
+        hold_ttl(),
+    )
+    .await
+    .map_err(purchase_error)?;
+
+    println!(
+        "Reserved {} ticket(s) for event {} at {} until {}",
+        buy_ticket.amount, buy_ticket.event_id, hold.unit_price, hold.expires_at
+    );
+    Ok(hold)
+}
+
+/// Charges the user for a hold; its tickets are sold once payment captures.
+pub async fn confirm_reservation(
+    user_id: i32,
+    hold_id: Uuid,
+) -> Result<TicketPurchaseResponse, ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+    let provider = omicron::payments::provider();
+
+    let purchase = omicron::checkout::pay(pg_pool, provider, user_id, hold_id).await?;
+    println!("Confirmed hold {} for user {}", hold_id, user_id);
+    Ok(purchase)
+}
+
+pub async fn release_reservation(user_id: i32, hold_id: Uuid) -> Result<(), ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    omicron::checkout::release(pg_pool, user_id, hold_id).await?;
+    Ok(())
+}
+
+/// Queues the user for tickets of a sold-out event. Offers arrive on this
+/// connection, or the next one if the user is offline.
+pub async fn join_waitlist(
+    user_id: i32,
+    event_id: i32,
+    tier_id: Option<i32>,
+    amount: i64,
+) -> Result<WaitlistEntry, ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    Ok(omicron::waitlist::join(pg_pool, user_id, event_id, tier_id, amount).await?)
+}
+
+pub async fn leave_waitlist(user_id: i32, event_id: i32) -> Result<(), ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    omicron::waitlist::leave(pg_pool, user_id, event_id).await?;
+    Ok(())
+}
+
+pub async fn buy_listing(user_id: i32, listing_id: i32) -> Result<Transfer, ApiError> {
+    println!("Purchasing resale listing: {}", listing_id);
+
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    let transfer = omicron::resale::purchase_listing(
+        pg_pool,
+        omicron::payments::provider(),
+        user_id,
+        listing_id,
+    )
+    .await?;
+    println!(
+        "Transferred ticket {} to user {}",
+        transfer.ticket_id, transfer.to_user_id
+    );
+    Ok(transfer)
 }
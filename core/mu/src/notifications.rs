@@ -0,0 +1,46 @@
+use crate::offers::{self, Connections};
+use crate::subscriptions::{self, Subscriptions};
+use omicron::inventory::INVENTORY_CHANNEL;
+use omicron::waitlist::OFFERS_CHANNEL;
+use sqlx::postgres::PgListener;
+
+const RECONNECT_DELAY_SECONDS: u64 = 5;
+
+/// Relays what omicron announces over Postgres to the connections it
+/// concerns, for the life of the process. Reconnects if the database goes
+/// away; announcements made meanwhile are lost, but offers stay queued and
+/// subscribers catch up with the next change.
+pub async fn listen(connections: Connections, subscriptions: Subscriptions) {
+    dotenv::dotenv().ok();
+    let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+
+    loop {
+        if let Err(e) = forward(&db_url, &connections, &subscriptions).await {
+            println!("Notification listener failed: {}", e);
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(RECONNECT_DELAY_SECONDS)).await;
+    }
+}
+
+async fn forward(
+    db_url: &str,
+    connections: &Connections,
+    subscriptions: &Subscriptions,
+) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect(db_url).await?;
+    listener
+        .listen_all([OFFERS_CHANNEL, INVENTORY_CHANNEL])
+        .await?;
+
+    loop {
+        let notification = listener.recv().await?;
+        match notification.channel() {
+            OFFERS_CHANNEL => offers::push(connections, notification.payload()),
+            INVENTORY_CHANNEL => match notification.payload().parse() {
+                Ok(event_id) => subscriptions::refresh(subscriptions, event_id).await,
+                Err(e) => println!("Ignoring malformed inventory notice: {}", e),
+            },
+            channel => println!("Ignoring notification on {}", channel),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::protocol::ServerMessage;
+use omicron::waitlist::Offer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// Messages queued for one WebSocket connection's writer.
+pub type Outbox = UnboundedSender<Message>;
+
+/// Open connections by user, so offers can be pushed to whichever of a
+/// user's clients are online.
+#[derive(Clone, Default)]
+pub struct Connections(Arc<Mutex<HashMap<i32, Vec<Outbox>>>>);
+
+impl Connections {
+    pub fn register(&self, user_id: i32, outbox: Outbox) {
+        let mut connections = self.0.lock().expect("connections lock poisoned");
+        connections.entry(user_id).or_default().push(outbox);
+    }
+
+    pub fn unregister(&self, user_id: i32, outbox: &Outbox) {
+        let mut connections = self.0.lock().expect("connections lock poisoned");
+        if let Some(outboxes) = connections.get_mut(&user_id) {
+            outboxes.retain(|other| !other.same_channel(outbox));
+            if outboxes.is_empty() {
+                connections.remove(&user_id);
+            }
+        }
+    }
+
+    /// Sends `message` to every connection of `user_id`. Returns whether
+    /// any of them took it.
+    pub fn send(&self, user_id: i32, message: &ServerMessage) -> bool {
+        let connections = self.0.lock().expect("connections lock poisoned");
+        connections.get(&user_id).is_some_and(|outboxes| {
+            outboxes
+                .iter()
+                .filter(|outbox| outbox.send(message.to_message()).is_ok())
+                .count()
+                > 0
+        })
+    }
+}
+
+/// Sends a newly connected user the offers they were made while offline.
+pub async fn deliver_pending(user_id: i32, outbox: &Outbox) {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    match omicron::waitlist::pending_offers(pg_pool, user_id).await {
+        Ok(offers) => {
+            for offer in offers {
+                let _ = outbox.send(ServerMessage::from(offer).to_message());
+            }
+        }
+        Err(e) => println!("Failed to load waitlist offers for user {}: {}", user_id, e),
+    }
+}
+
+/// Pushes an offer announced on `OFFERS_CHANNEL` to its user. Offers for
+/// users who are not connected stay queued in the database until they are.
+pub fn push(connections: &Connections, payload: &str) {
+    match serde_json::from_str::<Offer>(payload) {
+        Ok(offer) => {
+            let (user_id, hold_id) = (offer.user_id, offer.hold_id);
+            if !connections.send(user_id, &ServerMessage::from(offer)) {
+                println!("User {} is offline; queued offer {}", user_id, hold_id);
+            }
+        }
+        Err(e) => println!("Ignoring malformed waitlist offer: {}", e),
+    }
+}
@@ -0,0 +1,346 @@
+use chrono::{DateTime, Utc};
+use omicron::checkout::Hold;
+use omicron::error::ApiError;
+use omicron::internal::TicketPurchaseResponse;
+use omicron::inventory::{Availability, TierAvailability};
+use omicron::models::event::EventStatus;
+use omicron::money::Money;
+use omicron::waitlist::Offer;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request},
+    http::StatusCode,
+    protocol::Message,
+};
+use uuid::Uuid;
+
+/// Newest protocol version this server speaks.
+pub const PROTOCOL_VERSION: u32 = 1;
+const SUPPORTED_VERSIONS: &[u32] = &[1];
+
+/// Query parameter clients list the protocol versions they speak in, e.g.
+/// `?protocol=1,2`. Clients that leave it out get `PROTOCOL_VERSION`.
+const VERSION_PARAM: &str = "protocol";
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum ClientMessage {
+    BuyTicket {
+        #[serde(rename = "eventId")]
+        event_id: i32,
+        #[serde(rename = "tierId", default)]
+        tier_id: Option<i32>,
+        qty: i64,
+        #[serde(rename = "promoCode", default)]
+        promo_code: Option<String>,
+        /// Resending a purchase with the same key, e.g. after reconnecting,
+        /// returns the first purchase instead of buying again.
+        #[serde(rename = "idempotencyKey", default)]
+        idempotency_key: Option<String>,
+    },
+    BuyListing {
+        #[serde(rename = "listingId")]
+        listing_id: i32,
+    },
+    ReserveTickets {
+        #[serde(rename = "eventId")]
+        event_id: i32,
+        #[serde(rename = "tierId", default)]
+        tier_id: Option<i32>,
+        qty: i64,
+        #[serde(rename = "promoCode", default)]
+        promo_code: Option<String>,
+    },
+    ConfirmReservation {
+        #[serde(rename = "holdId")]
+        hold_id: Uuid,
+    },
+    ReleaseReservation {
+        #[serde(rename = "holdId")]
+        hold_id: Uuid,
+    },
+    JoinWaitlist {
+        #[serde(rename = "eventId")]
+        event_id: i32,
+        #[serde(rename = "tierId", default)]
+        tier_id: Option<i32>,
+        qty: i64,
+    },
+    LeaveWaitlist {
+        #[serde(rename = "eventId")]
+        event_id: i32,
+    },
+    Subscribe {
+        #[serde(rename = "eventId")]
+        event_id: i32,
+    },
+    Unsubscribe {
+        #[serde(rename = "eventId")]
+        event_id: i32,
+    },
+}
+
+/// A client message with the id the client wants echoed on its reply.
+#[derive(Debug, Deserialize)]
+pub struct ClientRequest {
+    #[serde(rename = "requestId", default)]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+/// A text frame that is not a valid request.
+#[derive(Debug)]
+pub struct InvalidRequest {
+    /// Recovered where possible, so the error can still be correlated.
+    pub request_id: Option<String>,
+    pub reason: String,
+}
+
+impl ClientRequest {
+    pub fn parse(text: &str) -> Result<ClientRequest, InvalidRequest> {
+        serde_json::from_str(text).map_err(|e| InvalidRequest {
+            request_id: serde_json::from_str::<serde_json::Value>(text)
+                .ok()
+                .and_then(|value| value.get("requestId")?.as_str().map(str::to_string)),
+            reason: e.to_string(),
+        })
+    }
+}
+
+/// Everything the server sends. Replies carry the `requestId` of the
+/// request they answer; pushed messages have none.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ServerMessage {
+    Welcome {
+        #[serde(rename = "protocolVersion")]
+        protocol_version: u32,
+        #[serde(rename = "userId")]
+        user_id: i32,
+    },
+    /// The request succeeded and has nothing more to report.
+    Ack {
+        #[serde(rename = "requestId")]
+        request_id: Option<String>,
+        message: String,
+    },
+    PurchaseResult {
+        #[serde(rename = "requestId")]
+        request_id: Option<String>,
+        #[serde(rename = "orderId")]
+        order_id: i32,
+        #[serde(rename = "ticketIds")]
+        ticket_ids: Vec<i32>,
+        #[serde(rename = "eventName")]
+        event_name: String,
+        total: Money,
+    },
+    Reservation {
+        #[serde(rename = "requestId")]
+        request_id: Option<String>,
+        #[serde(rename = "holdId")]
+        hold_id: Uuid,
+        #[serde(rename = "eventId")]
+        event_id: i32,
+        #[serde(rename = "eventName")]
+        event_name: String,
+        #[serde(rename = "tierId")]
+        tier_id: Option<i32>,
+        #[serde(rename = "ticketIds")]
+        ticket_ids: Vec<i32>,
+        #[serde(rename = "unitPrice")]
+        unit_price: Money,
+        total: Money,
+        discount: Option<Money>,
+        #[serde(rename = "expiresAt")]
+        expires_at: DateTime<Utc>,
+    },
+    ListingPurchased {
+        #[serde(rename = "requestId")]
+        request_id: Option<String>,
+        #[serde(rename = "listingId")]
+        listing_id: i32,
+        #[serde(rename = "ticketId")]
+        ticket_id: i32,
+    },
+    /// Tickets held for the user off an event's waitlist.
+    WaitlistOffer {
+        #[serde(rename = "holdId")]
+        hold_id: Uuid,
+        #[serde(rename = "eventId")]
+        event_id: i32,
+        #[serde(rename = "eventName")]
+        event_name: String,
+        #[serde(rename = "tierId")]
+        tier_id: Option<i32>,
+        #[serde(rename = "ticketIds")]
+        ticket_ids: Vec<i32>,
+        #[serde(rename = "unitPrice")]
+        unit_price: Money,
+        total: Money,
+        #[serde(rename = "expiresAt")]
+        expires_at: DateTime<Utc>,
+    },
+    /// Where a subscribed event stands after a change.
+    Availability {
+        #[serde(rename = "eventId")]
+        event_id: i32,
+        status: EventStatus,
+        available: i64,
+        #[serde(rename = "soldOut")]
+        sold_out: bool,
+        #[serde(rename = "unitPrice")]
+        unit_price: Option<Money>,
+        tiers: Vec<TierState>,
+    },
+    /// `code` is one of omicron's stable API error codes, or
+    /// `invalid_message` for frames that could not be parsed.
+    Error {
+        code: &'static str,
+        message: String,
+        #[serde(rename = "requestId")]
+        request_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct TierState {
+    #[serde(rename = "tierId")]
+    pub tier_id: i32,
+    pub name: String,
+    pub available: i64,
+    #[serde(rename = "soldOut")]
+    pub sold_out: bool,
+    #[serde(rename = "unitPrice")]
+    pub unit_price: Money,
+}
+
+impl ServerMessage {
+    pub fn ack(request_id: Option<String>, message: String) -> ServerMessage {
+        ServerMessage::Ack {
+            request_id,
+            message,
+        }
+    }
+
+    /// Internal details are logged here and never sent.
+    pub fn error(request_id: Option<String>, e: &ApiError) -> ServerMessage {
+        if let ApiError::Internal(details) = e {
+            println!("Internal error: {}", details);
+        }
+        ServerMessage::Error {
+            code: e.code(),
+            message: e.message().to_string(),
+            request_id,
+        }
+    }
+
+    pub fn purchase(request_id: Option<String>, purchase: TicketPurchaseResponse) -> ServerMessage {
+        ServerMessage::PurchaseResult {
+            request_id,
+            order_id: purchase.order_id,
+            ticket_ids: purchase.ticket_ids,
+            event_name: purchase.event_name,
+            total: purchase.total,
+        }
+    }
+
+    pub fn reservation(request_id: Option<String>, hold: Hold) -> ServerMessage {
+        ServerMessage::Reservation {
+            request_id,
+            hold_id: hold.hold_id,
+            event_id: hold.event_id,
+            event_name: hold.event_name,
+            tier_id: hold.tier_id,
+            ticket_ids: hold.ticket_ids,
+            unit_price: hold.unit_price,
+            total: hold.total,
+            discount: hold.discount,
+            expires_at: hold.expires_at,
+        }
+    }
+
+    pub fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).expect("server messages serialize to JSON"))
+    }
+}
+
+impl From<InvalidRequest> for ServerMessage {
+    fn from(invalid: InvalidRequest) -> Self {
+        ServerMessage::Error {
+            code: "invalid_message",
+            message: invalid.reason,
+            request_id: invalid.request_id,
+        }
+    }
+}
+
+impl From<Offer> for ServerMessage {
+    fn from(offer: Offer) -> Self {
+        ServerMessage::WaitlistOffer {
+            hold_id: offer.hold_id,
+            event_id: offer.event_id,
+            event_name: offer.event_name,
+            tier_id: offer.tier_id,
+            ticket_ids: offer.ticket_ids,
+            unit_price: offer.unit_price,
+            total: offer.total,
+            expires_at: offer.expires_at,
+        }
+    }
+}
+
+impl From<&Availability> for ServerMessage {
+    fn from(availability: &Availability) -> Self {
+        ServerMessage::Availability {
+            event_id: availability.event_id,
+            status: availability.status,
+            available: availability.available,
+            sold_out: availability.sold_out,
+            unit_price: availability.unit_price,
+            tiers: availability.tiers.iter().map(TierState::from).collect(),
+        }
+    }
+}
+
+impl From<&TierAvailability> for TierState {
+    fn from(tier: &TierAvailability) -> Self {
+        TierState {
+            tier_id: tier.tier_id,
+            name: tier.name.clone(),
+            available: tier.available,
+            sold_out: tier.sold_out,
+            unit_price: tier.unit_price,
+        }
+    }
+}
+
+/// Picks the newest version both sides speak from the versions the client
+/// listed in the `protocol` query parameter. Upgrades with none in common
+/// are refused with 400.
+// The error type is dictated by tungstenite's `Callback` trait.
+#[allow(clippy::result_large_err)]
+pub fn negotiate(req: &Request) -> Result<u32, ErrorResponse> {
+    let Some(offered) = req.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == VERSION_PARAM)
+            .map(|(_, versions)| versions.into_owned())
+    }) else {
+        return Ok(PROTOCOL_VERSION);
+    };
+
+    offered
+        .split(',')
+        .filter_map(|version| version.trim().parse::<u32>().ok())
+        .filter(|version| SUPPORTED_VERSIONS.contains(version))
+        .max()
+        .ok_or_else(|| {
+            let mut response = ErrorResponse::new(Some(format!(
+                "unsupported protocol version; this server speaks {:?}",
+                SUPPORTED_VERSIONS
+            )));
+            *response.status_mut() = StatusCode::BAD_REQUEST;
+            response
+        })
+}
@@ -0,0 +1,107 @@
+use crate::offers::Outbox;
+use crate::protocol::ServerMessage;
+use omicron::error::ApiError;
+use omicron::inventory::Availability;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Watchers {
+    outboxes: Vec<Outbox>,
+    /// What subscribers were last told, so repeats can be skipped.
+    last: Option<Availability>,
+}
+
+/// Connections following each event's availability.
+#[derive(Clone, Default)]
+pub struct Subscriptions(Arc<Mutex<HashMap<i32, Watchers>>>);
+
+impl Subscriptions {
+    pub fn subscribe(&self, event_id: i32, outbox: Outbox, current: &Availability) {
+        let mut events = self.0.lock().expect("subscriptions lock poisoned");
+        let watchers = events.entry(event_id).or_default();
+        if !watchers
+            .outboxes
+            .iter()
+            .any(|other| other.same_channel(&outbox))
+        {
+            watchers.outboxes.push(outbox);
+        }
+        watchers.last.get_or_insert_with(|| current.clone());
+    }
+
+    /// Returns whether `outbox` was subscribed to `event_id`.
+    pub fn unsubscribe(&self, event_id: i32, outbox: &Outbox) -> bool {
+        let mut events = self.0.lock().expect("subscriptions lock poisoned");
+        let Some(watchers) = events.get_mut(&event_id) else {
+            return false;
+        };
+        let before = watchers.outboxes.len();
+        watchers
+            .outboxes
+            .retain(|other| !other.same_channel(outbox));
+        let removed = watchers.outboxes.len() < before;
+        if watchers.outboxes.is_empty() {
+            events.remove(&event_id);
+        }
+        removed
+    }
+
+    /// Drops every subscription of a connection that went away.
+    pub fn unsubscribe_all(&self, outbox: &Outbox) {
+        let mut events = self.0.lock().expect("subscriptions lock poisoned");
+        events.retain(|_, watchers| {
+            watchers
+                .outboxes
+                .retain(|other| !other.same_channel(outbox));
+            !watchers.outboxes.is_empty()
+        });
+    }
+
+    fn is_watched(&self, event_id: i32) -> bool {
+        let events = self.0.lock().expect("subscriptions lock poisoned");
+        events.contains_key(&event_id)
+    }
+
+    /// Sends `availability` to the event's subscribers, unless it is what
+    /// they were last told.
+    fn broadcast(&self, availability: Availability) {
+        let mut events = self.0.lock().expect("subscriptions lock poisoned");
+        let Some(watchers) = events.get_mut(&availability.event_id) else {
+            return;
+        };
+        if watchers.last.as_ref() == Some(&availability) {
+            return;
+        }
+
+        let message = ServerMessage::from(&availability).to_message();
+        watchers
+            .outboxes
+            .retain(|outbox| outbox.send(message.clone()).is_ok());
+        watchers.last = Some(availability);
+    }
+}
+
+/// Private events can only be watched by the users invited to them.
+pub async fn check_access(user_id: i32, event_id: i32) -> Result<(), ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    omicron::invitations::check_visible(pg_pool, event_id, Some(user_id)).await
+}
+
+pub async fn snapshot(event_id: i32) -> Result<Availability, ApiError> {
+    let pg_pool = omicron::DB_POOL.get().expect("DB_POOL must be initialized");
+
+    Ok(omicron::inventory::availability(pg_pool, event_id).await?)
+}
+
+/// Tells the subscribers of `event_id`, if it has any, where it stands now.
+pub async fn refresh(subscriptions: &Subscriptions, event_id: i32) {
+    if !subscriptions.is_watched(event_id) {
+        return;
+    }
+    match snapshot(event_id).await {
+        Ok(availability) => subscriptions.broadcast(availability),
+        Err(e) => println!("Failed to load availability of event {}: {}", event_id, e),
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::{Duration, Utc};
+use mu::auth::authenticate;
+use omicron::auth::jwt::{encode_token, Claims, ISSUER};
+use std::sync::Once;
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{header, StatusCode},
+};
+
+static SECRET: Once = Once::new();
+
+/// Sets the JWT secret omicron signs and checks tokens with.
+fn secret() {
+    SECRET.call_once(|| std::env::set_var("JWT_SECRET", "test-jwt-secret"));
+}
+
+fn token(user_id: i32, expires_in: Duration) -> String {
+    secret();
+    encode_token(&Claims {
+        sub: user_id.to_string(),
+        exp: (Utc::now() + expires_in).timestamp() as usize,
+        iss: ISSUER.to_string(),
+        email: "fan@halo.test".to_string(),
+        name: "fan".to_string(),
+    })
+    .unwrap()
+}
+
+/// Runs the handshake callback and returns who it let in.
+#[allow(clippy::result_large_err)]
+fn handshake(request: Request) -> (Result<Response, ErrorResponse>, Option<i32>) {
+    secret();
+    let mut user_id = None;
+    let result = authenticate(&mut user_id)(&request, Response::new(()));
+    (result, user_id)
+}
+
+#[test]
+fn accepts_a_token_in_the_query_string() {
+    let request = Request::builder()
+        .uri(format!(
+            "/?protocol=1&token={}",
+            token(7, Duration::minutes(5))
+        ))
+        .body(())
+        .unwrap();
+
+    let (result, user_id) = handshake(request);
+    let response = result.unwrap();
+    assert_eq!(user_id, Some(7));
+    assert!(response
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .is_none());
+}
+
+#[test]
+fn accepts_a_bearer_authorization_header() {
+    let request = Request::builder()
+        .uri("/")
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token(8, Duration::minutes(5))),
+        )
+        .body(())
+        .unwrap();
+
+    let (result, user_id) = handshake(request);
+    assert!(result.is_ok());
+    assert_eq!(user_id, Some(8));
+}
+
+#[test]
+fn accepts_a_token_in_the_bearer_subprotocol_and_echoes_it() {
+    let request = Request::builder()
+        .uri("/")
+        .header(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            format!("bearer, {}", token(9, Duration::minutes(5))),
+        )
+        .body(())
+        .unwrap();
+
+    let (result, user_id) = handshake(request);
+    let response = result.unwrap();
+    assert_eq!(user_id, Some(9));
+    // Browsers drop the connection unless one of their offered
+    // subprotocols comes back, and the token must not.
+    assert_eq!(
+        response
+            .headers()
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .unwrap(),
+        "bearer"
+    );
+}
+
+#[test]
+fn refuses_missing_invalid_and_expired_tokens() {
+    let requests = [
+        Request::builder().uri("/").body(()).unwrap(),
+        Request::builder()
+            .uri("/?token=not-a-jwt")
+            .body(())
+            .unwrap(),
+        Request::builder()
+            .uri("/")
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token(7, -Duration::hours(1))),
+            )
+            .body(())
+            .unwrap(),
+        Request::builder()
+            .uri("/")
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "chat, something")
+            .body(())
+            .unwrap(),
+    ];
+
+    for request in requests {
+        let (result, user_id) = handshake(request);
+        assert_eq!(result.unwrap_err().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(user_id, None);
+    }
+}
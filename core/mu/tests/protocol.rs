@@ -0,0 +1,64 @@
+use mu::protocol::{self, ClientMessage, ClientRequest, ServerMessage, PROTOCOL_VERSION};
+use omicron::error::ApiError;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::{handshake::server::Request, http::StatusCode};
+
+fn upgrade(uri: &str) -> Request {
+    Request::builder().uri(uri).body(()).unwrap()
+}
+
+#[test]
+fn replies_echo_the_request_id() {
+    let request =
+        ClientRequest::parse(r#"{"action":"buyTicket","requestId":"r-1","eventId":7,"qty":2}"#)
+            .unwrap();
+    assert_eq!(request.request_id.as_deref(), Some("r-1"));
+    assert!(matches!(
+        request.message,
+        ClientMessage::BuyTicket {
+            event_id: 7,
+            qty: 2,
+            ..
+        }
+    ));
+
+    let reply = ServerMessage::error(
+        request.request_id,
+        &ApiError::InsufficientSupply("Only 1 left".to_string()),
+    );
+    assert_eq!(
+        serde_json::to_value(&reply).unwrap(),
+        json!({
+            "type": "error",
+            "code": "insufficient_supply",
+            "message": "Only 1 left",
+            "requestId": "r-1",
+        })
+    );
+}
+
+#[test]
+fn malformed_requests_keep_their_id() {
+    let invalid = ClientRequest::parse(r#"{"action":"buyTicket","requestId":"r-2"}"#).unwrap_err();
+    let reply = serde_json::to_value(ServerMessage::from(invalid)).unwrap();
+    assert_eq!(reply["type"], "error");
+    assert_eq!(reply["code"], "invalid_message");
+    assert_eq!(reply["requestId"], "r-2");
+
+    let invalid = ClientRequest::parse("not json").unwrap_err();
+    assert_eq!(invalid.request_id, None);
+}
+
+#[test]
+fn clients_settle_on_a_shared_version() {
+    assert_eq!(
+        protocol::negotiate(&upgrade("/?token=t")).unwrap(),
+        PROTOCOL_VERSION
+    );
+    assert_eq!(
+        protocol::negotiate(&upgrade("/?token=t&protocol=1,2")).unwrap(),
+        1
+    );
+    let refused = protocol::negotiate(&upgrade("/?protocol=2")).unwrap_err();
+    assert_eq!(refused.status(), StatusCode::BAD_REQUEST);
+}
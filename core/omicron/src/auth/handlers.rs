@@ -1,10 +1,11 @@
+use crate::auth::jwt::{encode_token, AuthUser, Claims, ISSUER};
+use crate::auth::sessions::{self, Rotation};
+use crate::error::ApiError;
 use argon2::{
     password_hash::{PasswordHasher, SaltString},
     Argon2, PasswordHash, PasswordVerifier,
 };
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use chrono::{Duration, Utc};
-use jsonwebtoken::{encode, EncodingKey, Header};
+use axum::{extract::State, response::IntoResponse, Json};
 use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
@@ -15,14 +16,6 @@ pub struct SignupRequest {
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: i32, // user id
-    exp: usize,
-    email: String,
-    name: String,
-}
-
 #[derive(Serialize)]
 pub struct AuthResponse {
     pub message: String,
@@ -31,7 +24,7 @@ pub struct AuthResponse {
 pub async fn signup(
     State(pg_pool): State<PgPool>,
     Json(req): Json<SignupRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, ApiError> {
     let password_hash = hash_password(&req.password)?;
 
     sqlx::query!(
@@ -42,21 +35,24 @@ pub async fn signup(
     )
     .execute(&pg_pool)
     .await
-    .map_err(|e| internal_error(format!("Failed to create user: {}", e)))?;
+    .map_err(|e| match ApiError::from(e) {
+        ApiError::Conflict(_) => ApiError::Conflict("Email is already registered".to_string()),
+        other => other,
+    })?;
 
     Ok(Json(AuthResponse {
         message: "User created successfully".to_string(),
     }))
 }
 
-fn hash_password(password: &str) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+fn hash_password(password: &str) -> Result<String, ApiError> {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
 
     argon2
         .hash_password(password.as_bytes(), &salt)
         .map(|hash| hash.to_string())
-        .map_err(|_| internal_error("Failed to hash password".into()))
+        .map_err(|_| ApiError::Internal("Failed to hash password".to_string()))
 }
 
 #[derive(Deserialize)]
@@ -75,53 +71,61 @@ pub struct UserData {
 #[derive(Serialize)]
 pub struct LoginResponse {
     token: String,
+    refresh_token: String,
     user: UserData,
 }
 
+fn access_token(id: i32, email: String, name: String) -> Result<String, ApiError> {
+    let claims = Claims {
+        sub: id.to_string(),
+        email,
+        name,
+        exp: sessions::access_token_expiry().timestamp() as usize,
+        iss: ISSUER.to_string(),
+    };
+
+    encode_token(&claims)
+        .map_err(|e| ApiError::Internal(format!("Failed to generate token: {}", e)))
+}
+
 pub async fn login(
     State(pg_pool): State<PgPool>,
     Json(req): Json<LoginRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, ApiError> {
     let row = sqlx::query!(
         "SELECT id, name, email, password FROM users WHERE email = $1",
         req.email
     )
     .fetch_optional(&pg_pool)
-    .await
-    .map_err(|e| internal_error(format!("Database error: {}", e)))?;
+    .await?;
 
     let Some(user) = row else {
-        return Err(unauthorized("Invalid email or password"));
+        return Err(ApiError::Unauthorized(
+            "Invalid email or password".to_string(),
+        ));
     };
 
     let parsed_hash = PasswordHash::new(user.password.as_deref().unwrap_or(""))
-        .map_err(|_| internal_error("Stored password hash is invalid".to_string()))?;
+        .map_err(|_| ApiError::Internal("Stored password hash is invalid".to_string()))?;
 
     let is_valid = Argon2::default()
         .verify_password(req.password.as_bytes(), &parsed_hash)
         .is_ok();
 
     if !is_valid {
-        return Err(unauthorized("Invalid email or password"));
+        return Err(ApiError::Unauthorized(
+            "Invalid email or password".to_string(),
+        ));
     }
 
-    let claims = Claims {
-        sub: user.id,
-        email: user.email.clone(),
-        name: user.name.clone(),
-        exp: (Utc::now() + Duration::days(7)).timestamp() as usize,
-    };
-
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret".into());
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .map_err(|e| internal_error(format!("Failed to generate token: {}", e)))?;
+    let token = access_token(user.id, user.email.clone(), user.name.clone())?;
+    let refresh_token = sessions::issue(&pg_pool, user.id)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to create session: {}", e)))?;
 
     Ok(Json(LoginResponse {
         token,
+        refresh_token,
         user: UserData {
             id: user.id,
             name: user.name,
@@ -130,16 +134,76 @@ pub async fn login(
     }))
 }
 
-fn unauthorized(msg: &str) -> (StatusCode, Json<serde_json::Value>) {
-    (
-        StatusCode::UNAUTHORIZED,
-        Json(serde_json::json!({ "error": msg })),
-    )
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
 }
 
-fn internal_error(msg: String) -> (StatusCode, Json<serde_json::Value>) {
-    (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(serde_json::json!({ "error": msg })),
-    )
+#[derive(Serialize)]
+pub struct TokenResponse {
+    token: String,
+    refresh_token: String,
+}
+
+pub async fn refresh(
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<RefreshRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let rotation = sessions::rotate(&pg_pool, &req.refresh_token).await?;
+
+    let (user_id, refresh_token) = match rotation {
+        Rotation::Rotated {
+            user_id,
+            refresh_token,
+        } => (user_id, refresh_token),
+        Rotation::Reused => {
+            return Err(ApiError::Unauthorized(
+                "Refresh token was already used, session has been revoked".to_string(),
+            ))
+        }
+        Rotation::Expired => {
+            return Err(ApiError::Unauthorized(
+                "Refresh token has expired".to_string(),
+            ))
+        }
+        Rotation::Unknown => {
+            return Err(ApiError::Unauthorized("Invalid refresh token".to_string()))
+        }
+    };
+
+    let user = sqlx::query!("SELECT name, email FROM users WHERE id = $1", user_id)
+        .fetch_one(&pg_pool)
+        .await?;
+
+    Ok(Json(TokenResponse {
+        token: access_token(user_id, user.email, user.name)?,
+        refresh_token,
+    }))
+}
+
+pub async fn logout(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<RefreshRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let revoked = sessions::revoke(&pg_pool, user.id, &req.refresh_token).await?;
+
+    if !revoked {
+        return Err(ApiError::Unauthorized("Invalid refresh token".to_string()));
+    }
+
+    Ok(Json(AuthResponse {
+        message: "Logged out".to_string(),
+    }))
+}
+
+pub async fn logout_all(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let revoked = sessions::revoke_all(&pg_pool, user.id).await?;
+
+    Ok(Json(AuthResponse {
+        message: format!("Revoked {} session(s)", revoked),
+    }))
 }
@@ -0,0 +1,92 @@
+use crate::error::ApiError;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts},
+};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+pub const ISSUER: &str = "omicron";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    /// The user id. JWT subjects are strings, so it is sent as one.
+    pub sub: String,
+    pub exp: usize,
+    pub iss: String,
+    pub email: String,
+    pub name: String,
+}
+
+impl Claims {
+    pub fn user_id(&self) -> Option<i32> {
+        self.sub.parse().ok()
+    }
+}
+
+fn secret() -> String {
+    std::env::var("JWT_SECRET").expect("JWT_SECRET is not set")
+}
+
+pub fn encode_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
+    encode(
+        &Header::new(Algorithm::HS256),
+        claims,
+        &EncodingKey::from_secret(secret().as_bytes()),
+    )
+}
+
+/// Checks the signature, expiry, issuer and subject of an HS256 token
+/// minted by `login`.
+pub fn decode_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_issuer(&[ISSUER]);
+    validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret().as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+}
+
+/// The caller identified by a valid `Authorization: Bearer <token>` header.
+/// Adding it to a handler's arguments is enough to protect the route.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: i32,
+    pub email: String,
+    pub name: String,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| ApiError::Unauthorized("Missing authorization header".to_string()))?;
+
+        let token = header.strip_prefix("Bearer ").ok_or_else(|| {
+            ApiError::Unauthorized("Authorization header must be a bearer token".to_string())
+        })?;
+
+        let invalid = || ApiError::Unauthorized("Invalid or expired token".to_string());
+        let claims = decode_token(token).map_err(|_| invalid())?;
+        let id = claims.user_id().ok_or_else(invalid)?;
+
+        Ok(AuthUser {
+            id,
+            email: claims.email,
+            name: claims.name,
+        })
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::{DateTime, Duration, Utc};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// Outcome of presenting a refresh token to `rotate`.
+pub enum Rotation {
+    Rotated {
+        user_id: i32,
+        refresh_token: String,
+    },
+    /// The token was already rotated or revoked. Its whole family has been
+    /// revoked since someone is replaying a stolen token.
+    Reused,
+    Expired,
+    Unknown,
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Only the SHA-256 of a refresh token is stored, so a database leak
+/// does not hand out live sessions.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+async fn insert_token(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+    family_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        family_id,
+        hash_token(&token),
+        expires_at
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(token)
+}
+
+/// Starts a new session (token family) for a freshly logged in user.
+pub async fn issue(pg_pool: &PgPool, user_id: i32) -> Result<String, sqlx::Error> {
+    let mut tx = pg_pool.begin().await?;
+    let token = insert_token(&mut tx, user_id, Uuid::new_v4()).await?;
+    tx.commit().await?;
+    Ok(token)
+}
+
+/// Exchanges a refresh token for a new one in the same family. Each token
+/// can be used exactly once.
+pub async fn rotate(pg_pool: &PgPool, refresh_token: &str) -> Result<Rotation, sqlx::Error> {
+    let mut tx = pg_pool.begin().await?;
+
+    let row = sqlx::query!(
+        r#"
+        SELECT id, user_id, family_id, expires_at, revoked_at
+        FROM refresh_tokens
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+        hash_token(refresh_token)
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(Rotation::Unknown);
+    };
+
+    if row.revoked_at.is_some() {
+        revoke_family_in(&mut tx, row.family_id).await?;
+        tx.commit().await?;
+        return Ok(Rotation::Reused);
+    }
+
+    if row.expires_at <= Utc::now() {
+        return Ok(Rotation::Expired);
+    }
+
+    sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE id = $1",
+        row.id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let refresh_token = insert_token(&mut tx, row.user_id, row.family_id).await?;
+    tx.commit().await?;
+
+    Ok(Rotation::Rotated {
+        user_id: row.user_id,
+        refresh_token,
+    })
+}
+
+async fn revoke_family_in(
+    tx: &mut Transaction<'_, Postgres>,
+    family_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = NOW()
+        WHERE family_id = $1 AND revoked_at IS NULL
+        "#,
+        family_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Ends the session the refresh token belongs to. Returns false if the
+/// token is unknown or belongs to another user.
+pub async fn revoke(
+    pg_pool: &PgPool,
+    user_id: i32,
+    refresh_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pg_pool.begin().await?;
+
+    let family_id = sqlx::query_scalar!(
+        "SELECT family_id FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2",
+        hash_token(refresh_token),
+        user_id
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let Some(family_id) = family_id else {
+        return Ok(false);
+    };
+
+    revoke_family_in(&mut tx, family_id).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Ends every session of the user, e.g. after a password change.
+pub async fn revoke_all(pg_pool: &PgPool, user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = NOW()
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub fn access_token_expiry() -> DateTime<Utc> {
+    Utc::now() + Duration::minutes(ACCESS_TOKEN_TTL_MINUTES)
+}
@@ -0,0 +1,438 @@
+use crate::auth::jwt::AuthUser;
+use crate::credentials::{self, Credential, CredentialError};
+use crate::error::ApiError;
+use crate::events::owned_event;
+use crate::models::ticket::TicketStatus;
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Longest door name a check-in can be recorded with.
+const MAX_DOOR_LENGTH: usize = 64;
+
+/// A ticket's QR payload, as shown to its holder.
+#[derive(Debug, Clone, Serialize)]
+pub struct TicketCredential {
+    pub ticket_id: i32,
+    pub status: TicketStatus,
+    pub credential: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckIn {
+    pub ticket_id: i32,
+    pub holder_id: i32,
+    pub door: Option<String>,
+    pub checked_in_at: DateTime<Utc>,
+}
+
+/// Everything a door needs to admit people while offline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub event_id: i32,
+    pub public_key: String,
+    pub generated_at: DateTime<Utc>,
+    pub tickets: Vec<ManifestTicket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestTicket {
+    pub ticket_id: i32,
+    pub holder_id: i32,
+    pub nonce: Uuid,
+    pub status: TicketStatus,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    pub checked_in_door: Option<String>,
+}
+
+/// A credential a door scanned, possibly while offline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scan {
+    pub credential: String,
+    pub scanned_at: DateTime<Utc>,
+}
+
+/// What became of one offline scan once omicron saw it. `Conflict` means
+/// the ticket had already been admitted, usually at another door.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "outcome")]
+pub enum ScanOutcome {
+    Admitted(CheckIn),
+    Conflict {
+        ticket_id: i32,
+        door: Option<String>,
+        checked_in_at: DateTime<Utc>,
+    },
+    Rejected {
+        ticket_id: Option<i32>,
+        reason: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum CheckInError {
+    Credential(CredentialError),
+    TicketNotFound(i32),
+    Revoked(i32),
+    AlreadyCheckedIn(i32, DateTime<Utc>, Option<String>),
+    NotAdmissible(i32, String),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for CheckInError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckInError::Credential(e) => write!(f, "{}", e),
+            CheckInError::TicketNotFound(id) => write!(f, "Ticket {} not found", id),
+            CheckInError::Revoked(id) => {
+                write!(f, "Credential for ticket {} is no longer valid", id)
+            }
+            CheckInError::AlreadyCheckedIn(id, at, Some(door)) => write!(
+                f,
+                "Ticket {} was already checked in at {} by door {}",
+                id, at, door
+            ),
+            CheckInError::AlreadyCheckedIn(id, at, None) => {
+                write!(f, "Ticket {} was already checked in at {}", id, at)
+            }
+            CheckInError::NotAdmissible(id, reason) => write!(f, "Ticket {}: {}", id, reason),
+            CheckInError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for CheckInError {
+    fn from(e: sqlx::Error) -> Self {
+        CheckInError::Database(e)
+    }
+}
+
+impl From<CredentialError> for CheckInError {
+    fn from(e: CredentialError) -> Self {
+        CheckInError::Credential(e)
+    }
+}
+
+impl From<CheckInError> for ApiError {
+    fn from(e: CheckInError) -> Self {
+        match e {
+            CheckInError::Credential(CredentialError::Malformed)
+            | CheckInError::Credential(CredentialError::UnsupportedVersion(_)) => {
+                ApiError::ValidationFailed(e.to_string())
+            }
+            CheckInError::Credential(_) | CheckInError::Revoked(_) => {
+                ApiError::Forbidden(e.to_string())
+            }
+            CheckInError::TicketNotFound(_) => ApiError::NotFound(e.to_string()),
+            CheckInError::AlreadyCheckedIn(..) | CheckInError::NotAdmissible(..) => {
+                ApiError::Conflict(e.to_string())
+            }
+            CheckInError::Database(e) => ApiError::from(e),
+        }
+    }
+}
+
+/// Signed credentials for every ticket `user_id` holds to `event_id`.
+pub async fn credentials_for(
+    pg_pool: &PgPool,
+    user_id: i32,
+    event_id: i32,
+) -> Result<Vec<TicketCredential>, sqlx::Error> {
+    let tickets = sqlx::query!(
+        r#"
+        SELECT id, status as "status: TicketStatus", credential_nonce
+        FROM tickets
+        WHERE event_id = $1 AND user_id = $2 AND status IN ('Sold', 'CheckedIn')
+        ORDER BY id
+        "#,
+        event_id,
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let key = credentials::signing_key(event_id);
+    Ok(tickets
+        .into_iter()
+        .map(|ticket| TicketCredential {
+            ticket_id: ticket.id,
+            status: ticket.status,
+            credential: Credential {
+                ticket_id: ticket.id,
+                event_id,
+                holder_id: user_id,
+                nonce: ticket.credential_nonce,
+            }
+            .sign(&key),
+        })
+        .collect())
+}
+
+/// The tickets `event_id`'s doors should know about: everything sold,
+/// with who holds it and whether it has been used. Refunded tickets are
+/// sent too, so a door turns their still-signed credentials away instead
+/// of admitting them as sold since the last sync.
+pub async fn manifest(pg_pool: &PgPool, event_id: i32) -> Result<Manifest, sqlx::Error> {
+    let tickets = sqlx::query_as!(
+        ManifestTicket,
+        r#"
+        SELECT
+            id as ticket_id,
+            user_id as "holder_id!",
+            credential_nonce as nonce,
+            status as "status: TicketStatus",
+            checked_in_at,
+            checked_in_door
+        FROM tickets
+        WHERE event_id = $1 AND user_id IS NOT NULL AND status IN ('Sold', 'CheckedIn', 'Cancelled')
+        ORDER BY id
+        "#,
+        event_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let key = credentials::signing_key(event_id).verifying_key();
+    Ok(Manifest {
+        event_id,
+        public_key: credentials::encode_public_key(&key),
+        generated_at: Utc::now(),
+        tickets,
+    })
+}
+
+/// Admits the holder of `token` to `event_id` as of `scanned_at`. The
+/// signature is checked the same way an offline scanner would; the ticket
+/// row then has to still belong to the holder the credential was issued
+/// to, so credentials for resold or refunded tickets stop working.
+///
+/// Presenting the same scan from the same door again returns the original
+/// check-in, so doors can safely retry a sync.
+pub async fn admit(
+    pg_pool: &PgPool,
+    event_id: i32,
+    token: &str,
+    door: Option<&str>,
+    scanned_at: DateTime<Utc>,
+) -> Result<CheckIn, CheckInError> {
+    let credential = credentials::verifier(event_id).verify(token)?;
+    let ticket_id = credential.ticket_id;
+    // Postgres keeps microseconds; match that so retries compare equal.
+    let scanned_at = scanned_at.trunc_subsecs(6);
+
+    let mut tx = pg_pool.begin().await?;
+
+    let ticket = sqlx::query!(
+        r#"
+        SELECT
+            user_id,
+            status as "status: TicketStatus",
+            credential_nonce,
+            checked_in_at,
+            checked_in_door
+        FROM tickets
+        WHERE id = $1 AND event_id = $2
+        FOR UPDATE
+        "#,
+        ticket_id,
+        event_id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(CheckInError::TicketNotFound(ticket_id))?;
+
+    if ticket.user_id != Some(credential.holder_id) || ticket.credential_nonce != credential.nonce {
+        return Err(CheckInError::Revoked(ticket_id));
+    }
+    if let Some(checked_in_at) = ticket.checked_in_at {
+        if door.is_some()
+            && ticket.checked_in_door.as_deref() == door
+            && checked_in_at == scanned_at
+        {
+            return Ok(CheckIn {
+                ticket_id,
+                holder_id: credential.holder_id,
+                door: ticket.checked_in_door,
+                checked_in_at,
+            });
+        }
+        return Err(CheckInError::AlreadyCheckedIn(
+            ticket_id,
+            checked_in_at,
+            ticket.checked_in_door,
+        ));
+    }
+
+    let status = ticket
+        .status
+        .check_in()
+        .map_err(|reason| CheckInError::NotAdmissible(ticket_id, reason))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE tickets
+        SET status = $2, checked_in_at = $3, checked_in_door = $4
+        WHERE id = $1
+        "#,
+        ticket_id,
+        status as TicketStatus,
+        scanned_at,
+        door
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(CheckIn {
+        ticket_id,
+        holder_id: credential.holder_id,
+        door: door.map(str::to_string),
+        checked_in_at: scanned_at,
+    })
+}
+
+/// Replays scans a door recorded offline, in order. A ticket that another
+/// door already admitted comes back as a `Conflict` for staff to look into
+/// rather than failing the whole batch.
+pub async fn reconcile(
+    pg_pool: &PgPool,
+    event_id: i32,
+    door: &str,
+    scans: &[Scan],
+) -> Result<Vec<ScanOutcome>, sqlx::Error> {
+    let mut outcomes = Vec::with_capacity(scans.len());
+
+    for scan in scans {
+        let outcome = match admit(
+            pg_pool,
+            event_id,
+            &scan.credential,
+            Some(door),
+            scan.scanned_at,
+        )
+        .await
+        {
+            Ok(check_in) => ScanOutcome::Admitted(check_in),
+            Err(CheckInError::AlreadyCheckedIn(ticket_id, checked_in_at, door)) => {
+                log::warn!(
+                    "ticket {} for event {} was scanned again after check-in at {:?}",
+                    ticket_id,
+                    event_id,
+                    door
+                );
+                ScanOutcome::Conflict {
+                    ticket_id,
+                    door,
+                    checked_in_at,
+                }
+            }
+            Err(CheckInError::Database(e)) => return Err(e),
+            Err(e) => ScanOutcome::Rejected {
+                ticket_id: Credential::decode(&scan.credential)
+                    .ok()
+                    .map(|(credential, _)| credential.ticket_id),
+                reason: e.to_string(),
+            },
+        };
+        outcomes.push(outcome);
+    }
+
+    Ok(outcomes)
+}
+
+fn validate_door(door: &str) -> Result<(), ApiError> {
+    if door.trim().is_empty() || door.len() > MAX_DOOR_LENGTH {
+        return Err(ApiError::ValidationFailed(format!(
+            "door must be between 1 and {} characters",
+            MAX_DOOR_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+pub async fn credentials(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(credentials_for(&pg_pool, user.id, event_id).await?))
+}
+
+#[derive(Serialize)]
+pub struct PublicKeyResponse {
+    pub event_id: i32,
+    pub algorithm: &'static str,
+    pub public_key: String,
+}
+
+/// The key scanners need to verify this event's credentials offline.
+pub async fn public_key(Path(event_id): Path<i32>) -> impl IntoResponse {
+    let key = credentials::signing_key(event_id).verifying_key();
+    Json(PublicKeyResponse {
+        event_id,
+        algorithm: "Ed25519",
+        public_key: credentials::encode_public_key(&key),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct CheckInRequest {
+    pub credential: String,
+    pub door: Option<String>,
+}
+
+pub async fn check_in(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<CheckInRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+    if let Some(door) = &req.door {
+        validate_door(door)?;
+    }
+
+    let check_in = admit(
+        &pg_pool,
+        event_id,
+        &req.credential,
+        req.door.as_deref(),
+        Utc::now(),
+    )
+    .await?;
+    Ok(Json(check_in))
+}
+
+pub async fn get_manifest(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+    Ok(Json(manifest(&pg_pool, event_id).await?))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SyncRequest {
+    pub door: String,
+    pub scans: Vec<Scan>,
+}
+
+pub async fn sync(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<SyncRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+    validate_door(&req.door)?;
+    Ok(Json(
+        reconcile(&pg_pool, event_id, &req.door, &req.scans).await?,
+    ))
+}
@@ -0,0 +1,652 @@
+use crate::idempotency;
+use crate::internal::{PurchaseError, TicketPurchaseResponse};
+use crate::inventory;
+use crate::invitations;
+use crate::models::event::EventStatus;
+use crate::models::tier;
+use crate::money::{Currency, Money};
+use crate::orders;
+use crate::payments::{self, PaymentProvider, PaymentStatus};
+use crate::pricing::{self, Market, Quote};
+use crate::promos;
+use crate::waitlist;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Tickets set aside for one buyer until `expires_at`. Confirming the hold
+/// sells them; letting it lapse puts them back on sale.
+#[derive(Debug, Serialize)]
+pub struct Hold {
+    pub hold_id: Uuid,
+    pub event_id: i32,
+    pub event_name: String,
+    pub tier_id: Option<i32>,
+    pub ticket_ids: Vec<i32>,
+    pub unit_price: Money,
+    pub total: Money,
+    pub promo_code: Option<String>,
+    /// What the promo code took off the whole hold.
+    pub discount: Option<Money>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Reserves `quantity` tickets of `event_id` in `tier_id` for `user_id` at
+/// `price`, reusing rows that an expired or released hold put back to
+/// `Available` before minting new ones. The caller must already hold the
+/// event row lock.
+#[allow(clippy::too_many_arguments)]
+async fn claim_tickets(
+    tx: &mut Transaction<'_, Postgres>,
+    event_id: i32,
+    tier_id: Option<i32>,
+    user_id: i32,
+    quantity: i64,
+    price: Money,
+    hold_id: Uuid,
+    reserved_until: DateTime<Utc>,
+) -> Result<Vec<i32>, sqlx::Error> {
+    let mut ticket_ids = sqlx::query_scalar!(
+        r#"
+        UPDATE tickets
+        SET user_id = $2, status = 'Reserved', hold_id = $3, reserved_until = $4,
+            price_cents = $6, currency = $7
+        WHERE id IN (
+            SELECT id FROM tickets
+            WHERE event_id = $1 AND tier_id IS NOT DISTINCT FROM $8 AND status = 'Available'
+            ORDER BY id
+            LIMIT $5
+            FOR UPDATE
+        )
+        RETURNING id
+        "#,
+        event_id,
+        user_id,
+        hold_id,
+        reserved_until,
+        quantity,
+        price.cents,
+        price.currency as Currency,
+        tier_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let remaining = quantity - ticket_ids.len() as i64;
+    if remaining > 0 {
+        let minted = sqlx::query_scalar!(
+            r#"
+            INSERT INTO tickets (
+                user_id, event_id, tier_id, status, hold_id, reserved_until, price_cents,
+                currency
+            )
+            SELECT $1, $2, $8, 'Reserved', $3, $4, $6, $7 FROM generate_series(1, $5::BIGINT)
+            RETURNING id
+            "#,
+            user_id,
+            event_id,
+            hold_id,
+            reserved_until,
+            remaining,
+            price.cents,
+            price.currency as Currency,
+            tier_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        ticket_ids.extend(minted);
+    }
+
+    // RETURNING promises no order.
+    ticket_ids.sort_unstable();
+    Ok(ticket_ids)
+}
+
+/// Takes `quantity` tickets off sale for `user_id` for `ttl`. Supply is
+/// deducted from `available` immediately so a hold can never be oversold.
+/// Events that sell tiers need `tier_id`; the tier's price, sale window,
+/// order limits and allocation then apply on top of the event's capacity.
+/// Tickets are priced by the event's pricing rules at the time of the hold,
+/// less `promo_code` if one is given; the code's redemption is recorded
+/// with the tickets and given back if the hold lapses.
+pub async fn reserve(
+    pg_pool: &PgPool,
+    user_id: i32,
+    event_id: i32,
+    tier_id: Option<i32>,
+    quantity: i64,
+    promo_code: Option<&str>,
+    ttl: Duration,
+) -> Result<Hold, PurchaseError> {
+    hold(
+        pg_pool, user_id, event_id, tier_id, quantity, promo_code, ttl, None,
+    )
+    .await
+}
+
+/// Like `reserve`, but at the price `quote` offered, as long as the quote
+/// has not expired. The hold keeps that price until it is paid or lapses.
+pub async fn reserve_quoted(
+    pg_pool: &PgPool,
+    user_id: i32,
+    quote: &Quote,
+    quantity: i64,
+    promo_code: Option<&str>,
+    ttl: Duration,
+) -> Result<Hold, PurchaseError> {
+    if !quote.is_valid_at(Utc::now()) {
+        return Err(PurchaseError::QuoteExpired(quote.expires_at));
+    }
+    hold(
+        pg_pool,
+        user_id,
+        quote.event_id,
+        quote.tier_id,
+        quantity,
+        promo_code,
+        ttl,
+        Some(quote.price.unit_price),
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn hold(
+    pg_pool: &PgPool,
+    user_id: i32,
+    event_id: i32,
+    tier_id: Option<i32>,
+    quantity: i64,
+    promo_code: Option<&str>,
+    ttl: Duration,
+    quoted_price: Option<Money>,
+) -> Result<Hold, PurchaseError> {
+    if quantity < 1 {
+        return Err(PurchaseError::InvalidQuantity(quantity));
+    }
+
+    let mut tx = pg_pool.begin().await?;
+
+    let event = sqlx::query!(
+        r#"
+        SELECT
+            name,
+            capacity,
+            available,
+            start_time,
+            price_cents,
+            currency as "currency: Currency",
+            status as "status: EventStatus"
+        FROM events
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        event_id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(PurchaseError::EventNotFound(event_id))?;
+
+    if event.status != EventStatus::Published {
+        return Err(PurchaseError::EventNotOnSale(event_id));
+    }
+
+    if !invitations::has_access(&mut tx, event_id, user_id).await? {
+        return Err(PurchaseError::NotInvited(event_id));
+    }
+
+    let mut available = event.available.unwrap_or(0);
+    let mut market = Market {
+        base_price: Money::new(event.price_cents, event.currency),
+        quantity: event.capacity,
+        available,
+        starts_at: event.start_time,
+    };
+
+    match tier_id {
+        Some(tier_id) => {
+            let tier = tier::store::lock(&mut tx, event_id, tier_id)
+                .await?
+                .ok_or(PurchaseError::TierNotFound(tier_id))?;
+
+            tier.check_sale_window(Utc::now())
+                .map_err(PurchaseError::TierNotOnSale)?;
+            tier.check_order_size(quantity)
+                .map_err(PurchaseError::OrderLimit)?;
+
+            available = available.min(tier.available);
+            market.base_price = tier.price;
+            market.quantity = tier.quantity;
+            market.available = tier.available;
+        }
+        None => {
+            let tiered = sqlx::query_scalar!(
+                r#"SELECT EXISTS (SELECT 1 FROM ticket_tiers WHERE event_id = $1) as "tiered!""#,
+                event_id
+            )
+            .fetch_one(&mut tx)
+            .await?;
+            if tiered {
+                return Err(PurchaseError::TierRequired(event_id));
+            }
+        }
+    }
+
+    if available < quantity {
+        return Err(PurchaseError::InsufficientSupply {
+            available,
+            requested: quantity,
+        });
+    }
+
+    let unit_price = match quoted_price {
+        Some(price) => price,
+        None => {
+            let rules = pricing::rules(&mut tx, event_id).await?;
+            pricing::price(&rules, tier_id, &market, Utc::now())?.unit_price
+        }
+    };
+
+    let promo = match promo_code {
+        Some(code) => Some(
+            promos::apply(
+                &mut tx,
+                event_id,
+                tier_id,
+                user_id,
+                code,
+                unit_price,
+                quantity,
+                Utc::now(),
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    let unit_price = promo.as_ref().map_or(unit_price, |promo| promo.unit_price);
+    let total = unit_price.checked_mul(quantity)?;
+
+    sqlx::query!(
+        "UPDATE events SET available = available - $2 WHERE id = $1",
+        event_id,
+        quantity
+    )
+    .execute(&mut tx)
+    .await?;
+
+    if let Some(tier_id) = tier_id {
+        sqlx::query!(
+            "UPDATE ticket_tiers SET available = available - $2 WHERE id = $1",
+            tier_id,
+            quantity
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    inventory::announce(&mut tx, event_id).await?;
+
+    let hold_id = Uuid::new_v4();
+    let expires_at = Utc::now() + ttl;
+    let ticket_ids = claim_tickets(
+        &mut tx, event_id, tier_id, user_id, quantity, unit_price, hold_id, expires_at,
+    )
+    .await?;
+
+    if let Some(promo) = &promo {
+        promos::redeem(&mut tx, promo, user_id, hold_id, quantity).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Hold {
+        hold_id,
+        event_id,
+        event_name: event.name,
+        tier_id,
+        ticket_ids,
+        unit_price,
+        total,
+        promo_code: promo.as_ref().map(|promo| promo.promo.code.clone()),
+        discount: promo.map(|promo| promo.discount),
+        expires_at,
+    })
+}
+
+/// Charges the buyer for a hold and sells its tickets once the payment is
+/// captured. A declined capture puts the tickets back on sale; a hold that
+/// lapsed while the card was being charged, or that could not be sold for
+/// any other reason, is refunded. Holds on events that are no longer on
+/// sale cannot be paid for.
+pub async fn pay(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    user_id: i32,
+    hold_id: Uuid,
+) -> Result<TicketPurchaseResponse, PurchaseError> {
+    let tickets = sqlx::query!(
+        r#"
+        SELECT t.event_id, t.price_cents, t.currency as "currency: Currency"
+        FROM tickets t
+        JOIN events e ON e.id = t.event_id
+        WHERE t.hold_id = $1 AND t.user_id = $2 AND t.status = 'Reserved'
+          AND t.reserved_until > NOW() AND e.status = 'Published'
+        "#,
+        hold_id,
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let Some(first) = tickets.first() else {
+        return Err(PurchaseError::HoldUnavailable(hold_id));
+    };
+    let event_id = first.event_id;
+    let total = Money::checked_sum(
+        first.currency,
+        tickets
+            .iter()
+            .map(|ticket| Money::new(ticket.price_cents, ticket.currency)),
+    )?;
+
+    if total.is_zero() {
+        return confirm(pg_pool, user_id, hold_id, total, None).await;
+    }
+
+    let intent = provider
+        .create_intent(total, &hold_id.to_string())
+        .await
+        .map_err(|e| PurchaseError::PaymentFailed(e.to_string()))?;
+
+    let payment_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO payments (user_id, event_id, hold_id, provider, intent_id, amount_cents, currency)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+        user_id,
+        event_id,
+        hold_id,
+        provider.name(),
+        intent.id,
+        total.cents,
+        total.currency as Currency
+    )
+    .fetch_one(pg_pool)
+    .await?;
+
+    if let Err(e) = provider.capture(&intent.id).await {
+        payments::set_status(
+            pg_pool,
+            payment_id,
+            PaymentStatus::Failed,
+            Some(e.to_string()),
+        )
+        .await?;
+        match release(pg_pool, user_id, hold_id).await {
+            Ok(()) | Err(PurchaseError::HoldUnavailable(_)) => {}
+            Err(e) => return Err(e),
+        }
+        return Err(PurchaseError::PaymentFailed(e.to_string()));
+    }
+
+    match confirm(pg_pool, user_id, hold_id, total, Some(payment_id)).await {
+        Ok(response) => Ok(response),
+        Err(e) => {
+            if let Err(refund_error) = provider.refund(&intent.id, total).await {
+                // Still captured; keep the reason so it can be reconciled.
+                payments::set_status(
+                    pg_pool,
+                    payment_id,
+                    PaymentStatus::Succeeded,
+                    Some(format!("Refund failed after {}: {}", e, refund_error)),
+                )
+                .await?;
+                return Err(PurchaseError::PaymentFailed(refund_error.to_string()));
+            }
+            payments::set_status(
+                pg_pool,
+                payment_id,
+                PaymentStatus::Refunded,
+                Some(e.to_string()),
+            )
+            .await?;
+            Err(e)
+        }
+    }
+}
+
+/// Sells the tickets in a hold that has not yet expired, marking the
+/// payment that covered them as succeeded.
+async fn confirm(
+    pg_pool: &PgPool,
+    user_id: i32,
+    hold_id: Uuid,
+    total: Money,
+    payment_id: Option<i32>,
+) -> Result<TicketPurchaseResponse, PurchaseError> {
+    let mut tx = pg_pool.begin().await?;
+
+    let mut ticket_ids = sqlx::query_scalar!(
+        r#"
+        UPDATE tickets
+        SET status = 'Sold', hold_id = NULL, reserved_until = NULL, payment_id = $3
+        WHERE hold_id = $1 AND user_id = $2 AND status = 'Reserved' AND reserved_until > NOW()
+        RETURNING id
+        "#,
+        hold_id,
+        user_id,
+        payment_id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    if ticket_ids.is_empty() {
+        return Err(PurchaseError::HoldUnavailable(hold_id));
+    }
+    ticket_ids.sort_unstable();
+
+    if let Some(payment_id) = payment_id {
+        sqlx::query!(
+            "UPDATE payments SET status = 'Succeeded', updated_at = NOW() WHERE id = $1",
+            payment_id
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    waitlist::fulfil(&mut tx, hold_id).await?;
+    let order = orders::record(&mut tx, user_id, hold_id, payment_id, &ticket_ids).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO ticket_transfers (ticket_id, to_user_id)
+        SELECT UNNEST($1::INT[]), $2
+        "#,
+        &ticket_ids,
+        user_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(TicketPurchaseResponse {
+        order_id: order.id,
+        ticket_ids,
+        event_name: order.event_name,
+        total,
+    })
+}
+
+/// Gives up a hold early, e.g. when the buyer abandons checkout. Its
+/// tickets are offered to the event's waitlist first.
+pub async fn release(pg_pool: &PgPool, user_id: i32, hold_id: Uuid) -> Result<(), PurchaseError> {
+    let event_id = return_hold(pg_pool, user_id, hold_id).await?;
+    waitlist::offer_returned(pg_pool, &[event_id]).await;
+    Ok(())
+}
+
+/// Puts a hold's tickets back on sale without offering them to anyone.
+/// Returns the event they belong to.
+pub(crate) async fn return_hold(
+    pg_pool: &PgPool,
+    user_id: i32,
+    hold_id: Uuid,
+) -> Result<i32, PurchaseError> {
+    let mut tx = pg_pool.begin().await?;
+
+    let event_id = sqlx::query_scalar!(
+        r#"
+        SELECT event_id FROM tickets
+        WHERE hold_id = $1 AND user_id = $2 AND status = 'Reserved'
+        LIMIT 1
+        "#,
+        hold_id,
+        user_id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(PurchaseError::HoldUnavailable(hold_id))?;
+
+    lock_events(&mut tx, &[event_id]).await?;
+    restock(&mut tx, &[hold_id]).await?;
+    inventory::announce(&mut tx, event_id).await?;
+
+    tx.commit().await?;
+    Ok(event_id)
+}
+
+/// Returns every lapsed hold to stock and offers it to the waitlists of
+/// the events it came from. Returns the number of tickets put back on
+/// sale.
+pub async fn release_expired(pg_pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let mut tx = pg_pool.begin().await?;
+
+    let expired = sqlx::query!(
+        r#"
+        SELECT DISTINCT hold_id as "hold_id!", event_id
+        FROM tickets
+        WHERE status = 'Reserved' AND reserved_until <= NOW()
+        "#
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    if expired.is_empty() {
+        return Ok(0);
+    }
+
+    let mut event_ids: Vec<i32> = expired.iter().map(|row| row.event_id).collect();
+    let hold_ids: Vec<Uuid> = expired.iter().map(|row| row.hold_id).collect();
+
+    lock_events(&mut tx, &event_ids).await?;
+    let restored = restock(&mut tx, &hold_ids).await?;
+    event_ids.sort_unstable();
+    event_ids.dedup();
+    for &event_id in &event_ids {
+        inventory::announce(&mut tx, event_id).await?;
+    }
+
+    tx.commit().await?;
+    waitlist::offer_returned(pg_pool, &event_ids).await;
+    Ok(restored)
+}
+
+/// Runs `release_expired`, and forgets idempotency keys past their window,
+/// every `interval` for the life of the process.
+pub fn spawn_sweeper(pg_pool: PgPool, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match release_expired(&pg_pool).await {
+                Ok(0) => {}
+                Ok(restored) => log::info!("released {} expired ticket hold(s)", restored),
+                Err(e) => log::error!("failed to release expired holds: {}", e),
+            }
+            if let Err(e) = idempotency::purge_expired(&pg_pool).await {
+                log::error!("failed to purge expired idempotency keys: {}", e);
+            }
+        }
+    });
+}
+
+/// Locks events in id order, the same order purchases take them, so the
+/// sweeper cannot deadlock against a buyer.
+async fn lock_events(
+    tx: &mut Transaction<'_, Postgres>,
+    event_ids: &[i32],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "SELECT id FROM events WHERE id = ANY($1) ORDER BY id FOR UPDATE",
+        event_ids
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Flips still-reserved tickets in `hold_ids` back to `Available`, adds
+/// them back to their events' and tiers' `available` counts, gives back
+/// the promo code uses of the holds they came from and expires the
+/// waitlist offers they were. Each ticket's status is checked again once
+/// its row is locked, so a ticket `confirm` sold meanwhile stays sold.
+async fn restock(
+    tx: &mut Transaction<'_, Postgres>,
+    hold_ids: &[Uuid],
+) -> Result<i64, sqlx::Error> {
+    let restored = sqlx::query_scalar!(
+        r#"
+        WITH released AS (
+            UPDATE tickets t
+            SET status = 'Available', user_id = NULL, hold_id = NULL, reserved_until = NULL
+            FROM (
+                SELECT id, hold_id FROM tickets WHERE hold_id = ANY($1) AND status = 'Reserved'
+            ) held
+            WHERE t.id = held.id AND t.status = 'Reserved'
+            RETURNING t.event_id, t.tier_id, held.hold_id
+        ), dropped AS (
+            DELETE FROM promo_redemptions r
+            USING (SELECT DISTINCT hold_id FROM released) h
+            WHERE r.hold_id = h.hold_id
+            RETURNING r.promo_code_id
+        ), given_back AS (
+            UPDATE promo_codes p
+            SET uses = p.uses - d.redemptions
+            FROM (
+                SELECT promo_code_id, COUNT(*) as redemptions FROM dropped GROUP BY promo_code_id
+            ) d
+            WHERE p.id = d.promo_code_id
+        ), lapsed AS (
+            UPDATE waitlist_entries w
+            SET status = 'Expired', updated_at = NOW()
+            FROM (SELECT DISTINCT hold_id FROM released) h
+            WHERE w.hold_id = h.hold_id AND w.status = 'Offered'
+        ), counts AS (
+            SELECT event_id, COUNT(*) as released FROM released GROUP BY event_id
+        ), tier_counts AS (
+            SELECT tier_id, COUNT(*) as released
+            FROM released
+            WHERE tier_id IS NOT NULL
+            GROUP BY tier_id
+        ), restored_tiers AS (
+            UPDATE ticket_tiers tt
+            SET available = tt.available + c.released
+            FROM tier_counts c
+            WHERE tt.id = c.tier_id
+        ), restored AS (
+            UPDATE events e
+            SET available = e.available + c.released
+            FROM counts c
+            WHERE e.id = c.event_id
+            RETURNING c.released
+        )
+        SELECT COALESCE(SUM(released), 0)::BIGINT as "restored!" FROM restored
+        "#,
+        hold_ids
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok(restored)
+}
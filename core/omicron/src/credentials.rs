@@ -0,0 +1,171 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier as _, VerifyingKey, SIGNATURE_LENGTH};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// First byte of every credential, bumped if the layout ever changes.
+const VERSION: u8 = 1;
+
+/// version, ticket id, event id, holder id, nonce.
+const CLAIMS_LENGTH: usize = 1 + 4 + 4 + 4 + 16;
+
+/// What a ticket's QR code vouches for: that `holder_id` owns `ticket_id`
+/// for `event_id`. The nonce is stored with the ticket so a credential can
+/// be matched to the exact ticket row it was issued for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credential {
+    pub ticket_id: i32,
+    pub event_id: i32,
+    pub holder_id: i32,
+    pub nonce: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialError {
+    Malformed,
+    UnsupportedVersion(u8),
+    WrongEvent { expected: i32, found: i32 },
+    InvalidSignature,
+}
+
+impl std::fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialError::Malformed => write!(f, "Credential is malformed"),
+            CredentialError::UnsupportedVersion(version) => {
+                write!(f, "Credential version {} is not supported", version)
+            }
+            CredentialError::WrongEvent { expected, found } => write!(
+                f,
+                "Credential is for event {}, not event {}",
+                found, expected
+            ),
+            CredentialError::InvalidSignature => write!(f, "Credential signature is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+impl Credential {
+    fn claims(&self) -> [u8; CLAIMS_LENGTH] {
+        let mut claims = [0u8; CLAIMS_LENGTH];
+        claims[0] = VERSION;
+        claims[1..5].copy_from_slice(&self.ticket_id.to_be_bytes());
+        claims[5..9].copy_from_slice(&self.event_id.to_be_bytes());
+        claims[9..13].copy_from_slice(&self.holder_id.to_be_bytes());
+        claims[13..].copy_from_slice(self.nonce.as_bytes());
+        claims
+    }
+
+    /// The QR payload: the claims followed by an Ed25519 signature over
+    /// them, base64url encoded without padding.
+    pub fn sign(&self, key: &SigningKey) -> String {
+        let claims = self.claims();
+        let signature = key.sign(&claims);
+
+        let mut token = Vec::with_capacity(CLAIMS_LENGTH + SIGNATURE_LENGTH);
+        token.extend_from_slice(&claims);
+        token.extend_from_slice(&signature.to_bytes());
+        URL_SAFE_NO_PAD.encode(token)
+    }
+
+    /// Reads the claims out of a token without checking its signature.
+    pub fn decode(token: &str) -> Result<(Credential, Signature), CredentialError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(token.trim())
+            .map_err(|_| CredentialError::Malformed)?;
+        if bytes.len() != CLAIMS_LENGTH + SIGNATURE_LENGTH {
+            return Err(CredentialError::Malformed);
+        }
+        if bytes[0] != VERSION {
+            return Err(CredentialError::UnsupportedVersion(bytes[0]));
+        }
+
+        let int = |at: usize| i32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        let credential = Credential {
+            ticket_id: int(1),
+            event_id: int(5),
+            holder_id: int(9),
+            nonce: Uuid::from_slice(&bytes[13..CLAIMS_LENGTH])
+                .map_err(|_| CredentialError::Malformed)?,
+        };
+        let signature = Signature::from_slice(&bytes[CLAIMS_LENGTH..])
+            .map_err(|_| CredentialError::Malformed)?;
+        Ok((credential, signature))
+    }
+}
+
+/// Checks credentials for one event using only its public key, so door
+/// scanners can admit people without a connection. It does not know which
+/// tickets were already scanned or resold; that is up to the caller.
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    event_id: i32,
+    key: VerifyingKey,
+}
+
+impl Verifier {
+    pub fn new(event_id: i32, key: VerifyingKey) -> Self {
+        Verifier { event_id, key }
+    }
+
+    /// Builds a verifier from the base64url key served by omicron.
+    pub fn from_public_key(event_id: i32, public_key: &str) -> Result<Self, CredentialError> {
+        Ok(Verifier::new(event_id, decode_public_key(public_key)?))
+    }
+
+    pub fn event_id(&self) -> i32 {
+        self.event_id
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Credential, CredentialError> {
+        let (credential, signature) = Credential::decode(token)?;
+        if credential.event_id != self.event_id {
+            return Err(CredentialError::WrongEvent {
+                expected: self.event_id,
+                found: credential.event_id,
+            });
+        }
+
+        self.key
+            .verify(&credential.claims(), &signature)
+            .map_err(|_| CredentialError::InvalidSignature)?;
+        Ok(credential)
+    }
+}
+
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    URL_SAFE_NO_PAD.encode(key.as_bytes())
+}
+
+pub fn decode_public_key(public_key: &str) -> Result<VerifyingKey, CredentialError> {
+    let bytes: [u8; 32] = URL_SAFE_NO_PAD
+        .decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(CredentialError::Malformed)?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| CredentialError::Malformed)
+}
+
+fn secret() -> String {
+    std::env::var("TICKET_SIGNING_SECRET").expect("TICKET_SIGNING_SECRET is not set")
+}
+
+/// Each event signs with its own key, derived from `TICKET_SIGNING_SECRET`
+/// so nothing secret has to be stored per event.
+pub fn signing_key(event_id: i32) -> SigningKey {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret().as_bytes()).expect("HMAC accepts any key length");
+    mac.update(b"halo ticket credentials");
+    mac.update(&event_id.to_be_bytes());
+    SigningKey::from_bytes(&mac.finalize().into_bytes().into())
+}
+
+/// Verifies `event_id`'s credentials the same way a scanner holding only
+/// the public key would.
+pub fn verifier(event_id: i32) -> Verifier {
+    Verifier::new(event_id, signing_key(event_id).verifying_key())
+}
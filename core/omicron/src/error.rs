@@ -0,0 +1,377 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+/// Error returned by every omicron handler.
+///
+/// Clients get `{"error": {"code": ..., "message": ...}}` where `code` is
+/// stable and safe to match on. `Internal` details are logged, never sent.
+#[derive(Debug)]
+pub enum ApiError {
+    ValidationFailed(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    InsufficientSupply(String),
+    PaymentFailed(String),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::ValidationFailed(_) => "validation_failed",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::InsufficientSupply(_) => "insufficient_supply",
+            ApiError::PaymentFailed(_) => "payment_failed",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::ValidationFailed(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) | ApiError::InsufficientSupply(_) => StatusCode::CONFLICT,
+            ApiError::PaymentFailed(_) => StatusCode::PAYMENT_REQUIRED,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::ValidationFailed(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::InsufficientSupply(msg)
+            | ApiError::PaymentFailed(msg) => msg,
+            ApiError::Internal(_) => "Internal server error",
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Internal(details) => write!(f, "{}: {}", self.code(), details),
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let ApiError::Internal(details) = &self {
+            log::error!("internal error: {}", details);
+        }
+
+        let body = serde_json::json!({
+            "error": {
+                "code": self.code(),
+                "message": self.message(),
+            }
+        });
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+const UNIQUE_VIOLATION: &str = "23505";
+const CHECK_VIOLATION: &str = "23514";
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Resource not found".to_string()),
+            sqlx::Error::Database(db) if db.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+                ApiError::Conflict("Resource already exists".to_string())
+            }
+            sqlx::Error::Database(db) if db.code().as_deref() == Some(CHECK_VIOLATION) => {
+                ApiError::Conflict("Request conflicts with the current state".to_string())
+            }
+            _ => ApiError::Internal(format!("Database error: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::PurchaseError;
+    use crate::money::{Currency, Money, MoneyError};
+    use crate::payments::PaymentError;
+    use crate::refunds::RefundError;
+    use crate::resale::ResaleError;
+    use std::borrow::Cow;
+
+    /// A Postgres error carrying only a SQLSTATE code.
+    #[derive(Debug)]
+    struct SqlState(&'static str);
+
+    impl std::fmt::Display for SqlState {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "SQLSTATE {}", self.0)
+        }
+    }
+
+    impl std::error::Error for SqlState {}
+
+    impl sqlx::error::DatabaseError for SqlState {
+        fn message(&self) -> &str {
+            "database error"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+    }
+
+    fn sql_state(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(SqlState(code)))
+    }
+
+    /// `(status, code)` the error is sent to clients with.
+    fn sent_as(e: impl Into<ApiError>) -> (StatusCode, &'static str) {
+        let e = e.into();
+        (e.status(), e.code())
+    }
+
+    #[test]
+    fn every_variant_has_a_status_and_stable_code() {
+        let cases = [
+            (
+                ApiError::ValidationFailed("bad".into()),
+                StatusCode::BAD_REQUEST,
+                "validation_failed",
+            ),
+            (
+                ApiError::Unauthorized("who".into()),
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+            ),
+            (
+                ApiError::Forbidden("no".into()),
+                StatusCode::FORBIDDEN,
+                "forbidden",
+            ),
+            (
+                ApiError::NotFound("gone".into()),
+                StatusCode::NOT_FOUND,
+                "not_found",
+            ),
+            (
+                ApiError::Conflict("busy".into()),
+                StatusCode::CONFLICT,
+                "conflict",
+            ),
+            (
+                ApiError::InsufficientSupply("sold out".into()),
+                StatusCode::CONFLICT,
+                "insufficient_supply",
+            ),
+            (
+                ApiError::PaymentFailed("declined".into()),
+                StatusCode::PAYMENT_REQUIRED,
+                "payment_failed",
+            ),
+            (
+                ApiError::Internal("boom".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+            ),
+        ];
+        for (e, status, code) in cases {
+            assert_eq!((e.status(), e.code()), (status, code), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn internal_details_are_never_sent() {
+        let e = ApiError::Internal("password=hunter2".into());
+        assert_eq!(e.message(), "Internal server error");
+        assert_eq!(ApiError::NotFound("gone".into()).message(), "gone");
+    }
+
+    #[tokio::test]
+    async fn responses_carry_the_status_code_and_message() {
+        let response = ApiError::Forbidden("no".into()).into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"error": {"code": "forbidden", "message": "no"}})
+        );
+    }
+
+    #[test]
+    fn database_errors_map_by_sqlstate() {
+        assert_eq!(
+            sent_as(sqlx::Error::RowNotFound),
+            (StatusCode::NOT_FOUND, "not_found")
+        );
+        assert_eq!(
+            sent_as(sql_state(UNIQUE_VIOLATION)),
+            (StatusCode::CONFLICT, "conflict")
+        );
+        assert_eq!(
+            sent_as(sql_state(CHECK_VIOLATION)),
+            (StatusCode::CONFLICT, "conflict")
+        );
+        assert_eq!(
+            sent_as(sql_state("40P01")),
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+        );
+        assert_eq!(
+            sent_as(sqlx::Error::PoolTimedOut),
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+        );
+    }
+
+    #[test]
+    fn purchase_errors_map_to_api_errors() {
+        let cases = [
+            (PurchaseError::InvalidQuantity(0), "validation_failed"),
+            (PurchaseError::TierRequired(1), "validation_failed"),
+            (
+                PurchaseError::OrderLimit("limit".into()),
+                "validation_failed",
+            ),
+            (
+                PurchaseError::InvalidIdempotencyKey("key".into()),
+                "validation_failed",
+            ),
+            (
+                PurchaseError::IdempotencyKeyReused("key".into()),
+                "validation_failed",
+            ),
+            (
+                PurchaseError::Pricing(MoneyError::Overflow),
+                "validation_failed",
+            ),
+            (PurchaseError::EventNotFound(1), "not_found"),
+            (PurchaseError::TierNotFound(1), "not_found"),
+            (PurchaseError::PromoCodeNotFound("CODE".into()), "not_found"),
+            (PurchaseError::EventNotOnSale(1), "conflict"),
+            (PurchaseError::TierNotOnSale("later".into()), "conflict"),
+            (
+                PurchaseError::HoldUnavailable(uuid::Uuid::nil()),
+                "conflict",
+            ),
+            (PurchaseError::QuoteExpired(chrono::Utc::now()), "conflict"),
+            (PurchaseError::PromoCodeRejected("used".into()), "conflict"),
+            (PurchaseError::PurchaseInProgress("key".into()), "conflict"),
+            (PurchaseError::NotInvited(1), "forbidden"),
+            (
+                PurchaseError::InsufficientSupply {
+                    available: 1,
+                    requested: 2,
+                },
+                "insufficient_supply",
+            ),
+            (
+                PurchaseError::PaymentFailed("declined".into()),
+                "payment_failed",
+            ),
+            (
+                PurchaseError::Database(sqlx::Error::RowNotFound),
+                "not_found",
+            ),
+            (
+                PurchaseError::Database(sqlx::Error::PoolTimedOut),
+                "internal_error",
+            ),
+        ];
+        for (e, code) in cases {
+            let label = format!("{:?}", e);
+            assert_eq!(sent_as(e).1, code, "{}", label);
+        }
+    }
+
+    #[test]
+    fn resale_errors_map_to_api_errors() {
+        let cases = [
+            (
+                ResaleError::InvalidPrice(Money::zero(Currency::USD)),
+                "validation_failed",
+            ),
+            (
+                ResaleError::Pricing(MoneyError::Overflow),
+                "validation_failed",
+            ),
+            (ResaleError::OwnListing(1), "validation_failed"),
+            (ResaleError::NotTicketOwner(1), "forbidden"),
+            (ResaleError::NotInvited(1), "forbidden"),
+            (ResaleError::TicketNotFound(1), "not_found"),
+            (ResaleError::ListingNotFound(1), "not_found"),
+            (ResaleError::NotResellable(1), "conflict"),
+            (ResaleError::AlreadyListed(1), "conflict"),
+            (ResaleError::ListingUnavailable(1), "conflict"),
+            (
+                ResaleError::PaymentFailed("declined".into()),
+                "payment_failed",
+            ),
+            (
+                ResaleError::Database(sql_state(UNIQUE_VIOLATION)),
+                "conflict",
+            ),
+        ];
+        for (e, code) in cases {
+            let label = format!("{:?}", e);
+            assert_eq!(sent_as(e).1, code, "{}", label);
+        }
+    }
+
+    #[test]
+    fn refund_errors_map_to_api_errors() {
+        let cases = [
+            (RefundError::TicketNotFound(1), "not_found"),
+            (RefundError::NotTicketOwner(1), "forbidden"),
+            (RefundError::NotRefundable(1), "conflict"),
+            (RefundError::Resold(1), "conflict"),
+            (RefundError::NotAllowed, "conflict"),
+            (RefundError::DeadlinePassed(chrono::Utc::now()), "conflict"),
+            (
+                RefundError::Pricing(MoneyError::Overflow),
+                "validation_failed",
+            ),
+            (
+                RefundError::Payment(PaymentError::Declined("no".into())),
+                "payment_failed",
+            ),
+            (
+                RefundError::Payment(PaymentError::Provider("down".into())),
+                "internal_error",
+            ),
+            (RefundError::Database(sqlx::Error::RowNotFound), "not_found"),
+        ];
+        for (e, code) in cases {
+            let label = format!("{:?}", e);
+            assert_eq!(sent_as(e).1, code, "{}", label);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::inventory;
+use crate::models::event::{self, validation::validate_event, Event, EventCategory};
+use crate::models::tier;
+use crate::money::{Currency, Money};
+use crate::payments;
+use crate::refunds::{self, RefundSummary};
+use crate::waitlist;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+
+#[derive(Deserialize)]
+pub struct CreateEventRequest {
+    pub title: String,
+    pub description: Option<String>,
+    pub location: String,
+    pub address: String,
+    pub category: EventCategory,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub capacity: i64,
+    pub price: Option<Money>,
+    #[serde(default)]
+    pub is_private: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub image_url: Option<String>,
+}
+
+/// Partial update; omitted fields are left unchanged.
+#[derive(Deserialize)]
+pub struct UpdateEventRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub address: Option<String>,
+    pub category: Option<EventCategory>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub capacity: Option<i64>,
+    pub price: Option<Money>,
+    pub is_private: Option<bool>,
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub add_tags: Vec<String>,
+    #[serde(default)]
+    pub remove_tags: Vec<String>,
+}
+
+pub async fn create(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<CreateEventRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = Event::new(
+        req.title,
+        req.description,
+        req.location,
+        req.address,
+        req.category,
+        req.start_time,
+        req.end_time,
+        req.capacity,
+        req.price.unwrap_or(Money::zero(Currency::USD)),
+        req.is_private,
+        user.id,
+        req.tags,
+        req.image_url,
+    );
+    validate_event(&event).map_err(ApiError::ValidationFailed)?;
+
+    let event = event::store::insert(&pg_pool, &event).await?;
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+pub async fn update(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<UpdateEventRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut tx = pg_pool.begin().await?;
+    let mut event = lock_owned_event(&mut tx, &user, event_id).await?;
+    if !event.is_editable() {
+        return Err(ApiError::Conflict(format!(
+            "Event is {:?} and can no longer be edited",
+            event.status
+        )));
+    }
+
+    if let Some(title) = req.title {
+        event.update_title(title);
+    }
+    if let Some(description) = req.description {
+        event.update_description(Some(description));
+    }
+    if let Some(location) = req.location {
+        event.update_location(location);
+    }
+    if let Some(address) = req.address {
+        event.update_address(address);
+    }
+    if let Some(category) = req.category {
+        event.update_category(category);
+    }
+    if req.start_time.is_some() || req.end_time.is_some() {
+        let start = req.start_time.unwrap_or(event.start_time);
+        let end = req.end_time.unwrap_or(event.end_time);
+        event.update_time(start, end);
+    }
+    if let Some(capacity) = req.capacity {
+        event.update_capacity(capacity);
+    }
+    if let Some(price) = req.price {
+        event.update_price(price);
+    }
+    if let Some(is_private) = req.is_private {
+        event.set_private(is_private);
+    }
+    if let Some(image_url) = req.image_url {
+        event.update_image_url(Some(image_url));
+    }
+    for tag in req.add_tags {
+        event.add_tag(tag);
+    }
+    for tag in &req.remove_tags {
+        event.remove_tag(tag);
+    }
+
+    validate_event(&event).map_err(ApiError::ValidationFailed)?;
+    check_tiers(&pg_pool, &event).await?;
+    event::store::update(&mut tx, &event).await?;
+    tx.commit().await?;
+    inventory::announce(&pg_pool, event_id).await?;
+    if req.capacity.is_some() {
+        waitlist::offer_returned(&pg_pool, &[event_id]).await;
+    }
+
+    let event = event::store::fetch(&pg_pool, event_id).await?;
+    Ok(Json(event))
+}
+
+/// Keeps the event's capacity and currency consistent with its tiers.
+async fn check_tiers(pg_pool: &PgPool, event: &Event) -> Result<(), ApiError> {
+    let tiers = tier::store::fetch_for_event(pg_pool, event.id).await?;
+
+    let allocated: i64 = tiers.iter().map(|tier| tier.quantity).sum();
+    if event.capacity < allocated {
+        return Err(ApiError::ValidationFailed(format!(
+            "Capacity cannot be lower than the {} tickets allocated to tiers",
+            allocated
+        )));
+    }
+    if tiers
+        .iter()
+        .any(|tier| tier.price.currency != event.price.currency)
+    {
+        return Err(ApiError::ValidationFailed(
+            "Event currency must match its tiers' prices".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Loads an event the caller is allowed to manage.
+pub(crate) async fn owned_event(
+    pg_pool: &PgPool,
+    user: &AuthUser,
+    event_id: i32,
+) -> Result<Event, ApiError> {
+    check_owner(event::store::fetch(pg_pool, event_id).await, user, event_id)
+}
+
+/// Like `owned_event`, but keeps the event locked until `tx` ends.
+async fn lock_owned_event(
+    tx: &mut Transaction<'_, Postgres>,
+    user: &AuthUser,
+    event_id: i32,
+) -> Result<Event, ApiError> {
+    check_owner(event::store::lock(tx, event_id).await, user, event_id)
+}
+
+fn check_owner(
+    event: Result<Event, sqlx::Error>,
+    user: &AuthUser,
+    event_id: i32,
+) -> Result<Event, ApiError> {
+    let event = event.map_err(|e| match e {
+        sqlx::Error::RowNotFound => ApiError::NotFound(format!("Event {} not found", event_id)),
+        e => e.into(),
+    })?;
+
+    if !event.is_owned_by(user.id) {
+        return Err(ApiError::Forbidden(
+            "Only the event's creator can manage it".to_string(),
+        ));
+    }
+    Ok(event)
+}
+
+async fn transition(
+    pg_pool: &PgPool,
+    user: &AuthUser,
+    event_id: i32,
+    apply: fn(&mut Event) -> Result<(), String>,
+) -> Result<Event, ApiError> {
+    let mut tx = pg_pool.begin().await?;
+    let mut event = lock_owned_event(&mut tx, user, event_id).await?;
+    apply(&mut event).map_err(ApiError::Conflict)?;
+    event::store::set_status(&mut tx, &event).await?;
+    tx.commit().await?;
+    inventory::announce(pg_pool, event_id).await?;
+    Ok(event)
+}
+
+pub async fn publish(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = transition(&pg_pool, &user, event_id, Event::publish).await?;
+    Ok(Json(event))
+}
+
+#[derive(Serialize)]
+pub struct CancelResponse {
+    pub event: Event,
+    pub refunds: RefundSummary,
+}
+
+/// Cancels the event and refunds every ticket sold for it in full.
+pub async fn cancel(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = transition(&pg_pool, &user, event_id, Event::cancel).await?;
+    let refunds = refunds::refund_event(&pg_pool, payments::provider(), event_id).await?;
+    Ok(Json(CancelResponse { event, refunds }))
+}
+
+pub async fn complete(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = transition(&pg_pool, &user, event_id, Event::complete).await?;
+    Ok(Json(event))
+}
@@ -0,0 +1,189 @@
+use crate::internal::{PurchaseError, TicketPurchaseResponse};
+use crate::models::order;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use std::future::Future;
+
+/// Header HTTP clients send their idempotency key in.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+const MAX_KEY_LENGTH: usize = 255;
+const DEFAULT_WINDOW_HOURS: i64 = 24;
+
+/// How long a purchase's result is replayed to retries with its key, from
+/// `IDEMPOTENCY_WINDOW_HOURS`. Older keys may be used again.
+pub fn window() -> Duration {
+    let hours = std::env::var("IDEMPOTENCY_WINDOW_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .unwrap_or(DEFAULT_WINDOW_HOURS);
+    Duration::hours(hours)
+}
+
+/// What a keyed purchase asked for. Retries must ask for the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurchaseIntent {
+    pub event_id: i32,
+    pub tier_id: Option<i32>,
+    pub quantity: i64,
+    pub promo_code: Option<String>,
+}
+
+enum Claim {
+    /// The key is ours; the purchase should go ahead.
+    Claimed(i32),
+    /// A purchase with the key already completed; this is what it bought.
+    Completed(TicketPurchaseResponse),
+}
+
+/// Runs `purchase` at most once per `key` and user. A retry with the same
+/// key gets the first purchase's result back instead of buying again; one
+/// that arrives while the first is still running is turned away. Failed
+/// purchases give the key up so it can be retried. Without a key the
+/// purchase simply runs.
+pub async fn purchase_once<F, E>(
+    pg_pool: &PgPool,
+    user_id: i32,
+    key: Option<&str>,
+    intent: &PurchaseIntent,
+    purchase: F,
+) -> Result<TicketPurchaseResponse, E>
+where
+    F: Future<Output = Result<TicketPurchaseResponse, E>>,
+    E: From<PurchaseError>,
+{
+    let Some(key) = key else {
+        return purchase.await;
+    };
+
+    let id = match claim(pg_pool, user_id, key, intent).await? {
+        Claim::Claimed(id) => id,
+        Claim::Completed(response) => {
+            log::info!("replayed purchase for idempotency key {}", key);
+            return Ok(response);
+        }
+    };
+
+    match purchase.await {
+        Ok(response) => {
+            // The tickets are sold either way; a retry after a failed write
+            // is turned away as in progress until the key ages out.
+            if let Err(e) = complete(pg_pool, id, response.order_id).await {
+                log::error!("failed to store result for idempotency key {}: {}", key, e);
+            }
+            Ok(response)
+        }
+        Err(e) => {
+            sqlx::query!("DELETE FROM idempotency_keys WHERE id = $1", id)
+                .execute(pg_pool)
+                .await
+                .map_err(PurchaseError::from)?;
+            Err(e)
+        }
+    }
+}
+
+async fn claim(
+    pg_pool: &PgPool,
+    user_id: i32,
+    key: &str,
+    intent: &PurchaseIntent,
+) -> Result<Claim, PurchaseError> {
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(PurchaseError::InvalidIdempotencyKey(format!(
+            "Idempotency keys must be 1 to {} characters",
+            MAX_KEY_LENGTH
+        )));
+    }
+    let cutoff = Utc::now() - window();
+
+    loop {
+        // Keys past the window are taken over as if they were new.
+        let claimed = sqlx::query_scalar!(
+            r#"
+            INSERT INTO idempotency_keys (user_id, key, event_id, tier_id, quantity, promo_code)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id, key) DO UPDATE
+            SET event_id = EXCLUDED.event_id, tier_id = EXCLUDED.tier_id,
+                quantity = EXCLUDED.quantity, promo_code = EXCLUDED.promo_code,
+                order_id = NULL, created_at = NOW()
+            WHERE idempotency_keys.created_at <= $7
+            RETURNING id
+            "#,
+            user_id,
+            key,
+            intent.event_id,
+            intent.tier_id,
+            intent.quantity,
+            intent.promo_code,
+            cutoff
+        )
+        .fetch_optional(pg_pool)
+        .await?;
+        if let Some(id) = claimed {
+            return Ok(Claim::Claimed(id));
+        }
+
+        let existing = sqlx::query!(
+            r#"
+            SELECT event_id, tier_id, quantity, promo_code, order_id
+            FROM idempotency_keys
+            WHERE user_id = $1 AND key = $2
+            "#,
+            user_id,
+            key
+        )
+        .fetch_optional(pg_pool)
+        .await?;
+        // Gone again if its purchase just failed; try to claim it afresh.
+        let Some(existing) = existing else {
+            continue;
+        };
+
+        let used_for = PurchaseIntent {
+            event_id: existing.event_id,
+            tier_id: existing.tier_id,
+            quantity: existing.quantity,
+            promo_code: existing.promo_code,
+        };
+        if used_for != *intent {
+            return Err(PurchaseError::IdempotencyKeyReused(key.to_string()));
+        }
+
+        return match existing.order_id {
+            Some(order_id) => {
+                let order = order::store::fetch(pg_pool, order_id).await?;
+                Ok(Claim::Completed(TicketPurchaseResponse {
+                    order_id,
+                    ticket_ids: order.ticket_ids(),
+                    event_name: order.event_name,
+                    total: order.total,
+                }))
+            }
+            None => Err(PurchaseError::PurchaseInProgress(key.to_string())),
+        };
+    }
+}
+
+/// Points the key at the order its purchase created.
+async fn complete(pg_pool: &PgPool, id: i32, order_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE idempotency_keys SET order_id = $2 WHERE id = $1",
+        id,
+        order_id
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Forgets keys that are past the window. Returns how many were removed.
+pub async fn purge_expired(pg_pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let purged = sqlx::query!(
+        "DELETE FROM idempotency_keys WHERE created_at <= $1",
+        Utc::now() - window()
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(purged.rows_affected())
+}
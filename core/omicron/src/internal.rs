@@ -1,5 +1,16 @@
-use crate::DB_POOL;
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use crate::auth::jwt::AuthUser;
+use crate::checkout;
+use crate::error::ApiError;
+use crate::idempotency::{self, PurchaseIntent, IDEMPOTENCY_KEY_HEADER};
+use crate::money::{Money, MoneyError};
+use crate::payments::{self, PaymentProvider};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::Duration;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
@@ -10,89 +21,229 @@ pub struct User {
 }
 
 pub async fn users(
+    _user: AuthUser,
     State(pg_pool): State<PgPool>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, ApiError> {
     let rows = sqlx::query_as!(User, "SELECT name, email FROM USERS")
         .fetch_all(&pg_pool)
-        .await
-        .map_err(|e| {
-            let error_response = serde_json::json!({
-                "status": "error",
-                "message": format!("Database error: { }", e),
-            });
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-        })?;
+        .await?;
     Ok(Json(rows))
 }
 
+/// The body of `POST /purchases`. The buyer is always the caller.
 #[derive(Deserialize)]
 pub struct TicketPurchaseRequest {
+    pub event_id: i32,
+    /// Required for events that sell tiers.
+    #[serde(default)]
+    pub tier_id: Option<i32>,
+    pub quantity: i64,
+    #[serde(default)]
+    pub promo_code: Option<String>,
+}
+
+impl TicketPurchaseRequest {
+    pub fn for_user(self, user_id: i32) -> TicketPurchase {
+        TicketPurchase {
+            user_id,
+            event_id: self.event_id,
+            tier_id: self.tier_id,
+            quantity: self.quantity,
+            promo_code: self.promo_code,
+        }
+    }
+}
+
+/// A purchase for a buyer the caller has already authenticated.
+pub struct TicketPurchase {
     pub user_id: i32,
     pub event_id: i32,
+    pub tier_id: Option<i32>,
     pub quantity: i64,
+    pub promo_code: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct TicketPurchaseResponse {
-    ticket_id: i32,
-    event_name: String,
+    pub order_id: i32,
+    pub ticket_ids: Vec<i32>,
+    pub event_name: String,
+    pub total: Money,
 }
 
-pub async fn purchase_ticket(
-    Json(request): Json<TicketPurchaseRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let pg_pool = DB_POOL.get().expect("DB_POOL must be initialized");
-    let available_tickets = sqlx::query_scalar!(
-        "SELECT available FROM events WHERE id = $1",
-        request.event_id
-    )
-    .fetch_one(pg_pool)
-    .await
-    .map_err(|e| {
-        let error_response = serde_json::json!({
-            "status": "error",
-            "message": format!("Database error: {}", e),
-        });
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-    })?;
-
-    if available_tickets < Some(request.quantity) {
-        let error_response = serde_json::json!({
-            "status": "error",
-            "message": "Not enough tickets available",
-        });
-        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+#[derive(Debug)]
+pub enum PurchaseError {
+    InvalidQuantity(i64),
+    EventNotFound(i32),
+    EventNotOnSale(i32),
+    NotInvited(i32),
+    TierNotFound(i32),
+    TierRequired(i32),
+    TierNotOnSale(String),
+    OrderLimit(String),
+    InsufficientSupply { available: i64, requested: i64 },
+    HoldUnavailable(uuid::Uuid),
+    QuoteExpired(chrono::DateTime<chrono::Utc>),
+    InvalidIdempotencyKey(String),
+    IdempotencyKeyReused(String),
+    PurchaseInProgress(String),
+    PromoCodeNotFound(String),
+    PromoCodeRejected(String),
+    PaymentFailed(String),
+    Pricing(MoneyError),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for PurchaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PurchaseError::InvalidQuantity(quantity) => {
+                write!(f, "Quantity must be at least 1, got {}", quantity)
+            }
+            PurchaseError::EventNotFound(event_id) => write!(f, "Event {} not found", event_id),
+            PurchaseError::EventNotOnSale(event_id) => {
+                write!(f, "Event {} is not on sale", event_id)
+            }
+            PurchaseError::NotInvited(event_id) => {
+                write!(f, "Event {} is private and you are not invited", event_id)
+            }
+            PurchaseError::TierNotFound(tier_id) => {
+                write!(f, "Ticket tier {} not found for this event", tier_id)
+            }
+            PurchaseError::TierRequired(event_id) => {
+                write!(f, "Event {} sells tiers; choose one", event_id)
+            }
+            PurchaseError::PromoCodeNotFound(code) => write!(f, "Promo code {} not found", code),
+            PurchaseError::TierNotOnSale(reason)
+            | PurchaseError::OrderLimit(reason)
+            | PurchaseError::PromoCodeRejected(reason) => write!(f, "{}", reason),
+            PurchaseError::InsufficientSupply {
+                available,
+                requested,
+            } => write!(
+                f,
+                "Not enough tickets available. Available: {}, Requested: {}",
+                available, requested
+            ),
+            PurchaseError::HoldUnavailable(hold_id) => {
+                write!(f, "Hold {} has expired or does not exist", hold_id)
+            }
+            PurchaseError::QuoteExpired(expires_at) => {
+                write!(f, "The quoted price expired at {}", expires_at)
+            }
+            PurchaseError::InvalidIdempotencyKey(reason) => write!(f, "{}", reason),
+            PurchaseError::IdempotencyKeyReused(key) => write!(
+                f,
+                "Idempotency key {} was already used for a different purchase",
+                key
+            ),
+            PurchaseError::PurchaseInProgress(key) => write!(
+                f,
+                "A purchase with idempotency key {} is still in progress",
+                key
+            ),
+            PurchaseError::PaymentFailed(reason) => write!(f, "{}", reason),
+            PurchaseError::Pricing(e) => write!(f, "{}", e),
+            PurchaseError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for PurchaseError {
+    fn from(e: sqlx::Error) -> Self {
+        PurchaseError::Database(e)
+    }
+}
+
+impl From<MoneyError> for PurchaseError {
+    fn from(e: MoneyError) -> Self {
+        PurchaseError::Pricing(e)
+    }
+}
+
+impl From<PurchaseError> for ApiError {
+    fn from(e: PurchaseError) -> Self {
+        match e {
+            PurchaseError::InvalidQuantity(_)
+            | PurchaseError::TierRequired(_)
+            | PurchaseError::OrderLimit(_)
+            | PurchaseError::InvalidIdempotencyKey(_)
+            | PurchaseError::IdempotencyKeyReused(_) => ApiError::ValidationFailed(e.to_string()),
+            PurchaseError::EventNotFound(_)
+            | PurchaseError::TierNotFound(_)
+            | PurchaseError::PromoCodeNotFound(_) => ApiError::NotFound(e.to_string()),
+            PurchaseError::EventNotOnSale(_)
+            | PurchaseError::TierNotOnSale(_)
+            | PurchaseError::HoldUnavailable(_)
+            | PurchaseError::QuoteExpired(_)
+            | PurchaseError::PromoCodeRejected(_)
+            | PurchaseError::PurchaseInProgress(_) => ApiError::Conflict(e.to_string()),
+            PurchaseError::NotInvited(_) => ApiError::Forbidden(e.to_string()),
+            PurchaseError::InsufficientSupply { .. } => ApiError::InsufficientSupply(e.to_string()),
+            PurchaseError::PaymentFailed(_) => ApiError::PaymentFailed(e.to_string()),
+            PurchaseError::Pricing(_) => ApiError::ValidationFailed(e.to_string()),
+            PurchaseError::Database(e) => ApiError::from(e),
+        }
     }
+}
 
-    let ticket = sqlx::query_as!(
-        TicketPurchaseResponse,
-        r#"
-        WITH new_ticket AS (
-            INSERT INTO tickets (user_id, event_id)
-            VALUES ($1, $2)
-            RETURNING id as ticket_id
-        ),
-        updated_event AS (
-            UPDATE events
-            SET available = available - 1
-            WHERE id = $2
-            RETURNING name as event_name
-        )
-        SELECT new_ticket.ticket_id, updated_event.event_name
-        FROM new_ticket, updated_event
-        "#,
+/// How long a direct purchase may spend charging the buyer before its
+/// tickets go back on sale.
+const DIRECT_PURCHASE_HOLD_TTL_MINUTES: i64 = 2;
+
+/// Buys `quantity` tickets outright: holds them, charges the buyer and
+/// sells them once the payment is captured.
+pub async fn purchase_tickets(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    request: &TicketPurchase,
+) -> Result<TicketPurchaseResponse, PurchaseError> {
+    let hold = checkout::reserve(
+        pg_pool,
         request.user_id,
         request.event_id,
+        request.tier_id,
+        request.quantity,
+        request.promo_code.as_deref(),
+        Duration::minutes(DIRECT_PURCHASE_HOLD_TTL_MINUTES),
+    )
+    .await?;
+
+    checkout::pay(pg_pool, provider, request.user_id, hold.hold_id).await
+}
+
+/// Buys tickets for the signed-in user. Retries carrying the same
+/// `Idempotency-Key` header get the original purchase back.
+pub async fn purchase_ticket(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(request): Json<TicketPurchaseRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|key| {
+            key.to_str().map_err(|_| {
+                ApiError::ValidationFailed("Idempotency key must be visible ASCII".to_string())
+            })
+        })
+        .transpose()?;
+
+    let purchase = request.for_user(user.id);
+    let intent = PurchaseIntent {
+        event_id: purchase.event_id,
+        tier_id: purchase.tier_id,
+        quantity: purchase.quantity,
+        promo_code: purchase.promo_code.clone(),
+    };
+    let tickets = idempotency::purchase_once(
+        &pg_pool,
+        user.id,
+        key,
+        &intent,
+        purchase_tickets(&pg_pool, payments::provider(), &purchase),
     )
-    .fetch_one(pg_pool)
-    .await
-    .map_err(|e| {
-        let error_response = serde_json::json!({
-            "status": "error",
-            "message": format!("Database error: {}", e),
-        });
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-    })?;
-
-    Ok((StatusCode::CREATED, Json(ticket)))
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(tickets)))
 }
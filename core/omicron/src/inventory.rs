@@ -0,0 +1,123 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::internal::PurchaseError;
+use crate::invitations;
+use crate::models::event::{self, EventStatus};
+use crate::models::tier;
+use crate::money::Money;
+use crate::pricing::{self, Market};
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres};
+
+/// Postgres channel that carries the id of every event whose stock, status
+/// or prices just changed.
+pub const INVENTORY_CHANNEL: &str = "event_inventory";
+
+/// What is left of an event and what it costs right now.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Availability {
+    pub event_id: i32,
+    pub status: EventStatus,
+    pub available: i64,
+    pub sold_out: bool,
+    /// Price of the next ticket; `None` for events that sell tiers.
+    pub unit_price: Option<Money>,
+    pub tiers: Vec<TierAvailability>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierAvailability {
+    pub tier_id: i32,
+    pub name: String,
+    /// Never more than the event itself has left.
+    pub available: i64,
+    pub sold_out: bool,
+    pub unit_price: Money,
+}
+
+/// Announces on `INVENTORY_CHANNEL` that `event_id` changed. Inside a
+/// transaction the notice is only sent if it commits, and once however
+/// often it was announced.
+pub async fn announce<'e, E>(executor: E, event_id: i32) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    sqlx::query!(
+        "SELECT pg_notify($1, $2::INT::TEXT)",
+        INVENTORY_CHANNEL,
+        event_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Current stock and pricing of `event_id`, priced by its pricing rules.
+pub async fn availability(pg_pool: &PgPool, event_id: i32) -> Result<Availability, PurchaseError> {
+    let event = event::store::fetch(pg_pool, event_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => PurchaseError::EventNotFound(event_id),
+            e => e.into(),
+        })?;
+    let tiers = tier::store::fetch_for_event(pg_pool, event_id).await?;
+    let rules = pricing::rules(pg_pool, event_id).await?;
+    let now = Utc::now();
+    let available = event.available.unwrap_or(0);
+
+    let unit_price = if tiers.is_empty() {
+        let market = Market {
+            base_price: event.price,
+            quantity: event.capacity,
+            available,
+            starts_at: event.start_time,
+        };
+        Some(pricing::price(&rules, None, &market, now)?.unit_price)
+    } else {
+        None
+    };
+
+    let tiers = tiers
+        .into_iter()
+        .map(|tier| {
+            let market = Market {
+                base_price: tier.price,
+                quantity: tier.quantity,
+                available: tier.available,
+                starts_at: event.start_time,
+            };
+            let left = tier.available.min(available);
+            Ok(TierAvailability {
+                tier_id: tier.id,
+                unit_price: pricing::price(&rules, Some(tier.id), &market, now)?.unit_price,
+                name: tier.name,
+                available: left,
+                sold_out: left == 0,
+            })
+        })
+        .collect::<Result<Vec<_>, PurchaseError>>()?;
+
+    Ok(Availability {
+        event_id,
+        status: event.status,
+        available,
+        sold_out: available == 0,
+        unit_price,
+        tiers,
+    })
+}
+
+pub async fn get_availability(
+    user: Option<AuthUser>,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    invitations::check_visible(&pg_pool, event_id, user.map(|user| user.id)).await?;
+    Ok(Json(availability(&pg_pool, event_id).await?))
+}
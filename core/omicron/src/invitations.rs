@@ -0,0 +1,347 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::events::owned_event;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres};
+
+/// Unambiguous characters only, since codes get read out loud and typed.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 8;
+
+#[derive(Serialize)]
+pub struct Invitation {
+    pub id: i32,
+    pub event_id: i32,
+    pub email: Option<String>,
+    pub user_id: Option<i32>,
+    pub access_code_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct AccessCode {
+    pub id: i32,
+    pub event_id: i32,
+    pub code: String,
+    pub link: String,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn generate_code() -> String {
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[(OsRng.next_u32() as usize) % CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
+fn share_link(event_id: i32, code: &str) -> String {
+    let base = std::env::var("APP_URL").unwrap_or_else(|_| "http://localhost:3000".into());
+    format!(
+        "{}/events/{}?code={}",
+        base.trim_end_matches('/'),
+        event_id,
+        code
+    )
+}
+
+/// Whether `user_id` may see and buy tickets for `event_id`. Public events
+/// are open to everyone; private ones require an invitation (by user or by
+/// the user's email) unless the caller created the event.
+pub async fn has_access<'e, E>(
+    executor: E,
+    event_id: i32,
+    user_id: i32,
+) -> Result<bool, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT
+            NOT e.is_private
+            OR e.creator_id = $2
+            OR EXISTS (
+                SELECT 1
+                FROM event_invitations i
+                JOIN users u ON u.id = $2
+                WHERE i.event_id = e.id
+                  AND (i.user_id = u.id OR LOWER(i.email) = LOWER(u.email))
+            ) as "has_access!"
+        FROM events e
+        WHERE e.id = $1
+        "#,
+        event_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+    .map(|access| access.unwrap_or(false))
+}
+
+/// Hides private events from callers without access, who get the same
+/// 404 as for an event that does not exist. `user_id` is `None` for
+/// anonymous callers, who only see public events.
+pub async fn check_visible(
+    pg_pool: &PgPool,
+    event_id: i32,
+    user_id: Option<i32>,
+) -> Result<(), ApiError> {
+    let visible = match user_id {
+        Some(user_id) => has_access(pg_pool, event_id, user_id).await?,
+        None => sqlx::query_scalar!("SELECT NOT is_private FROM events WHERE id = $1", event_id)
+            .fetch_optional(pg_pool)
+            .await?
+            .flatten()
+            .unwrap_or(false),
+    };
+    if !visible {
+        return Err(ApiError::NotFound(format!("Event {} not found", event_id)));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct InviteRequest {
+    pub emails: Vec<String>,
+}
+
+pub async fn invite(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<InviteRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+
+    let emails: Vec<String> = req
+        .emails
+        .iter()
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty())
+        .collect();
+    if emails.is_empty() {
+        return Err(ApiError::ValidationFailed(
+            "At least one email is required".to_string(),
+        ));
+    }
+
+    let invitations = sqlx::query_as!(
+        Invitation,
+        r#"
+        INSERT INTO event_invitations (event_id, email)
+        SELECT $1, UNNEST($2::VARCHAR[])
+        ON CONFLICT DO NOTHING
+        RETURNING id, event_id, email, user_id, access_code_id, created_at
+        "#,
+        event_id,
+        &emails
+    )
+    .fetch_all(&pg_pool)
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(invitations)))
+}
+
+pub async fn invitations(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+
+    let rows = sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT id, event_id, email, user_id, access_code_id, created_at
+        FROM event_invitations
+        WHERE event_id = $1
+        ORDER BY created_at
+        "#,
+        event_id
+    )
+    .fetch_all(&pg_pool)
+    .await?;
+
+    Ok(Json(rows))
+}
+
+pub async fn uninvite(
+    user: AuthUser,
+    Path((event_id, invitation_id)): Path<(i32, i32)>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+
+    let deleted = sqlx::query!(
+        "DELETE FROM event_invitations WHERE id = $1 AND event_id = $2",
+        invitation_id,
+        event_id
+    )
+    .execute(&pg_pool)
+    .await?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Invitation {} not found",
+            invitation_id
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct CreateAccessCodeRequest {
+    pub max_uses: Option<i32>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+pub async fn create_access_code(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<CreateAccessCodeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+
+    if req.max_uses.is_some_and(|max_uses| max_uses <= 0) {
+        return Err(ApiError::ValidationFailed(
+            "max_uses must be greater than 0".to_string(),
+        ));
+    }
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO event_access_codes (event_id, code, max_uses, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, code, uses
+        "#,
+        event_id,
+        generate_code(),
+        req.max_uses,
+        req.expires_at
+    )
+    .fetch_one(&pg_pool)
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(AccessCode {
+            id: row.id,
+            event_id,
+            link: share_link(event_id, &row.code),
+            code: row.code,
+            max_uses: req.max_uses,
+            uses: row.uses,
+            expires_at: req.expires_at,
+        }),
+    ))
+}
+
+pub async fn revoke_access_code(
+    user: AuthUser,
+    Path((event_id, code_id)): Path<(i32, i32)>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE event_access_codes
+        SET revoked_at = NOW()
+        WHERE id = $1 AND event_id = $2 AND revoked_at IS NULL
+        "#,
+        code_id,
+        event_id
+    )
+    .execute(&pg_pool)
+    .await?;
+
+    if revoked.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Access code {} not found",
+            code_id
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct RedeemRequest {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RedeemResponse {
+    pub event_id: i32,
+}
+
+/// Turns a shared access code into an invitation for the caller.
+/// Redeeming when the caller already has access is a no-op.
+pub async fn redeem(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<RedeemRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut tx = pg_pool.begin().await?;
+
+    let code = sqlx::query!(
+        r#"
+        SELECT id, event_id, max_uses, uses, expires_at, revoked_at
+        FROM event_access_codes
+        WHERE code = $1
+        FOR UPDATE
+        "#,
+        req.code.trim().to_uppercase()
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Access code not found".to_string()))?;
+
+    if has_access(&mut tx, code.event_id, user.id).await? {
+        return Ok(Json(RedeemResponse {
+            event_id: code.event_id,
+        }));
+    }
+
+    let expired = code.expires_at.is_some_and(|at| at <= Utc::now());
+    let exhausted = code.max_uses.is_some_and(|max| code.uses >= max);
+    if code.revoked_at.is_some() || expired || exhausted {
+        return Err(ApiError::Conflict(
+            "Access code is no longer valid".to_string(),
+        ));
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO event_invitations (event_id, user_id, access_code_id)
+        VALUES ($1, $2, $3)
+        "#,
+        code.event_id,
+        user.id,
+        code.id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE event_access_codes SET uses = uses + 1 WHERE id = $1",
+        code.id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(RedeemResponse {
+        event_id: code.event_id,
+    }))
+}
@@ -1,21 +1,37 @@
 pub mod auth;
+pub mod checkin;
+pub mod checkout;
+pub mod credentials;
+pub mod error;
+pub mod events;
+pub mod idempotency;
 pub mod internal;
+pub mod inventory;
+pub mod invitations;
+pub mod models;
+pub mod money;
+pub mod orders;
+pub mod payments;
+pub mod pricing;
+pub mod promos;
 pub mod public;
+pub mod refunds;
+pub mod resale;
+pub mod tiers;
 pub mod types;
 pub mod users;
+pub mod waitlist;
+pub mod wallet;
 
-use crate::auth::handlers::{login, signup};
+use crate::auth::handlers::{login, logout, logout_all, refresh, signup};
 use anyhow::{Context, Result};
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
-    Json, Router,
+    routing::{delete, get, patch, post},
+    Router,
 };
 use colored::*;
 use dotenv::dotenv;
-use serde::{Deserialize, Serialize};
+use simplelog::{ColorChoice, LevelFilter, TermLogger, TerminalMode};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::env;
 use tokio::net::TcpListener;
@@ -37,12 +53,41 @@ pub async fn initialize_db_pool() {
     DB_POOL.set(pool).expect("DB_POOL can only be set once");
 }
 
+/// Tokens and ticket credentials have no safe default key, so a missing
+/// secret stops the server here rather than on the first request.
+pub fn check_secrets() {
+    dotenv().ok();
+    for var in ["JWT_SECRET", "TICKET_SIGNING_SECRET"] {
+        if env::var(var).is_err() {
+            panic!("{} is not set", var);
+        }
+    }
+}
+
 #[tokio::main]
 pub async fn run() -> Result<(), Error> {
-    println!("{} {} {}", "starting", "OMICRON".purple().bold(), "...");
+    println!("starting {} ...", "OMICRON".purple().bold());
+
+    // Fails harmlessly if another service in this process set it up first.
+    let _ = TermLogger::init(
+        LevelFilter::Info,
+        simplelog::Config::default(),
+        TerminalMode::Mixed,
+        ColorChoice::Auto,
+    );
 
+    check_secrets();
     initialize_db_pool().await;
 
+    let sweep_interval = env::var("HOLD_SWEEP_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(30);
+    checkout::spawn_sweeper(
+        DB_POOL.get().expect("DB_POOL must be initialized").clone(),
+        std::time::Duration::from_secs(sweep_interval),
+    );
+
     let server_address = env::var("API_ADDRESS").expect("SERVER_ADDRESS not set");
 
     let cors = CorsLayer::new()
@@ -52,10 +97,84 @@ pub async fn run() -> Result<(), Error> {
 
     let app = Router::new()
         .route("/users", get(internal::users))
-        .route("/events", get(public::events))
+        .route("/events", get(public::events).post(events::create))
+        .route("/events/:event_id", patch(events::update))
+        .route(
+            "/events/:event_id/tiers",
+            get(tiers::tiers).post(tiers::create),
+        )
+        .route(
+            "/events/:event_id/tiers/:tier_id",
+            patch(tiers::update).delete(tiers::delete),
+        )
+        .route(
+            "/events/:event_id/pricing-rules",
+            get(pricing::get_rules).put(pricing::set_rules),
+        )
+        .route("/events/:event_id/quote", get(pricing::get_quote))
+        .route(
+            "/events/:event_id/availability",
+            get(inventory::get_availability),
+        )
+        .route(
+            "/events/:event_id/promo-codes",
+            get(promos::promo_codes).post(promos::create_promo_code),
+        )
+        .route(
+            "/events/:event_id/promo-codes/:code_id",
+            delete(promos::revoke_promo_code),
+        )
+        .route(
+            "/events/:event_id/waitlist",
+            post(waitlist::join_waitlist).delete(waitlist::leave_waitlist),
+        )
+        .route("/waitlist/offers", get(waitlist::offers))
+        .route("/events/:event_id/publish", post(events::publish))
+        .route("/events/:event_id/cancel", post(events::cancel))
+        .route("/events/:event_id/complete", post(events::complete))
+        .route(
+            "/events/:event_id/invitations",
+            get(invitations::invitations).post(invitations::invite),
+        )
+        .route(
+            "/events/:event_id/invitations/:invitation_id",
+            delete(invitations::uninvite),
+        )
+        .route(
+            "/events/:event_id/access-codes",
+            post(invitations::create_access_code),
+        )
+        .route(
+            "/events/:event_id/access-codes/:code_id",
+            delete(invitations::revoke_access_code),
+        )
+        .route(
+            "/events/:event_id/refund-policy",
+            get(refunds::get_policy).put(refunds::set_policy),
+        )
+        .route("/events/:event_id/checkin", post(checkin::check_in))
+        .route("/events/:event_id/checkin-key", get(checkin::public_key))
+        .route("/events/:event_id/checkins", post(checkin::sync))
+        .route("/events/:event_id/manifest", get(checkin::get_manifest))
+        .route("/events/:event_id/credentials", get(checkin::credentials))
+        .route("/access-codes/redeem", post(invitations::redeem))
         .route("/tickets/:event_id", get(public::tickets))
+        .route("/purchases", post(internal::purchase_ticket))
+        .route("/me/orders", get(orders::my_orders))
+        .route("/me/tickets", get(wallet::my_tickets))
+        .route("/orders/:order_id", get(orders::get_order))
+        .route("/listings", post(resale::list_ticket))
+        .route("/listings/:listing_id", delete(resale::delist_ticket))
+        .route("/listings/:listing_id/purchase", post(resale::buy_listing))
+        .route("/events/:event_id/listings", get(resale::listings))
+        .route("/transfers/:ticket_id", get(resale::transfers))
+        .route("/refunds", post(refunds::refund))
+        .route("/payments/webhook", post(payments::webhook))
         .route("/signup", post(signup))
         .route("/login", post(login))
+        .route("/token/refresh", post(refresh))
+        .route("/logout", post(logout))
+        .route("/logout-all", post(logout_all))
         .with_state(DB_POOL.get().expect("DB_POOL must be initialized").clone())
         .layer(cors);
 
@@ -69,39 +188,10 @@ pub async fn run() -> Result<(), Error> {
     Ok(())
 }
 
-#[derive(Serialize, Debug)]
-pub struct EventPartial {
-    pub id: i32,
-    pub name: String,
-    pub capacity: i64,
-    pub available: Option<i64>,
-}
-
-pub async fn event(event_id: i32) -> Result<EventPartial> {
+pub async fn event(event_id: i32) -> Result<models::event::Event> {
     let pool = DB_POOL.get().expect("DB_POOL must be initialized");
 
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id,
-            name,
-            capacity,
-            available
-        FROM events
-        WHERE id = $1
-        "#,
-        event_id
-    )
-    .fetch_one(pool)
-    .await
-    .context("Failed to fetch event from database")?;
-
-    let event_partial = EventPartial {
-        id: row.id,
-        name: row.name,
-        capacity: row.capacity,
-        available: row.available,
-    };
-
-    Ok(event_partial)
+    models::event::store::fetch(pool, event_id)
+        .await
+        .context("Failed to fetch event from database")
 }
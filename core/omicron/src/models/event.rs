@@ -1,8 +1,20 @@
+use crate::money::Money;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "event_category")]
+#[derive(Serialize, Deserialize)]
+pub enum EventCategory {
+    Festival,
+    Concert,
+    Club,
+    Birthday,
+    Dinner,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "event_status")]
 pub enum EventStatus {
     Draft,
     Published,
@@ -12,70 +24,112 @@ pub enum EventStatus {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
-    pub id: Uuid,
+    pub id: i32,
     pub title: String,
     pub description: Option<String>,
     pub location: String,
+    pub address: String,
+    pub category: EventCategory,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
-    pub capacity: Option<u32>,
+    pub capacity: i64,
+    pub available: Option<i64>,
+    pub price: Money,
     pub is_private: bool,
-    pub creator_id: Uuid,
+    pub creator_id: Option<i32>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub status: EventStatus,
-    pub categories: Vec<String>,
     pub tags: Vec<String>,
     pub image_url: Option<String>,
 }
 
 impl Event {
+    /// Builds an unsaved draft; `id` is assigned by `store::insert`.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         title: String,
         description: Option<String>,
         location: String,
+        address: String,
+        category: EventCategory,
         start_time: DateTime<Utc>,
         end_time: DateTime<Utc>,
-        capacity: Option<u32>,
+        capacity: i64,
+        price: Money,
         is_private: bool,
-        creator_id: Uuid,
-        categories: Vec<String>,
+        creator_id: i32,
         tags: Vec<String>,
         image_url: Option<String>,
     ) -> Self {
         let now = Utc::now();
         Event {
-            id: Uuid::new_v4(),
+            id: 0,
             title,
             description,
             location,
+            address,
+            category,
             start_time,
             end_time,
             capacity,
+            available: Some(capacity),
+            price,
             is_private,
-            creator_id,
+            creator_id: Some(creator_id),
             created_at: now,
             updated_at: now,
             status: EventStatus::Draft,
-            categories,
             tags,
             image_url,
         }
     }
 
-    pub fn publish(&mut self) {
-        self.status = EventStatus::Published;
-        self.updated_at = Utc::now();
+    pub fn publish(&mut self) -> Result<(), String> {
+        match self.status {
+            EventStatus::Draft => {
+                self.status = EventStatus::Published;
+                self.updated_at = Utc::now();
+                Ok(())
+            }
+            _ => Err("Only draft events can be published.".to_string()),
+        }
     }
 
-    pub fn cancel(&mut self) {
-        self.status = EventStatus::Cancelled;
-        self.updated_at = Utc::now();
+    pub fn cancel(&mut self) -> Result<(), String> {
+        match self.status {
+            EventStatus::Draft | EventStatus::Published => {
+                self.status = EventStatus::Cancelled;
+                self.updated_at = Utc::now();
+                Ok(())
+            }
+            _ => Err("Only draft or published events can be cancelled.".to_string()),
+        }
     }
 
-    pub fn complete(&mut self) {
-        self.status = EventStatus::Completed;
-        self.updated_at = Utc::now();
+    pub fn complete(&mut self) -> Result<(), String> {
+        match self.status {
+            EventStatus::Published => {
+                self.status = EventStatus::Completed;
+                self.updated_at = Utc::now();
+                Ok(())
+            }
+            _ => Err("Only published events can be completed.".to_string()),
+        }
+    }
+
+    /// Cancelled and completed events are frozen.
+    pub fn is_editable(&self) -> bool {
+        matches!(self.status, EventStatus::Draft | EventStatus::Published)
+    }
+
+    /// Tickets already issued, which capacity can never drop below.
+    pub fn sold(&self) -> i64 {
+        self.capacity - self.available.unwrap_or(0)
+    }
+
+    pub fn is_owned_by(&self, user_id: i32) -> bool {
+        self.creator_id == Some(user_id)
     }
 
     pub fn update_title(&mut self, new_title: String) {
@@ -99,20 +153,33 @@ impl Event {
         self.updated_at = Utc::now();
     }
 
-    pub fn update_capacity(&mut self, capacity: Option<u32>) {
+    pub fn update_address(&mut self, address: String) {
+        self.address = address;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn update_image_url(&mut self, image_url: Option<String>) {
+        self.image_url = image_url;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn set_private(&mut self, is_private: bool) {
+        self.is_private = is_private;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn update_capacity(&mut self, capacity: i64) {
         self.capacity = capacity;
         self.updated_at = Utc::now();
     }
 
-    pub fn add_category(&mut self, category: String) {
-        if !self.categories.contains(&category) {
-            self.categories.push(category);
-            self.updated_at = Utc::now();
-        }
+    pub fn update_price(&mut self, price: Money) {
+        self.price = price;
+        self.updated_at = Utc::now();
     }
 
-    pub fn remove_category(&mut self, category: &str) {
-        self.categories.retain(|c| c != category);
+    pub fn update_category(&mut self, category: EventCategory) {
+        self.category = category;
         self.updated_at = Utc::now();
     }
 
@@ -167,6 +234,18 @@ pub mod validation {
         if event.start_time >= event.end_time {
             return Err("Start time must be before end time".to_string());
         }
+        if event.capacity <= 0 {
+            return Err("Event capacity must be greater than 0".to_string());
+        }
+        if event.price.is_negative() {
+            return Err("Ticket price cannot be negative".to_string());
+        }
+        if event.capacity < event.sold() {
+            return Err(format!(
+                "Capacity cannot be lower than the {} tickets already sold",
+                event.sold()
+            ));
+        }
         Ok(())
     }
 }
@@ -187,6 +266,247 @@ pub mod search {
     }
 
     pub fn filter_upcoming(events: &[Event]) -> Vec<Event> {
-        events.iter().cloned().filter(|e| e.is_upcoming()).collect()
+        events.iter().filter(|e| e.is_upcoming()).cloned().collect()
+    }
+}
+
+pub mod store {
+    use super::*;
+    use crate::money::Currency;
+    use sqlx::{PgPool, Postgres, Transaction};
+
+    /// `events` row as stored; the price is split across two columns.
+    struct EventRow {
+        id: i32,
+        title: String,
+        description: Option<String>,
+        location: String,
+        address: String,
+        category: EventCategory,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        capacity: i64,
+        available: Option<i64>,
+        price_cents: i64,
+        currency: Currency,
+        is_private: bool,
+        creator_id: Option<i32>,
+        created_at: DateTime<Utc>,
+        updated_at: DateTime<Utc>,
+        status: EventStatus,
+        tags: Vec<String>,
+        image_url: Option<String>,
+    }
+
+    impl From<EventRow> for Event {
+        fn from(row: EventRow) -> Self {
+            Event {
+                id: row.id,
+                title: row.title,
+                description: row.description,
+                location: row.location,
+                address: row.address,
+                category: row.category,
+                start_time: row.start_time,
+                end_time: row.end_time,
+                capacity: row.capacity,
+                available: row.available,
+                price: Money::new(row.price_cents, row.currency),
+                is_private: row.is_private,
+                creator_id: row.creator_id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                status: row.status,
+                tags: row.tags,
+                image_url: row.image_url,
+            }
+        }
+    }
+
+    pub async fn fetch<'e, E>(executor: E, event_id: i32) -> Result<Event, sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            EventRow,
+            r#"
+            SELECT
+                id,
+                name as title,
+                description,
+                location,
+                address,
+                category as "category: EventCategory",
+                start_time,
+                end_time,
+                capacity,
+                available,
+                price_cents,
+                currency as "currency: Currency",
+                is_private,
+                creator_id,
+                created_at,
+                updated_at,
+                status as "status: EventStatus",
+                tags,
+                card_image_url as image_url
+            FROM events
+            WHERE id = $1
+            "#,
+            event_id
+        )
+        .fetch_one(executor)
+        .await
+        .map(Event::from)
+    }
+
+    /// Locks and loads an event. Edits and lifecycle changes both go
+    /// through here, so one cannot write back a row the other has changed.
+    pub async fn lock(
+        tx: &mut Transaction<'_, Postgres>,
+        event_id: i32,
+    ) -> Result<Event, sqlx::Error> {
+        sqlx::query!("SELECT id FROM events WHERE id = $1 FOR UPDATE", event_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        fetch(&mut *tx, event_id).await
+    }
+
+    /// Events visible in public listings: public events that left draft.
+    pub async fn fetch_listed(pg_pool: &PgPool) -> Result<Vec<Event>, sqlx::Error> {
+        sqlx::query_as!(
+            EventRow,
+            r#"
+            SELECT
+                id,
+                name as title,
+                description,
+                location,
+                address,
+                category as "category: EventCategory",
+                start_time,
+                end_time,
+                capacity,
+                available,
+                price_cents,
+                currency as "currency: Currency",
+                is_private,
+                creator_id,
+                created_at,
+                updated_at,
+                status as "status: EventStatus",
+                tags,
+                card_image_url as image_url
+            FROM events
+            WHERE status <> 'Draft' AND NOT is_private
+            ORDER BY start_time
+            "#
+        )
+        .fetch_all(pg_pool)
+        .await
+        .map(|rows| rows.into_iter().map(Event::from).collect())
+    }
+
+    /// Inserts a new event and returns it with its assigned id.
+    pub async fn insert(pg_pool: &PgPool, event: &Event) -> Result<Event, sqlx::Error> {
+        let event_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO events (
+                name, description, location, address, category, start_time, end_time,
+                capacity, available, price_cents, currency, is_private, creator_id, status,
+                tags, card_image_url, created_at, updated_at
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18
+            )
+            RETURNING id
+            "#,
+            event.title,
+            event.description,
+            event.location,
+            event.address,
+            event.category as EventCategory,
+            event.start_time,
+            event.end_time,
+            event.capacity,
+            event.available,
+            event.price.cents,
+            event.price.currency as Currency,
+            event.is_private,
+            event.creator_id,
+            event.status as EventStatus,
+            &event.tags,
+            event.image_url,
+            event.created_at,
+            event.updated_at
+        )
+        .fetch_one(pg_pool)
+        .await?;
+
+        fetch(pg_pool, event_id).await
+    }
+
+    /// Persists the editable fields of an event. Its status only changes
+    /// through `set_status`. `available` is owned by the purchase path, so a
+    /// capacity change is applied to it as a delta rather than overwritten.
+    pub async fn update<'e, E>(executor: E, event: &Event) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query!(
+            r#"
+            UPDATE events
+            SET name = $2,
+                description = $3,
+                location = $4,
+                address = $5,
+                category = $6,
+                start_time = $7,
+                end_time = $8,
+                available = available + ($9 - capacity),
+                capacity = $9,
+                price_cents = $10,
+                currency = $11,
+                is_private = $12,
+                tags = $13,
+                card_image_url = $14,
+                updated_at = $15
+            WHERE id = $1
+            "#,
+            event.id,
+            event.title,
+            event.description,
+            event.location,
+            event.address,
+            event.category as EventCategory,
+            event.start_time,
+            event.end_time,
+            event.capacity,
+            event.price.cents,
+            event.price.currency as Currency,
+            event.is_private,
+            &event.tags,
+            event.image_url,
+            event.updated_at
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// Persists a lifecycle change made by `publish`, `cancel` or `complete`.
+    pub async fn set_status<'e, E>(executor: E, event: &Event) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query!(
+            "UPDATE events SET status = $2, updated_at = $3 WHERE id = $1",
+            event.id,
+            event.status as EventStatus,
+            event.updated_at
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
     }
 }
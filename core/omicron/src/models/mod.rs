@@ -0,0 +1,4 @@
+pub mod event;
+pub mod order;
+pub mod ticket;
+pub mod tier;
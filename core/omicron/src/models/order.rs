@@ -0,0 +1,351 @@
+use crate::money::{Money, MoneyError};
+use crate::payments::PaymentStatus;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Tickets bought together in one purchase, what they cost and how they
+/// were paid for.
+#[derive(Debug, Clone, Serialize)]
+pub struct Order {
+    pub id: i32,
+    pub user_id: i32,
+    pub event_id: i32,
+    pub event_name: String,
+    /// The hold the order was paid from.
+    pub hold_id: Uuid,
+    pub items: Vec<OrderItem>,
+    /// The items at list price.
+    pub subtotal: Money,
+    pub discount: Money,
+    pub fees: Money,
+    /// What the buyer was charged: subtotal less discount plus fees.
+    pub total: Money,
+    /// What has since been refunded.
+    pub refunded: Money,
+    pub promo_code: Option<String>,
+    pub payment_id: Option<i32>,
+    /// `None` for orders that cost nothing.
+    pub payment_status: Option<PaymentStatus>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One line of an order: tickets of one tier at one price.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderItem {
+    /// `None` for events that do not sell tiers.
+    pub tier_id: Option<i32>,
+    pub description: String,
+    pub quantity: i64,
+    /// Price of one ticket before discounts.
+    pub unit_price: Money,
+    pub subtotal: Money,
+    pub ticket_ids: Vec<i32>,
+}
+
+/// Describes items of events that do not sell tiers.
+pub const GENERAL_ADMISSION: &str = "General admission";
+
+impl OrderItem {
+    pub fn new(
+        tier_id: Option<i32>,
+        description: String,
+        unit_price: Money,
+        ticket_ids: Vec<i32>,
+    ) -> Result<Self, MoneyError> {
+        let quantity = ticket_ids.len() as i64;
+        Ok(OrderItem {
+            tier_id,
+            description,
+            quantity,
+            unit_price,
+            subtotal: unit_price.checked_mul(quantity)?,
+            ticket_ids,
+        })
+    }
+}
+
+impl Order {
+    /// Builds an unsaved order and works out its totals; `id` is assigned
+    /// by `store::insert`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        user_id: i32,
+        event_id: i32,
+        event_name: String,
+        hold_id: Uuid,
+        items: Vec<OrderItem>,
+        discount: Money,
+        fees: Money,
+        promo_code: Option<String>,
+        payment_id: Option<i32>,
+    ) -> Result<Self, MoneyError> {
+        let currency = discount.currency;
+        let subtotal = Money::checked_sum(currency, items.iter().map(|item| item.subtotal))?;
+        let total = subtotal.checked_sub(discount)?.checked_add(fees)?;
+        let now = Utc::now();
+        Ok(Order {
+            id: 0,
+            user_id,
+            event_id,
+            event_name,
+            hold_id,
+            items,
+            subtotal,
+            discount,
+            fees,
+            total,
+            refunded: Money::zero(currency),
+            promo_code,
+            payment_id,
+            payment_status: payment_id.map(|_| PaymentStatus::Succeeded),
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    pub fn ticket_ids(&self) -> Vec<i32> {
+        self.items
+            .iter()
+            .flat_map(|item| item.ticket_ids.iter().copied())
+            .collect()
+    }
+}
+
+pub mod store {
+    use super::*;
+    use crate::money::Currency;
+    use sqlx::{PgPool, Postgres, Transaction};
+    use std::collections::HashMap;
+
+    /// `orders` row with its event, payment, promo code and refunds.
+    struct OrderRow {
+        id: i32,
+        user_id: i32,
+        event_id: i32,
+        event_name: String,
+        hold_id: Uuid,
+        payment_id: Option<i32>,
+        payment_status: Option<PaymentStatus>,
+        promo_code: Option<String>,
+        subtotal_cents: i64,
+        discount_cents: i64,
+        fees_cents: i64,
+        total_cents: i64,
+        refunded_cents: i64,
+        currency: Currency,
+        created_at: DateTime<Utc>,
+        updated_at: DateTime<Utc>,
+    }
+
+    struct ItemRow {
+        order_id: i32,
+        tier_id: Option<i32>,
+        description: String,
+        quantity: i64,
+        unit_price_cents: i64,
+        currency: Currency,
+        ticket_ids: Vec<i32>,
+    }
+
+    impl From<ItemRow> for OrderItem {
+        fn from(row: ItemRow) -> Self {
+            let unit_price = Money::new(row.unit_price_cents, row.currency);
+            OrderItem {
+                tier_id: row.tier_id,
+                description: row.description,
+                quantity: row.quantity,
+                unit_price,
+                subtotal: Money::new(row.unit_price_cents * row.quantity, row.currency),
+                ticket_ids: row.ticket_ids,
+            }
+        }
+    }
+
+    fn order(row: OrderRow, items: Vec<OrderItem>) -> Order {
+        let money = |cents| Money::new(cents, row.currency);
+        Order {
+            id: row.id,
+            user_id: row.user_id,
+            event_id: row.event_id,
+            event_name: row.event_name,
+            hold_id: row.hold_id,
+            items,
+            subtotal: money(row.subtotal_cents),
+            discount: money(row.discount_cents),
+            fees: money(row.fees_cents),
+            total: money(row.total_cents),
+            refunded: money(row.refunded_cents),
+            promo_code: row.promo_code,
+            payment_id: row.payment_id,
+            payment_status: row.payment_status,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+
+    /// Attaches their items to `rows`, keeping the rows' order.
+    async fn with_items(pg_pool: &PgPool, rows: Vec<OrderRow>) -> Result<Vec<Order>, sqlx::Error> {
+        let order_ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+        let items = sqlx::query_as!(
+            ItemRow,
+            r#"
+            SELECT
+                i.order_id, i.tier_id, i.description, i.quantity, i.unit_price_cents,
+                i.currency as "currency: Currency",
+                ARRAY(
+                    SELECT t.id FROM tickets t
+                    WHERE t.order_id = i.order_id AND t.tier_id IS NOT DISTINCT FROM i.tier_id
+                    ORDER BY t.id
+                ) as "ticket_ids!"
+            FROM order_items i
+            WHERE i.order_id = ANY($1)
+            ORDER BY i.id
+            "#,
+            &order_ids
+        )
+        .fetch_all(pg_pool)
+        .await?;
+
+        let mut by_order: HashMap<i32, Vec<OrderItem>> = HashMap::new();
+        for item in items {
+            by_order
+                .entry(item.order_id)
+                .or_default()
+                .push(OrderItem::from(item));
+        }
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let items = by_order.remove(&row.id).unwrap_or_default();
+                order(row, items)
+            })
+            .collect())
+    }
+
+    pub async fn fetch(pg_pool: &PgPool, order_id: i32) -> Result<Order, sqlx::Error> {
+        let row = sqlx::query_as!(
+            OrderRow,
+            r#"
+            SELECT
+                o.id, o.user_id, o.event_id, e.name as event_name, o.hold_id, o.payment_id,
+                p.status as "payment_status?: PaymentStatus",
+                pc.code as "promo_code?",
+                o.subtotal_cents, o.discount_cents, o.fees_cents, o.total_cents,
+                COALESCE((
+                    SELECT SUM(r.amount_cents) FROM refunds r
+                    WHERE r.id IN (SELECT t.refund_id FROM tickets t WHERE t.order_id = o.id)
+                ), 0)::BIGINT as "refunded_cents!",
+                o.currency as "currency: Currency",
+                o.created_at, o.updated_at
+            FROM orders o
+            JOIN events e ON e.id = o.event_id
+            LEFT JOIN payments p ON p.id = o.payment_id
+            LEFT JOIN promo_codes pc ON pc.id = o.promo_code_id
+            WHERE o.id = $1
+            "#,
+            order_id
+        )
+        .fetch_one(pg_pool)
+        .await?;
+
+        let mut orders = with_items(pg_pool, vec![row]).await?;
+        Ok(orders.remove(0))
+    }
+
+    /// Every order `user_id` has placed, newest first.
+    pub async fn fetch_for_user(pg_pool: &PgPool, user_id: i32) -> Result<Vec<Order>, sqlx::Error> {
+        let rows = sqlx::query_as!(
+            OrderRow,
+            r#"
+            SELECT
+                o.id, o.user_id, o.event_id, e.name as event_name, o.hold_id, o.payment_id,
+                p.status as "payment_status?: PaymentStatus",
+                pc.code as "promo_code?",
+                o.subtotal_cents, o.discount_cents, o.fees_cents, o.total_cents,
+                COALESCE((
+                    SELECT SUM(r.amount_cents) FROM refunds r
+                    WHERE r.id IN (SELECT t.refund_id FROM tickets t WHERE t.order_id = o.id)
+                ), 0)::BIGINT as "refunded_cents!",
+                o.currency as "currency: Currency",
+                o.created_at, o.updated_at
+            FROM orders o
+            JOIN events e ON e.id = o.event_id
+            LEFT JOIN payments p ON p.id = o.payment_id
+            LEFT JOIN promo_codes pc ON pc.id = o.promo_code_id
+            WHERE o.user_id = $1
+            ORDER BY o.created_at DESC, o.id DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pg_pool)
+        .await?;
+
+        with_items(pg_pool, rows).await
+    }
+
+    /// Inserts a new order with its items, links its tickets to it and
+    /// returns its id. The promo code is the one redeemed on its hold.
+    pub async fn insert(
+        tx: &mut Transaction<'_, Postgres>,
+        order: &Order,
+    ) -> Result<i32, sqlx::Error> {
+        let order_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO orders (
+                user_id, event_id, hold_id, payment_id, promo_code_id, subtotal_cents,
+                discount_cents, fees_cents, total_cents, currency, created_at, updated_at
+            )
+            VALUES (
+                $1, $2, $3, $4,
+                (SELECT promo_code_id FROM promo_redemptions WHERE hold_id = $3),
+                $5, $6, $7, $8, $9, $10, $11
+            )
+            RETURNING id
+            "#,
+            order.user_id,
+            order.event_id,
+            order.hold_id,
+            order.payment_id,
+            order.subtotal.cents,
+            order.discount.cents,
+            order.fees.cents,
+            order.total.cents,
+            order.total.currency as Currency,
+            order.created_at,
+            order.updated_at
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for item in &order.items {
+            sqlx::query!(
+                r#"
+                INSERT INTO order_items (
+                    order_id, tier_id, description, quantity, unit_price_cents, currency
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+                order_id,
+                item.tier_id,
+                item.description,
+                item.quantity,
+                item.unit_price.cents,
+                item.unit_price.currency as Currency
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE tickets SET order_id = $2 WHERE id = ANY($1)",
+            &order.ticket_ids(),
+            order_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(order_id)
+    }
+}
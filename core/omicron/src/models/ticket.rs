@@ -1,8 +1,10 @@
+use crate::money::Money;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "ticket_status")]
 pub enum TicketStatus {
     Available,
     Reserved,
@@ -11,16 +13,26 @@ pub enum TicketStatus {
     CheckedIn,
 }
 
+impl TicketStatus {
+    /// The status after admitting this ticket at the door.
+    pub fn check_in(self) -> Result<TicketStatus, String> {
+        match self {
+            TicketStatus::Sold => Ok(TicketStatus::CheckedIn),
+            TicketStatus::CheckedIn => Err("Ticket has already been checked in.".to_string()),
+            _ => Err("Only sold tickets can be checked in.".to_string()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ticket {
     pub id: Uuid,
     pub event_id: Uuid,
     pub user_id: Option<Uuid>,
-    pub price_cents: u32,
-    pub currency: String,
+    pub price: Money,
     pub status: TicketStatus,
     pub seat_number: Option<String>,
-    pub tier: Option<String>,
+    pub tier_id: Option<i32>,
     pub issued_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub metadata: Option<String>,
@@ -29,9 +41,8 @@ pub struct Ticket {
 impl Ticket {
     pub fn new(
         event_id: Uuid,
-        price_cents: u32,
-        currency: String,
-        tier: Option<String>,
+        price: Money,
+        tier_id: Option<i32>,
         seat_number: Option<String>,
         metadata: Option<String>,
     ) -> Self {
@@ -40,11 +51,10 @@ impl Ticket {
             id: Uuid::new_v4(),
             event_id,
             user_id: None,
-            price_cents,
-            currency,
+            price,
             status: TicketStatus::Available,
             seat_number,
-            tier,
+            tier_id,
             issued_at: now,
             updated_at: now,
             metadata,
@@ -81,13 +91,9 @@ impl Ticket {
     }
 
     pub fn check_in(&mut self) -> Result<(), String> {
-        if self.status == TicketStatus::Sold {
-            self.status = TicketStatus::CheckedIn;
-            self.updated_at = Utc::now();
-            Ok(())
-        } else {
-            Err("Only sold tickets can be checked in.".to_string())
-        }
+        self.status = self.status.check_in()?;
+        self.updated_at = Utc::now();
+        Ok(())
     }
 
     pub fn update_seat(&mut self, new_seat: Option<String>) {
@@ -95,15 +101,15 @@ impl Ticket {
         self.updated_at = Utc::now();
     }
 
-    pub fn update_tier(&mut self, new_tier: Option<String>) {
-        self.tier = new_tier;
+    pub fn update_tier(&mut self, new_tier_id: Option<i32>) {
+        self.tier_id = new_tier_id;
         self.updated_at = Utc::now();
     }
 
     pub fn summary(&self) -> String {
         format!(
-            "Ticket ID: {}, Event: {}, Status: {:?}, Price: {} {}",
-            self.id, self.event_id, self.status, self.price_cents, self.currency
+            "Ticket ID: {}, Event: {}, Status: {:?}, Price: {}",
+            self.id, self.event_id, self.status, self.price
         )
     }
 }
@@ -114,24 +120,24 @@ pub mod ticket_filters {
     pub fn available_tickets(tickets: &[Ticket]) -> Vec<Ticket> {
         tickets
             .iter()
-            .cloned()
             .filter(|t| t.status == TicketStatus::Available)
+            .cloned()
             .collect()
     }
 
     pub fn user_tickets(tickets: &[Ticket], user_id: Uuid) -> Vec<Ticket> {
         tickets
             .iter()
-            .cloned()
             .filter(|t| t.user_id == Some(user_id))
+            .cloned()
             .collect()
     }
 
     pub fn tickets_for_event(tickets: &[Ticket], event_id: Uuid) -> Vec<Ticket> {
         tickets
             .iter()
-            .cloned()
             .filter(|t| t.event_id == event_id)
+            .cloned()
             .collect()
     }
 }
@@ -140,12 +146,9 @@ pub mod validation {
     use super::*;
 
     pub fn validate_ticket(ticket: &Ticket) -> Result<(), String> {
-        if ticket.price_cents == 0 {
+        if ticket.price.cents <= 0 {
             return Err("Ticket price must be greater than 0.".to_string());
         }
-        if ticket.currency.trim().is_empty() {
-            return Err("Currency cannot be empty.".to_string());
-        }
         Ok(())
     }
 }
@@ -0,0 +1,400 @@
+use crate::money::Money;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A kind of ticket an organizer sells for an event, e.g. "Early Bird" or
+/// "Backstage", with its own price, allocation and sale window. Tiers
+/// share the event's capacity: their quantities never add up to more.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TicketTier {
+    pub id: i32,
+    pub event_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub price: Money,
+    pub quantity: i64,
+    pub available: i64,
+    pub sales_start: Option<DateTime<Utc>>,
+    pub sales_end: Option<DateTime<Utc>>,
+    pub min_per_order: i32,
+    pub max_per_order: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TicketTier {
+    /// Builds an unsaved tier; `id` is assigned by `store::insert`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        event_id: i32,
+        name: String,
+        description: Option<String>,
+        price: Money,
+        quantity: i64,
+        sales_start: Option<DateTime<Utc>>,
+        sales_end: Option<DateTime<Utc>>,
+        min_per_order: i32,
+        max_per_order: Option<i32>,
+    ) -> Self {
+        let now = Utc::now();
+        TicketTier {
+            id: 0,
+            event_id,
+            name,
+            description,
+            price,
+            quantity,
+            available: quantity,
+            sales_start,
+            sales_end,
+            min_per_order,
+            max_per_order,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Tickets already issued, which quantity can never drop below.
+    pub fn sold(&self) -> i64 {
+        self.quantity - self.available
+    }
+
+    pub fn is_on_sale(&self, now: DateTime<Utc>) -> bool {
+        self.check_sale_window(now).is_ok()
+    }
+
+    pub fn check_sale_window(&self, now: DateTime<Utc>) -> Result<(), String> {
+        if let Some(start) = self.sales_start {
+            if now < start {
+                return Err(format!("Sales for {} open at {}", self.name, start));
+            }
+        }
+        if let Some(end) = self.sales_end {
+            if now >= end {
+                return Err(format!("Sales for {} closed at {}", self.name, end));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether one order may contain `quantity` tickets of this tier.
+    pub fn check_order_size(&self, quantity: i64) -> Result<(), String> {
+        if quantity < i64::from(self.min_per_order) {
+            return Err(format!(
+                "{} tickets must be bought at least {} at a time",
+                self.name, self.min_per_order
+            ));
+        }
+        if let Some(max) = self.max_per_order {
+            if quantity > i64::from(max) {
+                return Err(format!(
+                    "At most {} {} tickets can be bought per order",
+                    max, self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn update_name(&mut self, name: String) {
+        self.name = name;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn update_description(&mut self, description: Option<String>) {
+        self.description = description;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn update_price(&mut self, price: Money) {
+        self.price = price;
+        self.updated_at = Utc::now();
+    }
+
+    /// Changes the allocation; tickets already issued stay issued.
+    pub fn update_quantity(&mut self, quantity: i64) {
+        self.available += quantity - self.quantity;
+        self.quantity = quantity;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn update_sale_window(
+        &mut self,
+        sales_start: Option<DateTime<Utc>>,
+        sales_end: Option<DateTime<Utc>>,
+    ) {
+        self.sales_start = sales_start;
+        self.sales_end = sales_end;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn update_order_limits(&mut self, min_per_order: i32, max_per_order: Option<i32>) {
+        self.min_per_order = min_per_order;
+        self.max_per_order = max_per_order;
+        self.updated_at = Utc::now();
+    }
+}
+
+pub mod validation {
+    use super::*;
+
+    pub const MAX_NAME_LENGTH: usize = 64;
+
+    pub fn validate_tier(tier: &TicketTier) -> Result<(), String> {
+        if tier.name.trim().is_empty() || tier.name.len() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "Tier name must be between 1 and {} characters",
+                MAX_NAME_LENGTH
+            ));
+        }
+        if tier.price.is_negative() {
+            return Err("Tier price cannot be negative".to_string());
+        }
+        if tier.quantity < 0 {
+            return Err("Tier quantity cannot be negative".to_string());
+        }
+        if tier.available < 0 {
+            return Err(format!(
+                "Quantity cannot be lower than the {} tickets already sold",
+                tier.sold()
+            ));
+        }
+        if let (Some(start), Some(end)) = (tier.sales_start, tier.sales_end) {
+            if start >= end {
+                return Err("Sales must start before they end".to_string());
+            }
+        }
+        if tier.min_per_order < 1 {
+            return Err("Orders must allow at least 1 ticket".to_string());
+        }
+        if tier
+            .max_per_order
+            .is_some_and(|max| max < tier.min_per_order)
+        {
+            return Err("max_per_order cannot be lower than min_per_order".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub mod store {
+    use super::*;
+    use crate::money::Currency;
+    use sqlx::{PgPool, Postgres};
+
+    /// `ticket_tiers` row as stored; the price is split across two columns.
+    struct TierRow {
+        id: i32,
+        event_id: i32,
+        name: String,
+        description: Option<String>,
+        price_cents: i64,
+        currency: Currency,
+        quantity: i64,
+        available: i64,
+        sales_start: Option<DateTime<Utc>>,
+        sales_end: Option<DateTime<Utc>>,
+        min_per_order: i32,
+        max_per_order: Option<i32>,
+        created_at: DateTime<Utc>,
+        updated_at: DateTime<Utc>,
+    }
+
+    impl From<TierRow> for TicketTier {
+        fn from(row: TierRow) -> Self {
+            TicketTier {
+                id: row.id,
+                event_id: row.event_id,
+                name: row.name,
+                description: row.description,
+                price: Money::new(row.price_cents, row.currency),
+                quantity: row.quantity,
+                available: row.available,
+                sales_start: row.sales_start,
+                sales_end: row.sales_end,
+                min_per_order: row.min_per_order,
+                max_per_order: row.max_per_order,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            }
+        }
+    }
+
+    pub async fn fetch<'e, E>(executor: E, tier_id: i32) -> Result<TicketTier, sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            TierRow,
+            r#"
+            SELECT
+                id, event_id, name, description, price_cents,
+                currency as "currency: Currency",
+                quantity, available, sales_start, sales_end, min_per_order, max_per_order,
+                created_at, updated_at
+            FROM ticket_tiers
+            WHERE id = $1
+            "#,
+            tier_id
+        )
+        .fetch_one(executor)
+        .await
+        .map(TicketTier::from)
+    }
+
+    /// Locks and loads a tier of `event_id`. Callers must already hold the
+    /// event's row lock, which is always taken first.
+    pub async fn lock<'e, E>(
+        executor: E,
+        event_id: i32,
+        tier_id: i32,
+    ) -> Result<Option<TicketTier>, sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            TierRow,
+            r#"
+            SELECT
+                id, event_id, name, description, price_cents,
+                currency as "currency: Currency",
+                quantity, available, sales_start, sales_end, min_per_order, max_per_order,
+                created_at, updated_at
+            FROM ticket_tiers
+            WHERE id = $1 AND event_id = $2
+            FOR UPDATE
+            "#,
+            tier_id,
+            event_id
+        )
+        .fetch_optional(executor)
+        .await
+        .map(|row| row.map(TicketTier::from))
+    }
+
+    pub async fn fetch_for_event(
+        pg_pool: &PgPool,
+        event_id: i32,
+    ) -> Result<Vec<TicketTier>, sqlx::Error> {
+        sqlx::query_as!(
+            TierRow,
+            r#"
+            SELECT
+                id, event_id, name, description, price_cents,
+                currency as "currency: Currency",
+                quantity, available, sales_start, sales_end, min_per_order, max_per_order,
+                created_at, updated_at
+            FROM ticket_tiers
+            WHERE event_id = $1
+            ORDER BY price_cents, id
+            "#,
+            event_id
+        )
+        .fetch_all(pg_pool)
+        .await
+        .map(|rows| rows.into_iter().map(TicketTier::from).collect())
+    }
+
+    /// Tickets allocated to the event's tiers, optionally leaving one out.
+    pub async fn allocated<'e, E>(
+        executor: E,
+        event_id: i32,
+        excluding: Option<i32>,
+    ) -> Result<i64, sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(SUM(quantity), 0)::BIGINT as "allocated!"
+            FROM ticket_tiers
+            WHERE event_id = $1 AND id IS DISTINCT FROM $2
+            "#,
+            event_id,
+            excluding
+        )
+        .fetch_one(executor)
+        .await
+    }
+
+    /// Inserts a new tier and returns its id.
+    pub async fn insert<'e, E>(executor: E, tier: &TicketTier) -> Result<i32, sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+            INSERT INTO ticket_tiers (
+                event_id, name, description, price_cents, currency, quantity, available,
+                sales_start, sales_end, min_per_order, max_per_order, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING id
+            "#,
+            tier.event_id,
+            tier.name,
+            tier.description,
+            tier.price.cents,
+            tier.price.currency as Currency,
+            tier.quantity,
+            tier.available,
+            tier.sales_start,
+            tier.sales_end,
+            tier.min_per_order,
+            tier.max_per_order,
+            tier.created_at,
+            tier.updated_at
+        )
+        .fetch_one(executor)
+        .await
+    }
+
+    /// Persists the editable fields of a tier. Like an event's capacity, a
+    /// quantity change is applied to `available` as a delta.
+    pub async fn update<'e, E>(executor: E, tier: &TicketTier) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query!(
+            r#"
+            UPDATE ticket_tiers
+            SET name = $2,
+                description = $3,
+                price_cents = $4,
+                currency = $5,
+                available = available + ($6 - quantity),
+                quantity = $6,
+                sales_start = $7,
+                sales_end = $8,
+                min_per_order = $9,
+                max_per_order = $10,
+                updated_at = $11
+            WHERE id = $1
+            "#,
+            tier.id,
+            tier.name,
+            tier.description,
+            tier.price.cents,
+            tier.price.currency as Currency,
+            tier.quantity,
+            tier.sales_start,
+            tier.sales_end,
+            tier.min_per_order,
+            tier.max_per_order,
+            tier.updated_at
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete<'e, E>(executor: E, tier_id: i32) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        sqlx::query!("DELETE FROM ticket_tiers WHERE id = $1", tier_id)
+            .execute(executor)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// ISO-4217 currencies we can price events in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "currency")]
+pub enum Currency {
+    USD,
+    EUR,
+    GBP,
+    CAD,
+    AUD,
+    JPY,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::USD => "USD",
+            Currency::EUR => "EUR",
+            Currency::GBP => "GBP",
+            Currency::CAD => "CAD",
+            Currency::AUD => "AUD",
+            Currency::JPY => "JPY",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::USD => "$",
+            Currency::EUR => "€",
+            Currency::GBP => "£",
+            Currency::CAD => "CA$",
+            Currency::AUD => "A$",
+            Currency::JPY => "¥",
+        }
+    }
+
+    /// Digits after the decimal point, e.g. 2 for USD and 0 for JPY.
+    pub fn exponent(&self) -> u32 {
+        match self {
+            Currency::JPY => 0,
+            _ => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    CurrencyMismatch(Currency, Currency),
+    Overflow,
+}
+
+impl std::fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch(a, b) => {
+                write!(f, "Cannot combine amounts in {} and {}", a, b)
+            }
+            MoneyError::Overflow => write!(f, "Amount is too large"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// An amount in a currency's minor unit (cents for USD, yen for JPY).
+///
+/// Serializes as `{"cents": 2500, "currency": "USD", "display": "$25.00"}`;
+/// `display` is ignored when deserializing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Money {
+    pub cents: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(cents: i64, currency: Currency) -> Self {
+        Money { cents, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Money::new(0, currency)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.cents == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.cents < 0
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(&other)?;
+        self.cents
+            .checked_add(other.cents)
+            .map(|cents| Money::new(cents, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(&other)?;
+        self.cents
+            .checked_sub(other.cents)
+            .map(|cents| Money::new(cents, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Price of `quantity` units at this unit price.
+    pub fn checked_mul(self, quantity: i64) -> Result<Money, MoneyError> {
+        self.cents
+            .checked_mul(quantity)
+            .map(|cents| Money::new(cents, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// `basis_points` hundredths of a percent of this amount, rounded half
+    /// away from zero. Used for percentage fees and discounts.
+    pub fn checked_percent(self, basis_points: i64) -> Result<Money, MoneyError> {
+        let scaled = (self.cents as i128)
+            .checked_mul(basis_points as i128)
+            .ok_or(MoneyError::Overflow)?;
+        let rounded = (scaled + scaled.signum() * 5_000) / 10_000;
+        i64::try_from(rounded)
+            .map(|cents| Money::new(cents, self.currency))
+            .map_err(|_| MoneyError::Overflow)
+    }
+
+    /// Adds up `amounts`, all of which must be in `currency`.
+    pub fn checked_sum<I>(currency: Currency, amounts: I) -> Result<Money, MoneyError>
+    where
+        I: IntoIterator<Item = Money>,
+    {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), Money::checked_add)
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            Err(MoneyError::CurrencyMismatch(self.currency, other.currency))
+        }
+    }
+}
+
+/// Formats with the currency symbol and thousands separators, e.g.
+/// `$1,234.50`, `-€3.00` or `¥5,000`.
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scale = 10u64.pow(self.currency.exponent());
+        let abs = self.cents.unsigned_abs();
+        let (units, fraction) = (abs / scale, abs % scale);
+
+        let digits = units.to_string();
+        let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+
+        let sign = if self.is_negative() { "-" } else { "" };
+        write!(f, "{}{}{}", sign, self.currency.symbol(), grouped)?;
+        if self.currency.exponent() > 0 {
+            write!(
+                f,
+                ".{:0width$}",
+                fraction,
+                width = self.currency.exponent() as usize
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct MoneyRepr<'a> {
+    cents: i64,
+    currency: Currency,
+    display: &'a str,
+}
+
+#[derive(Deserialize)]
+struct MoneyInput {
+    cents: i64,
+    currency: Currency,
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MoneyRepr {
+            cents: self.cents,
+            currency: self.currency,
+            display: &self.to_string(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = MoneyInput::deserialize(deserializer)?;
+        Ok(Money::new(input.cents, input.currency))
+    }
+}
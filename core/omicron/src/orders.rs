@@ -0,0 +1,127 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::internal::PurchaseError;
+use crate::models::order::{self, Order, OrderItem, GENERAL_ADMISSION};
+use crate::money::{Currency, Money};
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Records the order for a hold whose tickets, `ticket_ids`, were just
+/// sold to `user_id`, in the same transaction.
+pub(crate) async fn record(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+    hold_id: Uuid,
+    payment_id: Option<i32>,
+    ticket_ids: &[i32],
+) -> Result<Order, PurchaseError> {
+    let lines = sqlx::query!(
+        r#"
+        SELECT
+            t.event_id,
+            e.name as event_name,
+            t.tier_id,
+            tt.name as "tier_name?",
+            t.price_cents,
+            t.currency as "currency: Currency",
+            ARRAY_AGG(t.id ORDER BY t.id) as "ticket_ids!"
+        FROM tickets t
+        JOIN events e ON e.id = t.event_id
+        LEFT JOIN ticket_tiers tt ON tt.id = t.tier_id
+        WHERE t.id = ANY($1)
+        GROUP BY t.event_id, e.name, t.tier_id, tt.name, t.price_cents, t.currency
+        ORDER BY t.tier_id
+        "#,
+        ticket_ids
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let first = lines
+        .first()
+        .ok_or(PurchaseError::HoldUnavailable(hold_id))?;
+    let (event_id, event_name, currency) =
+        (first.event_id, first.event_name.clone(), first.currency);
+
+    let promo = sqlx::query!(
+        r#"
+        SELECT pc.code, r.discount_cents, r.currency as "currency: Currency"
+        FROM promo_redemptions r
+        JOIN promo_codes pc ON pc.id = r.promo_code_id
+        WHERE r.hold_id = $1
+        "#,
+        hold_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let discount = promo.as_ref().map_or(Money::zero(currency), |promo| {
+        Money::new(promo.discount_cents, promo.currency)
+    });
+
+    // Tickets carry what was paid for them. A promo takes the same off
+    // every ticket of a hold, so adding each one's share back gives the
+    // list price.
+    let discount_per_ticket = discount.cents / ticket_ids.len() as i64;
+    let items = lines
+        .into_iter()
+        .map(|line| {
+            OrderItem::new(
+                line.tier_id,
+                line.tier_name
+                    .unwrap_or_else(|| GENERAL_ADMISSION.to_string()),
+                Money::new(line.price_cents + discount_per_ticket, line.currency),
+                line.ticket_ids,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut order = Order::new(
+        user_id,
+        event_id,
+        event_name,
+        hold_id,
+        items,
+        discount,
+        // No fees are charged on top of ticket prices yet.
+        Money::zero(currency),
+        promo.map(|promo| promo.code),
+        payment_id,
+    )?;
+    order.id = order::store::insert(tx, &order).await?;
+    Ok(order)
+}
+
+/// Loads an order its buyer asked for. Other users' orders are reported
+/// as not found.
+async fn owned_order(pg_pool: &PgPool, user: &AuthUser, order_id: i32) -> Result<Order, ApiError> {
+    let not_found = || ApiError::NotFound(format!("Order {} not found", order_id));
+    let order = order::store::fetch(pg_pool, order_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => not_found(),
+            e => e.into(),
+        })?;
+    if order.user_id != user.id {
+        return Err(not_found());
+    }
+    Ok(order)
+}
+
+pub async fn my_orders(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(order::store::fetch_for_user(&pg_pool, user.id).await?))
+}
+
+pub async fn get_order(
+    user: AuthUser,
+    Path(order_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(owned_order(&pg_pool, &user, order_id).await?))
+}
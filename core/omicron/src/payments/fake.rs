@@ -0,0 +1,169 @@
+use super::{PaymentError, PaymentIntent, PaymentProvider, PaymentStatus, Refund, WebhookEvent};
+use crate::money::Money;
+use axum::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+struct Charge {
+    intent: PaymentIntent,
+    refunded: Money,
+}
+
+/// In-memory provider for development and tests. Every capture succeeds
+/// unless `fail_captures(true)` has been called, and webhooks are signed
+/// with HMAC-SHA256 over the raw body.
+pub struct FakeProvider {
+    secret: String,
+    fail_captures: AtomicBool,
+    charges: Mutex<HashMap<String, Charge>>,
+}
+
+impl FakeProvider {
+    pub fn new(secret: impl Into<String>) -> Self {
+        FakeProvider {
+            secret: secret.into(),
+            fail_captures: AtomicBool::new(false),
+            charges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Signs webhooks with `PAYMENT_WEBHOOK_SECRET`.
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("PAYMENT_WEBHOOK_SECRET").unwrap_or_else(|_| "secret".into()))
+    }
+
+    /// Makes every following capture get declined, to exercise failure paths.
+    pub fn fail_captures(&self, fail: bool) {
+        self.fail_captures.store(fail, Ordering::SeqCst);
+    }
+
+    /// The signature a real provider would send with `payload`.
+    pub fn sign(&self, payload: &[u8]) -> String {
+        let mut mac = self.mac();
+        mac.update(payload);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    pub fn intent(&self, intent_id: &str) -> Option<PaymentIntent> {
+        let charges = self.charges.lock().expect("fake provider lock poisoned");
+        charges.get(intent_id).map(|charge| charge.intent.clone())
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(self.secret.as_bytes()).expect("HMAC accepts any key length")
+    }
+
+    /// Random so ids stay unique across restarts and provider instances.
+    fn next_id(&self, prefix: &str) -> String {
+        format!("{}_fake_{}", prefix, Uuid::new_v4().simple())
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for FakeProvider {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    async fn create_intent(
+        &self,
+        amount: Money,
+        _reference: &str,
+    ) -> Result<PaymentIntent, PaymentError> {
+        if amount.cents <= 0 {
+            return Err(PaymentError::InvalidAmount(amount));
+        }
+
+        let intent = PaymentIntent {
+            id: self.next_id("pi"),
+            amount,
+            status: PaymentStatus::RequiresCapture,
+        };
+        let mut charges = self.charges.lock().expect("fake provider lock poisoned");
+        charges.insert(
+            intent.id.clone(),
+            Charge {
+                intent: intent.clone(),
+                refunded: Money::zero(amount.currency),
+            },
+        );
+        Ok(intent)
+    }
+
+    async fn capture(&self, intent_id: &str) -> Result<PaymentIntent, PaymentError> {
+        let mut charges = self.charges.lock().expect("fake provider lock poisoned");
+        let charge = charges
+            .get_mut(intent_id)
+            .ok_or_else(|| PaymentError::IntentNotFound(intent_id.to_string()))?;
+
+        if charge.intent.status != PaymentStatus::RequiresCapture {
+            return Err(PaymentError::InvalidState {
+                intent_id: intent_id.to_string(),
+                status: charge.intent.status,
+            });
+        }
+
+        if self.fail_captures.load(Ordering::SeqCst) {
+            charge.intent.status = PaymentStatus::Failed;
+            return Err(PaymentError::Declined("card declined".to_string()));
+        }
+
+        charge.intent.status = PaymentStatus::Succeeded;
+        Ok(charge.intent.clone())
+    }
+
+    async fn refund(&self, intent_id: &str, amount: Money) -> Result<Refund, PaymentError> {
+        let mut charges = self.charges.lock().expect("fake provider lock poisoned");
+        let charge = charges
+            .get_mut(intent_id)
+            .ok_or_else(|| PaymentError::IntentNotFound(intent_id.to_string()))?;
+
+        if charge.intent.status != PaymentStatus::Succeeded {
+            return Err(PaymentError::InvalidState {
+                intent_id: intent_id.to_string(),
+                status: charge.intent.status,
+            });
+        }
+
+        let refunded = charge
+            .refunded
+            .checked_add(amount)
+            .map_err(|_| PaymentError::InvalidAmount(amount))?;
+        if amount.cents <= 0 || refunded.cents > charge.intent.amount.cents {
+            return Err(PaymentError::InvalidAmount(amount));
+        }
+
+        charge.refunded = refunded;
+        if refunded == charge.intent.amount {
+            charge.intent.status = PaymentStatus::Refunded;
+        }
+
+        Ok(Refund {
+            id: self.next_id("re"),
+            intent_id: intent_id.to_string(),
+            amount,
+        })
+    }
+
+    fn verify_webhook(
+        &self,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<WebhookEvent, PaymentError> {
+        let signature = hex::decode(signature).map_err(|_| PaymentError::InvalidSignature)?;
+
+        let mut mac = self.mac();
+        mac.update(payload);
+        mac.verify_slice(&signature)
+            .map_err(|_| PaymentError::InvalidSignature)?;
+
+        serde_json::from_slice(payload)
+            .map_err(|e| PaymentError::Provider(format!("Malformed webhook payload: {}", e)))
+    }
+}
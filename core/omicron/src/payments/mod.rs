@@ -0,0 +1,182 @@
+pub mod fake;
+
+use crate::error::ApiError;
+use crate::money::Money;
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/// Header carrying the provider's signature over a webhook body.
+pub const SIGNATURE_HEADER: &str = "payment-signature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "payment_status")]
+pub enum PaymentStatus {
+    RequiresCapture,
+    Succeeded,
+    Failed,
+    Refunded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentIntent {
+    pub id: String,
+    pub amount: Money,
+    pub status: PaymentStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Refund {
+    pub id: String,
+    pub intent_id: String,
+    pub amount: Money,
+}
+
+/// A provider notification that an intent changed state outside of a call
+/// we made, e.g. a dispute or a refund issued from the provider dashboard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub intent_id: String,
+    pub status: PaymentStatus,
+}
+
+#[derive(Debug)]
+pub enum PaymentError {
+    Declined(String),
+    InvalidAmount(Money),
+    IntentNotFound(String),
+    InvalidState {
+        intent_id: String,
+        status: PaymentStatus,
+    },
+    InvalidSignature,
+    Provider(String),
+}
+
+impl std::fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentError::Declined(reason) => write!(f, "Payment declined: {}", reason),
+            PaymentError::InvalidAmount(amount) => {
+                write!(f, "Invalid payment amount: {}", amount)
+            }
+            PaymentError::IntentNotFound(id) => write!(f, "Payment intent {} not found", id),
+            PaymentError::InvalidState { intent_id, status } => {
+                write!(f, "Payment intent {} is {:?}", intent_id, status)
+            }
+            PaymentError::InvalidSignature => write!(f, "Invalid webhook signature"),
+            PaymentError::Provider(e) => write!(f, "Payment provider error: {}", e),
+        }
+    }
+}
+
+impl From<PaymentError> for ApiError {
+    fn from(e: PaymentError) -> Self {
+        match e {
+            PaymentError::Declined(_) => ApiError::PaymentFailed(e.to_string()),
+            PaymentError::InvalidAmount(_) => ApiError::ValidationFailed(e.to_string()),
+            PaymentError::IntentNotFound(_) => ApiError::NotFound(e.to_string()),
+            PaymentError::InvalidState { .. } => ApiError::Conflict(e.to_string()),
+            PaymentError::InvalidSignature => ApiError::Unauthorized(e.to_string()),
+            PaymentError::Provider(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+/// A card processor. Money moves in two steps: an intent authorizes the
+/// amount and `capture` actually takes it, so inventory can be held in
+/// between and released if the capture fails.
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Stored alongside each payment so webhooks can be matched to it.
+    fn name(&self) -> &'static str;
+
+    async fn create_intent(
+        &self,
+        amount: Money,
+        reference: &str,
+    ) -> Result<PaymentIntent, PaymentError>;
+
+    async fn capture(&self, intent_id: &str) -> Result<PaymentIntent, PaymentError>;
+
+    async fn refund(&self, intent_id: &str, amount: Money) -> Result<Refund, PaymentError>;
+
+    /// Checks `signature` against `payload` and decodes the event it carries.
+    fn verify_webhook(&self, payload: &[u8], signature: &str)
+        -> Result<WebhookEvent, PaymentError>;
+}
+
+static PROVIDER: Lazy<Box<dyn PaymentProvider>> = Lazy::new(|| {
+    let name = std::env::var("PAYMENT_PROVIDER").unwrap_or_else(|_| "fake".into());
+    match name.as_str() {
+        "fake" => Box::new(fake::FakeProvider::from_env()),
+        other => panic!("unsupported PAYMENT_PROVIDER: {}", other),
+    }
+});
+
+/// The provider selected by `PAYMENT_PROVIDER`. Only `fake` exists today.
+pub fn provider() -> &'static dyn PaymentProvider {
+    PROVIDER.as_ref()
+}
+
+/// Records what became of a payment we made.
+pub(crate) async fn set_status(
+    pg_pool: &PgPool,
+    payment_id: i32,
+    status: PaymentStatus,
+    failure_reason: Option<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE payments
+        SET status = $2, failure_reason = $3, updated_at = NOW()
+        WHERE id = $1
+        "#,
+        payment_id,
+        status as PaymentStatus,
+        failure_reason
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Applies provider-side status changes to our payment records.
+pub async fn webhook(
+    State(pg_pool): State<PgPool>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, ApiError> {
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(PaymentError::InvalidSignature)?;
+
+    let provider = provider();
+    let event = provider.verify_webhook(&body, signature)?;
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE payments
+        SET status = $3, updated_at = NOW()
+        WHERE intent_id = $1 AND provider = $2
+        "#,
+        event.intent_id,
+        provider.name(),
+        event.status as PaymentStatus
+    )
+    .execute(&pg_pool)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Err(PaymentError::IntentNotFound(event.intent_id).into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
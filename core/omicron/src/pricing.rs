@@ -0,0 +1,446 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::events::owned_event;
+use crate::internal::PurchaseError;
+use crate::inventory;
+use crate::invitations;
+use crate::models::event::{self, EventStatus};
+use crate::models::tier;
+use crate::money::{Money, MoneyError};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres};
+
+/// How long a quote's price is honoured by `checkout::reserve_quoted`.
+pub const QUOTE_TTL_SECONDS: i64 = 120;
+
+/// Largest surcharge a rule may add, as a multiple of the base price.
+const MAX_ADJUSTMENT_BPS: i32 = 100_000;
+const BPS: i64 = 10_000;
+
+/// Where pricing reads the time from, so rules can be evaluated at any
+/// moment in tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock stuck at one instant.
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "pricing_rule_kind")]
+pub enum PricingRuleKind {
+    EarlyBird,
+    DemandStep,
+    TimeToEvent,
+}
+
+/// When a rule kicks in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Trigger {
+    /// Until `ends_at`.
+    EarlyBird { ends_at: DateTime<Utc> },
+    /// Once fewer than `available_below_bps` of the allocation is left.
+    DemandStep { available_below_bps: i32 },
+    /// From `hours_before_start` hours before the event starts.
+    TimeToEvent { hours_before_start: i32 },
+}
+
+impl Trigger {
+    pub fn kind(&self) -> PricingRuleKind {
+        match self {
+            Trigger::EarlyBird { .. } => PricingRuleKind::EarlyBird,
+            Trigger::DemandStep { .. } => PricingRuleKind::DemandStep,
+            Trigger::TimeToEvent { .. } => PricingRuleKind::TimeToEvent,
+        }
+    }
+
+    fn applies(&self, market: &Market, now: DateTime<Utc>) -> bool {
+        match *self {
+            Trigger::EarlyBird { ends_at } => now < ends_at,
+            Trigger::DemandStep {
+                available_below_bps,
+            } => {
+                market.quantity > 0
+                    && market.available * BPS < market.quantity * i64::from(available_below_bps)
+            }
+            Trigger::TimeToEvent { hours_before_start } => {
+                now >= market.starts_at - Duration::hours(i64::from(hours_before_start))
+            }
+        }
+    }
+
+    /// Orders applicable rules of the same kind so the first is the most
+    /// specific: the shortest early-bird window, the steepest demand step,
+    /// the closest time to the event.
+    fn specificity(&self) -> i64 {
+        match *self {
+            Trigger::EarlyBird { ends_at } => ends_at.timestamp(),
+            Trigger::DemandStep {
+                available_below_bps,
+            } => i64::from(available_below_bps),
+            Trigger::TimeToEvent { hours_before_start } => i64::from(hours_before_start),
+        }
+    }
+}
+
+/// Moves the price of an event's tickets by `adjustment_bps` (negative for
+/// a discount) while `trigger` holds. Rules without a `tier_id` apply to
+/// every tier that has no rule of the same kind of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PricingRule {
+    pub tier_id: Option<i32>,
+    #[serde(flatten)]
+    pub trigger: Trigger,
+    pub adjustment_bps: i32,
+}
+
+impl PricingRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.adjustment_bps == 0
+            || !(-(BPS as i32)..=MAX_ADJUSTMENT_BPS).contains(&self.adjustment_bps)
+        {
+            return Err(format!(
+                "adjustment_bps must be non-zero and between -10000 and {}",
+                MAX_ADJUSTMENT_BPS
+            ));
+        }
+        match self.trigger {
+            Trigger::EarlyBird { .. } => {}
+            Trigger::DemandStep {
+                available_below_bps,
+            } if !(1..=BPS as i32).contains(&available_below_bps) => {
+                return Err("available_below_bps must be between 1 and 10000".to_string())
+            }
+            Trigger::TimeToEvent { hours_before_start } if hours_before_start < 1 => {
+                return Err("hours_before_start must be at least 1".to_string())
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// What the rules look at besides the clock: how much of the tier (or the
+/// event, when it has no tiers) is left and when the event starts.
+#[derive(Debug, Clone, Copy)]
+pub struct Market {
+    pub base_price: Money,
+    pub quantity: i64,
+    pub available: i64,
+    pub starts_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Adjustment {
+    pub kind: PricingRuleKind,
+    pub adjustment_bps: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Price {
+    pub base_price: Money,
+    pub unit_price: Money,
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// Prices one ticket of `tier_id` at `now`. At most one rule of each kind
+/// applies; their adjustments add up and the result never goes below zero.
+pub fn price(
+    rules: &[PricingRule],
+    tier_id: Option<i32>,
+    market: &Market,
+    now: DateTime<Utc>,
+) -> Result<Price, MoneyError> {
+    let mut adjustments = Vec::new();
+
+    for kind in [
+        PricingRuleKind::EarlyBird,
+        PricingRuleKind::DemandStep,
+        PricingRuleKind::TimeToEvent,
+    ] {
+        let of_kind = |tier: Option<i32>| {
+            rules
+                .iter()
+                .filter(move |rule| rule.trigger.kind() == kind && rule.tier_id == tier)
+        };
+        // A tier's own rules replace the event-wide ones of the same kind.
+        let candidates: Vec<&PricingRule> = match tier_id {
+            Some(tier_id) if of_kind(Some(tier_id)).next().is_some() => {
+                of_kind(Some(tier_id)).collect()
+            }
+            _ => of_kind(None).collect(),
+        };
+
+        if let Some(rule) = candidates
+            .into_iter()
+            .filter(|rule| rule.trigger.applies(market, now))
+            .min_by_key(|rule| rule.trigger.specificity())
+        {
+            adjustments.push(Adjustment {
+                kind,
+                adjustment_bps: rule.adjustment_bps,
+            });
+        }
+    }
+
+    let total_bps: i64 = adjustments
+        .iter()
+        .map(|adjustment| i64::from(adjustment.adjustment_bps))
+        .sum();
+    let mut unit_price = market.base_price.checked_percent(BPS + total_bps)?;
+    if unit_price.is_negative() {
+        unit_price = Money::zero(unit_price.currency);
+    }
+
+    Ok(Price {
+        base_price: market.base_price,
+        unit_price,
+        adjustments,
+    })
+}
+
+/// A price offered to a buyer before they reserve, honoured until
+/// `expires_at`. Quotes are only ever built server side; clients cannot
+/// hand one back.
+#[derive(Debug, Clone, Serialize)]
+pub struct Quote {
+    pub event_id: i32,
+    pub tier_id: Option<i32>,
+    #[serde(flatten)]
+    pub price: Price,
+    pub quoted_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Quote {
+    pub fn is_valid_at(&self, now: DateTime<Utc>) -> bool {
+        now < self.expires_at
+    }
+}
+
+pub async fn rules<'e, E>(executor: E, event_id: i32) -> Result<Vec<PricingRule>, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            tier_id,
+            kind as "kind: PricingRuleKind",
+            adjustment_bps,
+            ends_at,
+            available_below_bps,
+            hours_before_start
+        FROM pricing_rules
+        WHERE event_id = $1
+        ORDER BY id
+        "#,
+        event_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    // The table's check constraint guarantees each kind's parameter is set.
+    Ok(rows
+        .into_iter()
+        .map(|row| PricingRule {
+            tier_id: row.tier_id,
+            trigger: match row.kind {
+                PricingRuleKind::EarlyBird => Trigger::EarlyBird {
+                    ends_at: row.ends_at.unwrap_or_default(),
+                },
+                PricingRuleKind::DemandStep => Trigger::DemandStep {
+                    available_below_bps: row.available_below_bps.unwrap_or_default(),
+                },
+                PricingRuleKind::TimeToEvent => Trigger::TimeToEvent {
+                    hours_before_start: row.hours_before_start.unwrap_or_default(),
+                },
+            },
+            adjustment_bps: row.adjustment_bps,
+        })
+        .collect())
+}
+
+/// Quotes one ticket of `event_id` (in `tier_id`, for events that sell
+/// tiers) at the clock's current time.
+pub async fn quote(
+    pg_pool: &PgPool,
+    clock: &dyn Clock,
+    event_id: i32,
+    tier_id: Option<i32>,
+) -> Result<Quote, PurchaseError> {
+    let event = event::store::fetch(pg_pool, event_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => PurchaseError::EventNotFound(event_id),
+            e => e.into(),
+        })?;
+    if event.status != EventStatus::Published {
+        return Err(PurchaseError::EventNotOnSale(event_id));
+    }
+
+    let market = match tier_id {
+        Some(tier_id) => {
+            let tier = tier::store::fetch(pg_pool, tier_id)
+                .await
+                .ok()
+                .filter(|tier| tier.event_id == event_id)
+                .ok_or(PurchaseError::TierNotFound(tier_id))?;
+            Market {
+                base_price: tier.price,
+                quantity: tier.quantity,
+                available: tier.available,
+                starts_at: event.start_time,
+            }
+        }
+        None => {
+            if !tier::store::fetch_for_event(pg_pool, event_id)
+                .await?
+                .is_empty()
+            {
+                return Err(PurchaseError::TierRequired(event_id));
+            }
+            Market {
+                base_price: event.price,
+                quantity: event.capacity,
+                available: event.available.unwrap_or(0),
+                starts_at: event.start_time,
+            }
+        }
+    };
+
+    let now = clock.now();
+    let price = price(&rules(pg_pool, event_id).await?, tier_id, &market, now)?;
+    Ok(Quote {
+        event_id,
+        tier_id,
+        price,
+        quoted_at: now,
+        expires_at: now + Duration::seconds(QUOTE_TTL_SECONDS),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct QuoteQuery {
+    pub tier_id: Option<i32>,
+}
+
+pub async fn get_quote(
+    user: Option<AuthUser>,
+    Path(event_id): Path<i32>,
+    Query(query): Query<QuoteQuery>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    invitations::check_visible(&pg_pool, event_id, user.map(|user| user.id)).await?;
+    let quote = quote(&pg_pool, &SystemClock, event_id, query.tier_id).await?;
+    Ok(Json(quote))
+}
+
+pub async fn get_rules(
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(rules(&pg_pool, event_id).await?))
+}
+
+/// Replaces the event's pricing rules with `req`. Holds already taken keep
+/// the price they were quoted.
+pub async fn set_rules(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<Vec<PricingRule>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = owned_event(&pg_pool, &user, event_id).await?;
+    if !event.is_editable() {
+        return Err(ApiError::Conflict(format!(
+            "Event is {:?} and can no longer be edited",
+            event.status
+        )));
+    }
+    for rule in &req {
+        rule.validate().map_err(ApiError::ValidationFailed)?;
+    }
+
+    let tier_ids: Vec<i32> = req.iter().filter_map(|rule| rule.tier_id).collect();
+    let known = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT id) as "known!"
+        FROM ticket_tiers
+        WHERE event_id = $1 AND id = ANY($2)
+        "#,
+        event_id,
+        &tier_ids
+    )
+    .fetch_one(&pg_pool)
+    .await?;
+    let mut distinct = tier_ids.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if known != distinct.len() as i64 {
+        return Err(ApiError::ValidationFailed(
+            "Rules can only target this event's tiers".to_string(),
+        ));
+    }
+
+    let mut tx = pg_pool.begin().await?;
+    sqlx::query!("SELECT id FROM events WHERE id = $1 FOR UPDATE", event_id)
+        .fetch_one(&mut tx)
+        .await?;
+    sqlx::query!("DELETE FROM pricing_rules WHERE event_id = $1", event_id)
+        .execute(&mut tx)
+        .await?;
+
+    for rule in &req {
+        let (ends_at, available_below_bps, hours_before_start) = match rule.trigger {
+            Trigger::EarlyBird { ends_at } => (Some(ends_at), None, None),
+            Trigger::DemandStep {
+                available_below_bps,
+            } => (None, Some(available_below_bps), None),
+            Trigger::TimeToEvent { hours_before_start } => (None, None, Some(hours_before_start)),
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO pricing_rules (
+                event_id, tier_id, kind, adjustment_bps, ends_at, available_below_bps,
+                hours_before_start
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            event_id,
+            rule.tier_id,
+            rule.trigger.kind() as PricingRuleKind,
+            rule.adjustment_bps,
+            ends_at,
+            available_below_bps,
+            hours_before_start
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    inventory::announce(&mut tx, event_id).await?;
+    tx.commit().await?;
+
+    Ok(Json(rules(&pg_pool, event_id).await?))
+}
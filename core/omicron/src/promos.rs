@@ -0,0 +1,461 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::events::owned_event;
+use crate::internal::PurchaseError;
+use crate::money::{Currency, Money, MoneyError};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const MAX_CODE_LENGTH: usize = 32;
+const FULL_DISCOUNT_BPS: i32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "discount_kind")]
+pub enum DiscountKind {
+    Percent,
+    Fixed,
+}
+
+/// What a promo code takes off each ticket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Discount {
+    Percent { percent_bps: i32 },
+    Fixed { amount: Money },
+}
+
+impl Discount {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Discount::Percent { percent_bps } if !(1..=FULL_DISCOUNT_BPS).contains(percent_bps) => {
+                Err("percent_bps must be between 1 and 10000".to_string())
+            }
+            Discount::Fixed { amount } if amount.cents <= 0 => {
+                Err("A fixed discount must be greater than 0".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The discounted price of one ticket; never below zero.
+    pub fn apply(&self, unit_price: Money) -> Result<Money, MoneyError> {
+        let off = match *self {
+            Discount::Percent { percent_bps } => {
+                unit_price.checked_percent(i64::from(percent_bps))?
+            }
+            Discount::Fixed { amount } => amount,
+        };
+        let discounted = unit_price.checked_sub(off)?;
+        if discounted.is_negative() {
+            return Ok(Money::zero(unit_price.currency));
+        }
+        Ok(discounted)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PromoCode {
+    pub id: i32,
+    pub event_id: i32,
+    pub code: String,
+    #[serde(flatten)]
+    pub discount: Discount,
+    /// Tiers the code works for; empty means all of them.
+    pub tier_ids: Vec<i32>,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub uses: i32,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PromoCode {
+    pub fn applies_to(&self, tier_id: Option<i32>) -> bool {
+        self.tier_ids.is_empty() || tier_id.is_some_and(|tier_id| self.tier_ids.contains(&tier_id))
+    }
+
+    /// Whether the code can still be used at `now` by someone who has
+    /// already used it `used_by_user` times.
+    pub fn check(&self, now: DateTime<Utc>, used_by_user: i64) -> Result<(), String> {
+        if self.revoked_at.is_some() {
+            return Err(format!("Promo code {} has been revoked", self.code));
+        }
+        if self.starts_at.is_some_and(|start| now < start) {
+            return Err(format!("Promo code {} is not active yet", self.code));
+        }
+        if self.ends_at.is_some_and(|end| now >= end) {
+            return Err(format!("Promo code {} has expired", self.code));
+        }
+        if self.max_uses.is_some_and(|max| self.uses >= max) {
+            return Err(format!("Promo code {} has been used up", self.code));
+        }
+        if self
+            .max_uses_per_user
+            .is_some_and(|max| used_by_user >= i64::from(max))
+        {
+            return Err(format!(
+                "You have already used promo code {} as many times as allowed",
+                self.code
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Codes are matched case-insensitively and stored upper-case.
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// `promo_codes` row as stored; the discount is spread across columns.
+struct PromoCodeRow {
+    id: i32,
+    event_id: i32,
+    code: String,
+    kind: DiscountKind,
+    percent_bps: Option<i32>,
+    amount_cents: Option<i64>,
+    currency: Option<Currency>,
+    tier_ids: Vec<i32>,
+    max_uses: Option<i32>,
+    max_uses_per_user: Option<i32>,
+    uses: i32,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<PromoCodeRow> for PromoCode {
+    fn from(row: PromoCodeRow) -> Self {
+        // The table's check constraint guarantees each kind's columns are set.
+        let discount = match row.kind {
+            DiscountKind::Percent => Discount::Percent {
+                percent_bps: row.percent_bps.unwrap_or_default(),
+            },
+            DiscountKind::Fixed => Discount::Fixed {
+                amount: Money::new(
+                    row.amount_cents.unwrap_or_default(),
+                    row.currency.unwrap_or(Currency::USD),
+                ),
+            },
+        };
+        PromoCode {
+            id: row.id,
+            event_id: row.event_id,
+            code: row.code,
+            discount,
+            tier_ids: row.tier_ids,
+            max_uses: row.max_uses,
+            max_uses_per_user: row.max_uses_per_user,
+            uses: row.uses,
+            starts_at: row.starts_at,
+            ends_at: row.ends_at,
+            revoked_at: row.revoked_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// A code applied to a hold: the discounted unit price and what the whole
+/// order saves.
+#[derive(Debug, Clone)]
+pub struct AppliedPromo {
+    pub promo: PromoCode,
+    pub unit_price: Money,
+    pub discount: Money,
+}
+
+/// Locks `code` for `event_id` and works out what it takes off `quantity`
+/// tickets at `unit_price`. The caller must hold the event's row lock and
+/// record the redemption in the same transaction.
+#[allow(clippy::too_many_arguments)]
+pub async fn apply(
+    tx: &mut Transaction<'_, Postgres>,
+    event_id: i32,
+    tier_id: Option<i32>,
+    user_id: i32,
+    code: &str,
+    unit_price: Money,
+    quantity: i64,
+    now: DateTime<Utc>,
+) -> Result<AppliedPromo, PurchaseError> {
+    let code = normalize_code(code);
+    let promo: PromoCode = sqlx::query_as!(
+        PromoCodeRow,
+        r#"
+        SELECT
+            id, event_id, code,
+            kind as "kind: DiscountKind",
+            percent_bps, amount_cents,
+            currency as "currency: Currency",
+            tier_ids, max_uses, max_uses_per_user, uses, starts_at, ends_at, revoked_at,
+            created_at
+        FROM promo_codes
+        WHERE event_id = $1 AND code = $2
+        FOR UPDATE
+        "#,
+        event_id,
+        code
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(PurchaseError::PromoCodeNotFound(code))?
+    .into();
+
+    if !promo.applies_to(tier_id) {
+        return Err(PurchaseError::PromoCodeRejected(format!(
+            "Promo code {} does not apply to these tickets",
+            promo.code
+        )));
+    }
+
+    let used_by_user = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM promo_redemptions
+        WHERE promo_code_id = $1 AND user_id = $2
+        "#,
+        promo.id,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    promo
+        .check(now, used_by_user)
+        .map_err(PurchaseError::PromoCodeRejected)?;
+
+    let discounted = promo.discount.apply(unit_price)?;
+    let discount = unit_price.checked_sub(discounted)?.checked_mul(quantity)?;
+    Ok(AppliedPromo {
+        promo,
+        unit_price: discounted,
+        discount,
+    })
+}
+
+/// Records that `applied` was used for `hold_id` and counts the use.
+pub async fn redeem(
+    tx: &mut Transaction<'_, Postgres>,
+    applied: &AppliedPromo,
+    user_id: i32,
+    hold_id: Uuid,
+    quantity: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO promo_redemptions (
+            promo_code_id, user_id, hold_id, quantity, discount_cents, currency
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        applied.promo.id,
+        user_id,
+        hold_id,
+        quantity,
+        applied.discount.cents,
+        applied.discount.currency as Currency
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE promo_codes SET uses = uses + 1 WHERE id = $1",
+        applied.promo.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct CreatePromoCodeRequest {
+    pub code: String,
+    #[serde(flatten)]
+    pub discount: Discount,
+    #[serde(default)]
+    pub tier_ids: Vec<i32>,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+fn validate_code(code: &str) -> Result<(), String> {
+    let valid_chars = code
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if code.len() < 3 || code.len() > MAX_CODE_LENGTH || !valid_chars {
+        return Err(format!(
+            "Promo codes must be 3 to {} letters, digits, '-' or '_'",
+            MAX_CODE_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+pub async fn create_promo_code(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<CreatePromoCodeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = owned_event(&pg_pool, &user, event_id).await?;
+
+    let code = normalize_code(&req.code);
+    validate_code(&code).map_err(ApiError::ValidationFailed)?;
+    req.discount
+        .validate()
+        .map_err(ApiError::ValidationFailed)?;
+    if let Discount::Fixed { amount } = req.discount {
+        if amount.currency != event.price.currency {
+            return Err(ApiError::ValidationFailed(format!(
+                "Fixed discounts must be in the event's currency, {}",
+                event.price.currency.code()
+            )));
+        }
+    }
+    if req.max_uses.is_some_and(|max| max <= 0) || req.max_uses_per_user.is_some_and(|max| max <= 0)
+    {
+        return Err(ApiError::ValidationFailed(
+            "Usage limits must be greater than 0".to_string(),
+        ));
+    }
+    if let (Some(start), Some(end)) = (req.starts_at, req.ends_at) {
+        if start >= end {
+            return Err(ApiError::ValidationFailed(
+                "A promo code must start before it ends".to_string(),
+            ));
+        }
+    }
+
+    let mut tier_ids = req.tier_ids.clone();
+    tier_ids.sort_unstable();
+    tier_ids.dedup();
+    let known = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "known!" FROM ticket_tiers WHERE event_id = $1 AND id = ANY($2)"#,
+        event_id,
+        &tier_ids
+    )
+    .fetch_one(&pg_pool)
+    .await?;
+    if known != tier_ids.len() as i64 {
+        return Err(ApiError::ValidationFailed(
+            "Promo codes can only target this event's tiers".to_string(),
+        ));
+    }
+
+    let (kind, percent_bps, amount_cents, currency) = match req.discount {
+        Discount::Percent { percent_bps } => (DiscountKind::Percent, Some(percent_bps), None, None),
+        Discount::Fixed { amount } => (
+            DiscountKind::Fixed,
+            None,
+            Some(amount.cents),
+            Some(amount.currency),
+        ),
+    };
+
+    let promo: PromoCode = sqlx::query_as!(
+        PromoCodeRow,
+        r#"
+        INSERT INTO promo_codes (
+            event_id, code, kind, percent_bps, amount_cents, currency, tier_ids, max_uses,
+            max_uses_per_user, starts_at, ends_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING
+            id, event_id, code,
+            kind as "kind: DiscountKind",
+            percent_bps, amount_cents,
+            currency as "currency: Currency",
+            tier_ids, max_uses, max_uses_per_user, uses, starts_at, ends_at, revoked_at,
+            created_at
+        "#,
+        event_id,
+        code,
+        kind as DiscountKind,
+        percent_bps,
+        amount_cents,
+        currency as Option<Currency>,
+        &tier_ids,
+        req.max_uses,
+        req.max_uses_per_user,
+        req.starts_at,
+        req.ends_at
+    )
+    .fetch_one(&pg_pool)
+    .await?
+    .into();
+
+    Ok((StatusCode::CREATED, Json(promo)))
+}
+
+pub async fn promo_codes(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+
+    let rows = sqlx::query_as!(
+        PromoCodeRow,
+        r#"
+        SELECT
+            id, event_id, code,
+            kind as "kind: DiscountKind",
+            percent_bps, amount_cents,
+            currency as "currency: Currency",
+            tier_ids, max_uses, max_uses_per_user, uses, starts_at, ends_at, revoked_at,
+            created_at
+        FROM promo_codes
+        WHERE event_id = $1
+        ORDER BY created_at
+        "#,
+        event_id
+    )
+    .fetch_all(&pg_pool)
+    .await?;
+
+    let promos: Vec<PromoCode> = rows.into_iter().map(PromoCode::from).collect();
+    Ok(Json(promos))
+}
+
+/// Stops a code from being applied to new holds. Existing holds keep
+/// their discount.
+pub async fn revoke_promo_code(
+    user: AuthUser,
+    Path((event_id, code_id)): Path<(i32, i32)>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE promo_codes
+        SET revoked_at = NOW()
+        WHERE id = $1 AND event_id = $2 AND revoked_at IS NULL
+        "#,
+        code_id,
+        event_id
+    )
+    .execute(&pg_pool)
+    .await?;
+
+    if revoked.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Promo code {} not found",
+            code_id
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,576 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::events::owned_event;
+use crate::inventory;
+use crate::models::ticket::TicketStatus;
+use crate::money::{Currency, Money, MoneyError};
+use crate::payments::{self, PaymentError, PaymentProvider};
+use crate::waitlist;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+
+const FULL_REFUND_BPS: i32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "refund_policy_kind")]
+pub enum RefundPolicyKind {
+    NoRefunds,
+    Full,
+    Partial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "refund_reason")]
+pub enum RefundReason {
+    Requested,
+    EventCancelled,
+}
+
+/// What a buyer gets back when they ask for a refund. `refund_bps` only
+/// matters for `Partial`; past `deadline` no refunds are given at all.
+/// Events without a policy do not refund on request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefundPolicy {
+    pub kind: RefundPolicyKind,
+    #[serde(default)]
+    pub refund_bps: i32,
+    pub deadline: Option<DateTime<Utc>>,
+}
+
+impl Default for RefundPolicy {
+    fn default() -> Self {
+        RefundPolicy {
+            kind: RefundPolicyKind::NoRefunds,
+            refund_bps: 0,
+            deadline: None,
+        }
+    }
+}
+
+impl RefundPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0..=FULL_REFUND_BPS).contains(&self.refund_bps) {
+            return Err("refund_bps must be between 0 and 10000".to_string());
+        }
+        if self.kind == RefundPolicyKind::Partial && self.refund_bps == 0 {
+            return Err("A partial refund must return more than 0%".to_string());
+        }
+        Ok(())
+    }
+
+    /// How much of `paid` the buyer gets back if they ask at `now`.
+    pub fn refund_for(&self, paid: Money, now: DateTime<Utc>) -> Result<Money, RefundError> {
+        if let Some(deadline) = self.deadline {
+            if now > deadline {
+                return Err(RefundError::DeadlinePassed(deadline));
+            }
+        }
+
+        match self.kind {
+            RefundPolicyKind::NoRefunds => Err(RefundError::NotAllowed),
+            RefundPolicyKind::Full => Ok(paid),
+            RefundPolicyKind::Partial => Ok(paid.checked_percent(self.refund_bps as i64)?),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketRefund {
+    pub id: i32,
+    pub event_id: i32,
+    pub ticket_ids: Vec<i32>,
+    pub amount: Money,
+    pub reason: RefundReason,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of refunding everyone after an event is cancelled. Payments that
+/// could not be refunded are logged and left for a retry.
+#[derive(Debug, Serialize)]
+pub struct RefundSummary {
+    pub refunds: Vec<TicketRefund>,
+    pub failed: usize,
+}
+
+#[derive(Debug)]
+pub enum RefundError {
+    TicketNotFound(i32),
+    NotTicketOwner(i32),
+    NotRefundable(i32),
+    Resold(i32),
+    NotAllowed,
+    DeadlinePassed(DateTime<Utc>),
+    Pricing(MoneyError),
+    Payment(PaymentError),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for RefundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefundError::TicketNotFound(id) => write!(f, "Ticket {} not found", id),
+            RefundError::NotTicketOwner(id) => write!(f, "Ticket {} does not belong to you", id),
+            RefundError::NotRefundable(id) => write!(f, "Ticket {} cannot be refunded", id),
+            RefundError::Resold(id) => {
+                write!(
+                    f,
+                    "Ticket {} was bought on resale and cannot be refunded",
+                    id
+                )
+            }
+            RefundError::NotAllowed => write!(f, "This event does not offer refunds"),
+            RefundError::DeadlinePassed(deadline) => {
+                write!(f, "Refunds for this event closed at {}", deadline)
+            }
+            RefundError::Pricing(e) => write!(f, "{}", e),
+            RefundError::Payment(e) => write!(f, "{}", e),
+            RefundError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for RefundError {
+    fn from(e: sqlx::Error) -> Self {
+        RefundError::Database(e)
+    }
+}
+
+impl From<MoneyError> for RefundError {
+    fn from(e: MoneyError) -> Self {
+        RefundError::Pricing(e)
+    }
+}
+
+impl From<PaymentError> for RefundError {
+    fn from(e: PaymentError) -> Self {
+        RefundError::Payment(e)
+    }
+}
+
+impl From<RefundError> for ApiError {
+    fn from(e: RefundError) -> Self {
+        match e {
+            RefundError::TicketNotFound(_) => ApiError::NotFound(e.to_string()),
+            RefundError::NotTicketOwner(_) => ApiError::Forbidden(e.to_string()),
+            RefundError::NotRefundable(_)
+            | RefundError::Resold(_)
+            | RefundError::NotAllowed
+            | RefundError::DeadlinePassed(_) => ApiError::Conflict(e.to_string()),
+            RefundError::Pricing(_) => ApiError::ValidationFailed(e.to_string()),
+            RefundError::Payment(e) => ApiError::from(e),
+            RefundError::Database(e) => ApiError::from(e),
+        }
+    }
+}
+
+pub async fn policy(pg_pool: &PgPool, event_id: i32) -> Result<RefundPolicy, sqlx::Error> {
+    let policy = sqlx::query_as!(
+        RefundPolicy,
+        r#"
+        SELECT kind as "kind: RefundPolicyKind", refund_bps, deadline
+        FROM event_refund_policies
+        WHERE event_id = $1
+        "#,
+        event_id
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+    Ok(policy.unwrap_or_default())
+}
+
+/// Tickets being refunded together: all on the same payment (or none, for
+/// free tickets) and all belonging to `user_id`.
+struct RefundTarget {
+    event_id: i32,
+    user_id: i32,
+    payment_id: Option<i32>,
+    ticket_ids: Vec<i32>,
+    amount: Money,
+    reason: RefundReason,
+}
+
+/// Cancels the target tickets, puts them back into the event's and their
+/// tiers' `available` counts and refunds `amount` to the payment they were
+/// bought with. The provider is called last so a failed refund rolls the
+/// whole thing back.
+async fn refund_tickets(
+    tx: &mut Transaction<'_, Postgres>,
+    provider: &dyn PaymentProvider,
+    target: RefundTarget,
+) -> Result<TicketRefund, RefundError> {
+    sqlx::query!(
+        r#"
+        UPDATE resale_listings
+        SET status = 'Cancelled', updated_at = NOW()
+        WHERE ticket_id = ANY($1) AND status = 'Active'
+        "#,
+        &target.ticket_ids
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let refund = sqlx::query!(
+        r#"
+        INSERT INTO refunds (event_id, user_id, payment_id, amount_cents, currency, reason)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, created_at
+        "#,
+        target.event_id,
+        target.user_id,
+        target.payment_id,
+        target.amount.cents,
+        target.amount.currency as Currency,
+        target.reason as RefundReason
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE tickets SET status = 'Cancelled', refund_id = $2 WHERE id = ANY($1)",
+        &target.ticket_ids,
+        refund.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE orders SET updated_at = NOW()
+        WHERE id IN (SELECT order_id FROM tickets WHERE id = ANY($1))
+        "#,
+        &target.ticket_ids
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE events SET available = available + $2 WHERE id = $1",
+        target.event_id,
+        target.ticket_ids.len() as i64
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE ticket_tiers tt
+        SET available = tt.available + c.refunded
+        FROM (
+            SELECT tier_id, COUNT(*) as refunded
+            FROM tickets
+            WHERE id = ANY($1) AND tier_id IS NOT NULL
+            GROUP BY tier_id
+        ) c
+        WHERE tt.id = c.tier_id
+        "#,
+        &target.ticket_ids
+    )
+    .execute(&mut *tx)
+    .await?;
+    inventory::announce(&mut *tx, target.event_id).await?;
+
+    if let (Some(payment_id), false) = (target.payment_id, target.amount.is_zero()) {
+        let intent_id = sqlx::query_scalar!(
+            r#"
+            UPDATE payments
+            SET refunded_cents = refunded_cents + $2,
+                status = CASE
+                    WHEN refunded_cents + $2 = amount_cents THEN 'Refunded'::payment_status
+                    ELSE status
+                END,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING intent_id
+            "#,
+            payment_id,
+            target.amount.cents
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let provider_refund = provider.refund(&intent_id, target.amount).await?;
+
+        sqlx::query!(
+            "UPDATE refunds SET provider_refund_id = $2 WHERE id = $1",
+            refund.id,
+            provider_refund.id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(TicketRefund {
+        id: refund.id,
+        event_id: target.event_id,
+        ticket_ids: target.ticket_ids,
+        amount: target.amount,
+        reason: target.reason,
+        created_at: refund.created_at,
+    })
+}
+
+/// Refunds one ticket at the request of its holder, as far as the event's
+/// refund policy allows. The ticket is offered to the event's waitlist.
+pub async fn request_refund(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    user_id: i32,
+    ticket_id: i32,
+) -> Result<TicketRefund, RefundError> {
+    let event_id = sqlx::query_scalar!("SELECT event_id FROM tickets WHERE id = $1", ticket_id)
+        .fetch_optional(pg_pool)
+        .await?
+        .ok_or(RefundError::TicketNotFound(ticket_id))?;
+
+    let policy = policy(pg_pool, event_id).await?;
+
+    let mut tx = pg_pool.begin().await?;
+
+    // Same lock order as checkout: event first, then its tickets.
+    sqlx::query!("SELECT id FROM events WHERE id = $1 FOR UPDATE", event_id)
+        .fetch_one(&mut tx)
+        .await?;
+
+    let ticket = sqlx::query!(
+        r#"
+        SELECT
+            t.user_id,
+            t.status as "status: TicketStatus",
+            t.price_cents,
+            t.currency as "currency: Currency",
+            t.payment_id,
+            p.user_id as "payer_id?"
+        FROM tickets t
+        LEFT JOIN payments p ON p.id = t.payment_id
+        WHERE t.id = $1
+        FOR UPDATE OF t
+        "#,
+        ticket_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    if ticket.user_id != Some(user_id) {
+        return Err(RefundError::NotTicketOwner(ticket_id));
+    }
+    if ticket.status != TicketStatus::Sold {
+        return Err(RefundError::NotRefundable(ticket_id));
+    }
+    if ticket.payer_id.is_some_and(|payer_id| payer_id != user_id) {
+        return Err(RefundError::Resold(ticket_id));
+    }
+
+    let paid = Money::new(ticket.price_cents, ticket.currency);
+    let amount = policy.refund_for(paid, Utc::now())?;
+
+    let refund = refund_tickets(
+        &mut tx,
+        provider,
+        RefundTarget {
+            event_id,
+            user_id,
+            payment_id: ticket.payment_id,
+            ticket_ids: vec![ticket_id],
+            amount,
+            reason: RefundReason::Requested,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    waitlist::offer_returned(pg_pool, &[event_id]).await;
+    Ok(refund)
+}
+
+/// Fully refunds every sold ticket of a cancelled event, one payment at a
+/// time, regardless of its refund policy. Each ticket is refunded to whoever
+/// holds it, from the payment they bought it with: a resold ticket's buyer
+/// gets the resale price back, and its seller, who was already paid for it,
+/// is not refunded the original price. Safe to run again: tickets that were
+/// already refunded are no longer `Sold`.
+pub async fn refund_event(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    event_id: i32,
+) -> Result<RefundSummary, sqlx::Error> {
+    let groups = sqlx::query!(
+        r#"
+        SELECT DISTINCT
+            COALESCE(r.id, t.payment_id) as payment_id,
+            COALESCE(r.user_id, p.user_id, t.user_id) as "user_id!"
+        FROM tickets t
+        LEFT JOIN payments p ON p.id = t.payment_id
+        LEFT JOIN LATERAL (
+            SELECT rp.id, rp.user_id
+            FROM ticket_transfers tt
+            JOIN payments rp ON rp.listing_id = tt.listing_id AND rp.status = 'Succeeded'
+            WHERE tt.ticket_id = t.id AND tt.to_user_id = t.user_id
+            ORDER BY tt.id DESC
+            LIMIT 1
+        ) r ON TRUE
+        WHERE t.event_id = $1 AND t.status = 'Sold'
+        "#,
+        event_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let mut summary = RefundSummary {
+        refunds: Vec::new(),
+        failed: 0,
+    };
+
+    for group in groups {
+        match refund_payment(pg_pool, provider, event_id, group.payment_id, group.user_id).await {
+            Ok(Some(refund)) => summary.refunds.push(refund),
+            Ok(None) => {}
+            Err(e) => {
+                log::error!(
+                    "failed to refund payment {:?} for event {}: {}",
+                    group.payment_id,
+                    event_id,
+                    e
+                );
+                summary.failed += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+async fn refund_payment(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    event_id: i32,
+    payment_id: Option<i32>,
+    user_id: i32,
+) -> Result<Option<TicketRefund>, RefundError> {
+    let mut tx = pg_pool.begin().await?;
+
+    sqlx::query!("SELECT id FROM events WHERE id = $1 FOR UPDATE", event_id)
+        .fetch_one(&mut tx)
+        .await?;
+
+    let tickets = sqlx::query!(
+        r#"
+        SELECT
+            t.id,
+            COALESCE(r.amount_cents, t.price_cents) as "price_cents!",
+            COALESCE(r.currency, t.currency) as "currency!: Currency"
+        FROM tickets t
+        LEFT JOIN payments p ON p.id = t.payment_id
+        LEFT JOIN LATERAL (
+            SELECT rp.id, rp.user_id, rp.amount_cents, rp.currency
+            FROM ticket_transfers tt
+            JOIN payments rp ON rp.listing_id = tt.listing_id AND rp.status = 'Succeeded'
+            WHERE tt.ticket_id = t.id AND tt.to_user_id = t.user_id
+            ORDER BY tt.id DESC
+            LIMIT 1
+        ) r ON TRUE
+        WHERE t.event_id = $1
+          AND t.status = 'Sold'
+          AND COALESCE(r.id, t.payment_id) IS NOT DISTINCT FROM $2
+          AND COALESCE(r.user_id, p.user_id, t.user_id) = $3
+        FOR UPDATE OF t
+        "#,
+        event_id,
+        payment_id,
+        user_id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let Some(first) = tickets.first() else {
+        return Ok(None);
+    };
+    let amount = Money::checked_sum(
+        first.currency,
+        tickets
+            .iter()
+            .map(|ticket| Money::new(ticket.price_cents, ticket.currency)),
+    )?;
+
+    let refund = refund_tickets(
+        &mut tx,
+        provider,
+        RefundTarget {
+            event_id,
+            user_id,
+            payment_id,
+            ticket_ids: tickets.iter().map(|ticket| ticket.id).collect(),
+            amount,
+            reason: RefundReason::EventCancelled,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(refund))
+}
+
+#[derive(Deserialize)]
+pub struct RefundRequest {
+    pub ticket_id: i32,
+}
+
+pub async fn refund(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<RefundRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let refund = request_refund(&pg_pool, payments::provider(), user.id, req.ticket_id).await?;
+    Ok((StatusCode::CREATED, Json(refund)))
+}
+
+pub async fn get_policy(
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(policy(&pg_pool, event_id).await?))
+}
+
+pub async fn set_policy(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<RefundPolicy>,
+) -> Result<impl IntoResponse, ApiError> {
+    owned_event(&pg_pool, &user, event_id).await?;
+    req.validate().map_err(ApiError::ValidationFailed)?;
+
+    let refund_bps = match req.kind {
+        RefundPolicyKind::Full => FULL_REFUND_BPS,
+        RefundPolicyKind::NoRefunds => 0,
+        RefundPolicyKind::Partial => req.refund_bps,
+    };
+
+    let policy = sqlx::query_as!(
+        RefundPolicy,
+        r#"
+        INSERT INTO event_refund_policies (event_id, kind, refund_bps, deadline)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (event_id) DO UPDATE
+        SET kind = EXCLUDED.kind,
+            refund_bps = EXCLUDED.refund_bps,
+            deadline = EXCLUDED.deadline,
+            updated_at = NOW()
+        RETURNING kind as "kind: RefundPolicyKind", refund_bps, deadline
+        "#,
+        event_id,
+        req.kind as RefundPolicyKind,
+        refund_bps,
+        req.deadline
+    )
+    .fetch_one(&pg_pool)
+    .await?;
+
+    Ok(Json(policy))
+}
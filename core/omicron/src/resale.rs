@@ -0,0 +1,545 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::invitations;
+use crate::models::ticket::TicketStatus;
+use crate::money::{Currency, Money, MoneyError};
+use crate::payments::{self, PaymentProvider, PaymentStatus};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "listing_status")]
+#[derive(Serialize, Deserialize)]
+pub enum ListingStatus {
+    Active,
+    Sold,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Listing {
+    pub id: i32,
+    pub ticket_id: i32,
+    pub event_id: i32,
+    pub seller_id: i32,
+    pub price: Money,
+    pub status: ListingStatus,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Transfer {
+    pub id: i32,
+    pub ticket_id: i32,
+    pub from_user_id: Option<i32>,
+    pub to_user_id: i32,
+    pub listing_id: Option<i32>,
+    pub price: Option<Money>,
+    pub transferred_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug)]
+pub enum ResaleError {
+    InvalidPrice(Money),
+    Pricing(MoneyError),
+    TicketNotFound(i32),
+    NotTicketOwner(i32),
+    NotResellable(i32),
+    AlreadyListed(i32),
+    ListingNotFound(i32),
+    ListingUnavailable(i32),
+    OwnListing(i32),
+    NotInvited(i32),
+    PaymentFailed(String),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for ResaleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResaleError::InvalidPrice(price) => {
+                write!(f, "Listing price must be greater than 0, got {}", price)
+            }
+            ResaleError::Pricing(e) => write!(f, "{}", e),
+            ResaleError::TicketNotFound(id) => write!(f, "Ticket {} not found", id),
+            ResaleError::NotTicketOwner(id) => write!(f, "Ticket {} does not belong to you", id),
+            ResaleError::NotResellable(id) => {
+                write!(f, "Ticket {} is not a sold ticket that can be resold", id)
+            }
+            ResaleError::AlreadyListed(id) => write!(f, "Ticket {} is already listed", id),
+            ResaleError::ListingNotFound(id) => write!(f, "Listing {} not found", id),
+            ResaleError::ListingUnavailable(id) => {
+                write!(f, "Listing {} is no longer available", id)
+            }
+            ResaleError::OwnListing(id) => write!(f, "Listing {} is your own", id),
+            ResaleError::NotInvited(event_id) => {
+                write!(f, "Event {} is private and you are not invited", event_id)
+            }
+            ResaleError::PaymentFailed(reason) => write!(f, "Payment failed: {}", reason),
+            ResaleError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for ResaleError {
+    fn from(e: sqlx::Error) -> Self {
+        ResaleError::Database(e)
+    }
+}
+
+impl From<ResaleError> for ApiError {
+    fn from(e: ResaleError) -> Self {
+        match e {
+            ResaleError::InvalidPrice(_) | ResaleError::Pricing(_) | ResaleError::OwnListing(_) => {
+                ApiError::ValidationFailed(e.to_string())
+            }
+            ResaleError::NotTicketOwner(_) | ResaleError::NotInvited(_) => {
+                ApiError::Forbidden(e.to_string())
+            }
+            ResaleError::TicketNotFound(_) | ResaleError::ListingNotFound(_) => {
+                ApiError::NotFound(e.to_string())
+            }
+            ResaleError::NotResellable(_)
+            | ResaleError::AlreadyListed(_)
+            | ResaleError::ListingUnavailable(_) => ApiError::Conflict(e.to_string()),
+            ResaleError::PaymentFailed(_) => ApiError::PaymentFailed(e.to_string()),
+            ResaleError::Database(e) => ApiError::from(e),
+        }
+    }
+}
+
+/// Puts a sold ticket up for resale. Tickets still on hold, checked in or
+/// refunded cannot be listed, nor can tickets to a private event the seller
+/// was since uninvited from. The asking price must be in the currency the
+/// ticket was originally sold in.
+pub async fn create_listing(
+    pg_pool: &PgPool,
+    seller_id: i32,
+    ticket_id: i32,
+    price: Money,
+) -> Result<Listing, ResaleError> {
+    if price.cents <= 0 {
+        return Err(ResaleError::InvalidPrice(price));
+    }
+
+    let mut tx = pg_pool.begin().await?;
+
+    let ticket = sqlx::query!(
+        r#"
+        SELECT
+            user_id,
+            event_id,
+            status as "status: TicketStatus",
+            currency as "currency: Currency"
+        FROM tickets
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        ticket_id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(ResaleError::TicketNotFound(ticket_id))?;
+
+    if ticket.user_id != Some(seller_id) {
+        return Err(ResaleError::NotTicketOwner(ticket_id));
+    }
+    if ticket.status != TicketStatus::Sold {
+        return Err(ResaleError::NotResellable(ticket_id));
+    }
+    if !invitations::has_access(&mut tx, ticket.event_id, seller_id).await? {
+        return Err(ResaleError::NotInvited(ticket.event_id));
+    }
+    if price.currency != ticket.currency {
+        return Err(ResaleError::Pricing(MoneyError::CurrencyMismatch(
+            price.currency,
+            ticket.currency,
+        )));
+    }
+
+    let listing = sqlx::query!(
+        r#"
+        INSERT INTO resale_listings (ticket_id, seller_id, price_cents, currency)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        RETURNING id, status as "status: ListingStatus", created_at
+        "#,
+        ticket_id,
+        seller_id,
+        price.cents,
+        price.currency as Currency
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(ResaleError::AlreadyListed(ticket_id))?;
+
+    tx.commit().await?;
+
+    Ok(Listing {
+        id: listing.id,
+        ticket_id,
+        event_id: ticket.event_id,
+        seller_id,
+        price,
+        status: listing.status,
+        created_at: listing.created_at,
+    })
+}
+
+pub async fn cancel_listing(
+    pg_pool: &PgPool,
+    seller_id: i32,
+    listing_id: i32,
+) -> Result<(), ResaleError> {
+    let listing = sqlx::query!(
+        r#"
+        UPDATE resale_listings
+        SET status = 'Cancelled', updated_at = NOW()
+        WHERE id = $1 AND seller_id = $2 AND status = 'Active'
+        RETURNING id
+        "#,
+        listing_id,
+        seller_id
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+
+    match listing {
+        Some(_) => Ok(()),
+        None => Err(ResaleError::ListingUnavailable(listing_id)),
+    }
+}
+
+/// Charges the buyer the asking price and moves the listed ticket to them.
+/// The card is charged before any row is locked; the transfer then locks
+/// the listing and only goes through if it is still active and the seller
+/// still holds the ticket as sold. If the transfer fails for any reason,
+/// including losing that race, the buyer is refunded.
+pub async fn purchase_listing(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    buyer_id: i32,
+    listing_id: i32,
+) -> Result<Transfer, ResaleError> {
+    let listing = sqlx::query!(
+        r#"
+        SELECT
+            l.ticket_id,
+            t.event_id,
+            l.seller_id,
+            l.price_cents,
+            l.currency as "currency: Currency",
+            l.status as "status: ListingStatus"
+        FROM resale_listings l
+        JOIN tickets t ON t.id = l.ticket_id
+        WHERE l.id = $1
+        "#,
+        listing_id
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or(ResaleError::ListingNotFound(listing_id))?;
+
+    if listing.status != ListingStatus::Active {
+        return Err(ResaleError::ListingUnavailable(listing_id));
+    }
+    if listing.seller_id == buyer_id {
+        return Err(ResaleError::OwnListing(listing_id));
+    }
+    if !invitations::has_access(pg_pool, listing.event_id, buyer_id).await? {
+        return Err(ResaleError::NotInvited(listing.event_id));
+    }
+
+    let price = Money::new(listing.price_cents, listing.currency);
+    let intent = provider
+        .create_intent(price, &format!("listing-{}", listing_id))
+        .await
+        .map_err(|e| ResaleError::PaymentFailed(e.to_string()))?;
+
+    let payment_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO payments (user_id, event_id, listing_id, provider, intent_id, amount_cents, currency)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+        buyer_id,
+        listing.event_id,
+        listing_id,
+        provider.name(),
+        intent.id,
+        price.cents,
+        price.currency as Currency
+    )
+    .fetch_one(pg_pool)
+    .await?;
+
+    if let Err(e) = provider.capture(&intent.id).await {
+        payments::set_status(
+            pg_pool,
+            payment_id,
+            PaymentStatus::Failed,
+            Some(e.to_string()),
+        )
+        .await?;
+        return Err(ResaleError::PaymentFailed(e.to_string()));
+    }
+
+    match transfer(pg_pool, buyer_id, listing_id, payment_id).await {
+        Ok(transfer) => Ok(transfer),
+        Err(e) => {
+            if let Err(refund_error) = provider.refund(&intent.id, price).await {
+                // Still captured; keep the reason so it can be reconciled.
+                payments::set_status(
+                    pg_pool,
+                    payment_id,
+                    PaymentStatus::Succeeded,
+                    Some(format!("Refund failed after {}: {}", e, refund_error)),
+                )
+                .await?;
+                return Err(ResaleError::PaymentFailed(refund_error.to_string()));
+            }
+            payments::set_status(
+                pg_pool,
+                payment_id,
+                PaymentStatus::Refunded,
+                Some(e.to_string()),
+            )
+            .await?;
+            Err(e)
+        }
+    }
+}
+
+/// Moves a paid-for listing's ticket to the buyer. The listing row is
+/// locked so two buyers cannot both take it, and the ticket gets a fresh
+/// credential nonce so the seller's QR code stops admitting.
+async fn transfer(
+    pg_pool: &PgPool,
+    buyer_id: i32,
+    listing_id: i32,
+    payment_id: i32,
+) -> Result<Transfer, ResaleError> {
+    let mut tx = pg_pool.begin().await?;
+
+    let listing = sqlx::query!(
+        r#"
+        SELECT
+            ticket_id,
+            seller_id,
+            price_cents,
+            currency as "currency: Currency",
+            status as "status: ListingStatus"
+        FROM resale_listings
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        listing_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    if listing.status != ListingStatus::Active {
+        return Err(ResaleError::ListingUnavailable(listing_id));
+    }
+
+    let transferred = sqlx::query!(
+        r#"
+        UPDATE tickets SET user_id = $2, credential_nonce = gen_random_uuid()
+        WHERE id = $1 AND user_id = $3 AND status = 'Sold'
+        RETURNING id
+        "#,
+        listing.ticket_id,
+        buyer_id,
+        listing.seller_id
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    if transferred.is_none() {
+        return Err(ResaleError::ListingUnavailable(listing_id));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE resale_listings
+        SET status = 'Sold', buyer_id = $2, updated_at = NOW()
+        WHERE id = $1
+        "#,
+        listing_id,
+        buyer_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE payments SET status = 'Succeeded', updated_at = NOW() WHERE id = $1",
+        payment_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let transfer = sqlx::query!(
+        r#"
+        INSERT INTO ticket_transfers (
+            ticket_id, from_user_id, to_user_id, listing_id, price_cents, currency
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, transferred_at
+        "#,
+        listing.ticket_id,
+        listing.seller_id,
+        buyer_id,
+        listing_id,
+        listing.price_cents,
+        listing.currency as Currency
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Transfer {
+        id: transfer.id,
+        ticket_id: listing.ticket_id,
+        from_user_id: Some(listing.seller_id),
+        to_user_id: buyer_id,
+        listing_id: Some(listing_id),
+        price: Some(Money::new(listing.price_cents, listing.currency)),
+        transferred_at: transfer.transferred_at,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct CreateListingRequest {
+    pub ticket_id: i32,
+    pub price: Money,
+}
+
+pub async fn list_ticket(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+    Json(request): Json<CreateListingRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let listing = create_listing(&pg_pool, user.id, request.ticket_id, request.price).await?;
+    Ok((StatusCode::CREATED, Json(listing)))
+}
+
+pub async fn delist_ticket(
+    user: AuthUser,
+    Path(listing_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    cancel_listing(&pg_pool, user.id, listing_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn buy_listing(
+    user: AuthUser,
+    Path(listing_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let transfer = purchase_listing(&pg_pool, payments::provider(), user.id, listing_id).await?;
+    Ok((StatusCode::CREATED, Json(transfer)))
+}
+
+pub async fn listings(
+    user: Option<AuthUser>,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    invitations::check_visible(&pg_pool, event_id, user.map(|user| user.id)).await?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            l.id,
+            l.ticket_id,
+            t.event_id,
+            l.seller_id,
+            l.price_cents,
+            l.currency as "currency: Currency",
+            l.status as "status: ListingStatus",
+            l.created_at
+        FROM resale_listings l
+        JOIN tickets t ON t.id = l.ticket_id
+        WHERE t.event_id = $1 AND l.status = 'Active' AND t.status = 'Sold'
+        ORDER BY l.price_cents, l.created_at
+        "#,
+        event_id
+    )
+    .fetch_all(&pg_pool)
+    .await?;
+
+    let listings: Vec<Listing> = rows
+        .into_iter()
+        .map(|row| Listing {
+            id: row.id,
+            ticket_id: row.ticket_id,
+            event_id: row.event_id,
+            seller_id: row.seller_id,
+            price: Money::new(row.price_cents, row.currency),
+            status: row.status,
+            created_at: row.created_at,
+        })
+        .collect();
+    Ok(Json(listings))
+}
+
+/// Ownership history of a ticket, oldest first. Only its current holder
+/// may read it.
+pub async fn transfers(
+    user: AuthUser,
+    Path(ticket_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let owner = sqlx::query_scalar!("SELECT user_id FROM tickets WHERE id = $1", ticket_id)
+        .fetch_optional(&pg_pool)
+        .await?
+        .ok_or(ResaleError::TicketNotFound(ticket_id))?;
+
+    if owner != Some(user.id) {
+        return Err(ResaleError::NotTicketOwner(ticket_id).into());
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            ticket_id,
+            from_user_id,
+            to_user_id,
+            listing_id,
+            price_cents,
+            currency as "currency: Currency",
+            transferred_at
+        FROM ticket_transfers
+        WHERE ticket_id = $1
+        ORDER BY transferred_at, id
+        "#,
+        ticket_id
+    )
+    .fetch_all(&pg_pool)
+    .await?;
+
+    let transfers: Vec<Transfer> = rows
+        .into_iter()
+        .map(|row| Transfer {
+            id: row.id,
+            ticket_id: row.ticket_id,
+            from_user_id: row.from_user_id,
+            to_user_id: row.to_user_id,
+            listing_id: row.listing_id,
+            price: row
+                .price_cents
+                .zip(row.currency)
+                .map(|(cents, currency)| Money::new(cents, currency)),
+            transferred_at: row.transferred_at,
+        })
+        .collect();
+    Ok(Json(transfers))
+}
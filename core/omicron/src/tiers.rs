@@ -0,0 +1,231 @@
+use crate::auth::jwt::AuthUser;
+use crate::error::ApiError;
+use crate::events::owned_event;
+use crate::inventory;
+use crate::invitations;
+use crate::models::event::Event;
+use crate::models::tier::{self, validation::validate_tier, TicketTier};
+use crate::money::Money;
+use crate::waitlist;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+
+#[derive(Deserialize)]
+pub struct CreateTierRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub price: Money,
+    pub quantity: i64,
+    pub sales_start: Option<DateTime<Utc>>,
+    pub sales_end: Option<DateTime<Utc>>,
+    pub min_per_order: Option<i32>,
+    pub max_per_order: Option<i32>,
+}
+
+/// Partial update; omitted fields are left unchanged.
+#[derive(Deserialize)]
+pub struct UpdateTierRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub price: Option<Money>,
+    pub quantity: Option<i64>,
+    pub sales_start: Option<DateTime<Utc>>,
+    pub sales_end: Option<DateTime<Utc>>,
+    pub min_per_order: Option<i32>,
+    pub max_per_order: Option<i32>,
+}
+
+/// Loads an event whose tiers the caller may change.
+async fn editable_event(
+    pg_pool: &PgPool,
+    user: &AuthUser,
+    event_id: i32,
+) -> Result<Event, ApiError> {
+    let event = owned_event(pg_pool, user, event_id).await?;
+    if !event.is_editable() {
+        return Err(ApiError::Conflict(format!(
+            "Event is {:?} and can no longer be edited",
+            event.status
+        )));
+    }
+    Ok(event)
+}
+
+/// Checks `tier` against its event, which the caller has locked.
+async fn check_fits(
+    tx: &mut Transaction<'_, Postgres>,
+    event: &Event,
+    tier: &TicketTier,
+) -> Result<(), ApiError> {
+    if tier.price.currency != event.price.currency {
+        return Err(ApiError::ValidationFailed(format!(
+            "Tier price must be in the event's currency, {}",
+            event.price.currency.code()
+        )));
+    }
+
+    let allocated = tier::store::allocated(&mut *tx, event.id, Some(tier.id)).await?;
+    if allocated + tier.quantity > event.capacity {
+        return Err(ApiError::ValidationFailed(format!(
+            "Tiers would hold {} tickets but the event's capacity is {}",
+            allocated + tier.quantity,
+            event.capacity
+        )));
+    }
+    Ok(())
+}
+
+async fn lock_event(tx: &mut Transaction<'_, Postgres>, event_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT id FROM events WHERE id = $1 FOR UPDATE", event_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    Ok(())
+}
+
+fn tier_not_found(tier_id: i32) -> ApiError {
+    ApiError::NotFound(format!("Ticket tier {} not found", tier_id))
+}
+
+pub async fn tiers(
+    user: Option<AuthUser>,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    invitations::check_visible(&pg_pool, event_id, user.map(|user| user.id)).await?;
+    let tiers = tier::store::fetch_for_event(&pg_pool, event_id).await?;
+    Ok(Json(tiers))
+}
+
+pub async fn create(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<CreateTierRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = editable_event(&pg_pool, &user, event_id).await?;
+
+    let mut tier = TicketTier::new(
+        event_id,
+        req.name.trim().to_string(),
+        req.description,
+        req.price,
+        req.quantity,
+        req.sales_start,
+        req.sales_end,
+        req.min_per_order.unwrap_or(1),
+        req.max_per_order,
+    );
+    validate_tier(&tier).map_err(ApiError::ValidationFailed)?;
+
+    let mut tx = pg_pool.begin().await?;
+    lock_event(&mut tx, event_id).await?;
+    check_fits(&mut tx, &event, &tier).await?;
+    tier.id = tier::store::insert(&mut tx, &tier).await?;
+    inventory::announce(&mut tx, event_id).await?;
+    tx.commit().await?;
+
+    let tier = tier::store::fetch(&pg_pool, tier.id).await?;
+    Ok((StatusCode::CREATED, Json(tier)))
+}
+
+pub async fn update(
+    user: AuthUser,
+    Path((event_id, tier_id)): Path<(i32, i32)>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<UpdateTierRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let event = editable_event(&pg_pool, &user, event_id).await?;
+
+    let mut tx = pg_pool.begin().await?;
+    lock_event(&mut tx, event_id).await?;
+    let mut tier = tier::store::lock(&mut tx, event_id, tier_id)
+        .await?
+        .ok_or_else(|| tier_not_found(tier_id))?;
+
+    if let Some(name) = req.name {
+        tier.update_name(name.trim().to_string());
+    }
+    if let Some(description) = req.description {
+        tier.update_description(Some(description));
+    }
+    if let Some(price) = req.price {
+        tier.update_price(price);
+    }
+    if let Some(quantity) = req.quantity {
+        tier.update_quantity(quantity);
+    }
+    if req.sales_start.is_some() || req.sales_end.is_some() {
+        let start = req.sales_start.or(tier.sales_start);
+        let end = req.sales_end.or(tier.sales_end);
+        tier.update_sale_window(start, end);
+    }
+    if req.min_per_order.is_some() || req.max_per_order.is_some() {
+        let min = req.min_per_order.unwrap_or(tier.min_per_order);
+        let max = req.max_per_order.or(tier.max_per_order);
+        tier.update_order_limits(min, max);
+    }
+
+    validate_tier(&tier).map_err(ApiError::ValidationFailed)?;
+    check_fits(&mut tx, &event, &tier).await?;
+    tier::store::update(&mut tx, &tier).await?;
+    inventory::announce(&mut tx, event_id).await?;
+    tx.commit().await?;
+    if req.quantity.is_some() {
+        waitlist::offer_returned(&pg_pool, &[event_id]).await;
+    }
+
+    let tier = tier::store::fetch(&pg_pool, tier_id).await?;
+    Ok(Json(tier))
+}
+
+/// Removes a tier nobody holds tickets for. Rows that holds gave back are
+/// dropped with it; their seats stay in the event's `available` count.
+pub async fn delete(
+    user: AuthUser,
+    Path((event_id, tier_id)): Path<(i32, i32)>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    editable_event(&pg_pool, &user, event_id).await?;
+
+    let mut tx = pg_pool.begin().await?;
+    lock_event(&mut tx, event_id).await?;
+    tier::store::lock(&mut tx, event_id, tier_id)
+        .await?
+        .ok_or_else(|| tier_not_found(tier_id))?;
+
+    let issued = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "issued!"
+        FROM tickets
+        WHERE tier_id = $1 AND status <> 'Available'
+        "#,
+        tier_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    if issued > 0 {
+        return Err(ApiError::Conflict(format!(
+            "{} tickets have been issued in this tier; it can no longer be removed",
+            issued
+        )));
+    }
+
+    sqlx::query!(
+        "DELETE FROM tickets WHERE tier_id = $1 AND status = 'Available'",
+        tier_id
+    )
+    .execute(&mut tx)
+    .await?;
+    tier::store::delete(&mut tx, tier_id).await?;
+    inventory::announce(&mut tx, event_id).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,472 @@
+use crate::auth::jwt::AuthUser;
+use crate::checkout;
+use crate::error::ApiError;
+use crate::internal::PurchaseError;
+use crate::invitations;
+use crate::models::event::EventStatus;
+use crate::models::tier;
+use crate::money::{Currency, Money};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Postgres channel new offers are announced on, for whoever can push
+/// them to their buyer.
+pub const OFFERS_CHANNEL: &str = "waitlist_offers";
+
+const DEFAULT_OFFER_TTL_SECONDS: i64 = 900;
+
+/// How long a waitlisted buyer gets to pay for an offer, from
+/// `WAITLIST_OFFER_TTL_SECONDS`.
+pub fn offer_ttl() -> Duration {
+    let secs = std::env::var("WAITLIST_OFFER_TTL_SECONDS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_OFFER_TTL_SECONDS);
+    Duration::seconds(secs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "waitlist_status")]
+pub enum WaitlistStatus {
+    Waiting,
+    Offered,
+    Purchased,
+    Expired,
+    Left,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitlistEntry {
+    pub id: i32,
+    pub event_id: i32,
+    pub tier_id: Option<i32>,
+    pub user_id: i32,
+    pub quantity: i64,
+    pub status: WaitlistStatus,
+    pub hold_id: Option<Uuid>,
+    pub offered_at: Option<DateTime<Utc>>,
+    pub offer_expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Tickets held for a waitlisted buyer. Paying for `hold_id` before
+/// `expires_at` buys them; otherwise they go to the next in line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Offer {
+    pub entry_id: i32,
+    pub user_id: i32,
+    pub event_id: i32,
+    pub event_name: String,
+    pub tier_id: Option<i32>,
+    pub hold_id: Uuid,
+    pub ticket_ids: Vec<i32>,
+    pub unit_price: Money,
+    pub total: Money,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum WaitlistError {
+    AlreadyWaiting(i32),
+    NotWaiting(i32),
+    Purchase(PurchaseError),
+}
+
+impl std::fmt::Display for WaitlistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaitlistError::AlreadyWaiting(event_id) => {
+                write!(f, "You are already on the waitlist for event {}", event_id)
+            }
+            WaitlistError::NotWaiting(event_id) => {
+                write!(f, "You are not on the waitlist for event {}", event_id)
+            }
+            WaitlistError::Purchase(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<PurchaseError> for WaitlistError {
+    fn from(e: PurchaseError) -> Self {
+        WaitlistError::Purchase(e)
+    }
+}
+
+impl From<sqlx::Error> for WaitlistError {
+    fn from(e: sqlx::Error) -> Self {
+        WaitlistError::Purchase(PurchaseError::Database(e))
+    }
+}
+
+impl From<WaitlistError> for ApiError {
+    fn from(e: WaitlistError) -> Self {
+        match e {
+            WaitlistError::AlreadyWaiting(_) => ApiError::Conflict(e.to_string()),
+            WaitlistError::NotWaiting(_) => ApiError::NotFound(e.to_string()),
+            WaitlistError::Purchase(e) => ApiError::from(e),
+        }
+    }
+}
+
+/// Queues `user_id` for `quantity` tickets of `event_id`, in `tier_id` if
+/// the event sells tiers. The same checks as checkout apply, so that an
+/// offer can always be taken up. Tickets already on sale are offered
+/// straight away.
+pub async fn join(
+    pg_pool: &PgPool,
+    user_id: i32,
+    event_id: i32,
+    tier_id: Option<i32>,
+    quantity: i64,
+) -> Result<WaitlistEntry, WaitlistError> {
+    if quantity < 1 {
+        return Err(PurchaseError::InvalidQuantity(quantity).into());
+    }
+
+    let status = sqlx::query_scalar!(
+        r#"SELECT status as "status: EventStatus" FROM events WHERE id = $1"#,
+        event_id
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or(PurchaseError::EventNotFound(event_id))?;
+    if status != EventStatus::Published {
+        return Err(PurchaseError::EventNotOnSale(event_id).into());
+    }
+    if !invitations::has_access(pg_pool, event_id, user_id).await? {
+        return Err(PurchaseError::NotInvited(event_id).into());
+    }
+
+    match tier_id {
+        Some(tier_id) => {
+            let tier = tier::store::fetch(pg_pool, tier_id)
+                .await
+                .ok()
+                .filter(|tier| tier.event_id == event_id)
+                .ok_or(PurchaseError::TierNotFound(tier_id))?;
+            tier.check_order_size(quantity)
+                .map_err(PurchaseError::OrderLimit)?;
+        }
+        None => {
+            if !tier::store::fetch_for_event(pg_pool, event_id)
+                .await?
+                .is_empty()
+            {
+                return Err(PurchaseError::TierRequired(event_id).into());
+            }
+        }
+    }
+
+    let entry = sqlx::query_as!(
+        WaitlistEntry,
+        r#"
+        INSERT INTO waitlist_entries (event_id, tier_id, user_id, quantity)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        RETURNING
+            id, event_id, tier_id, user_id, quantity,
+            status as "status: WaitlistStatus",
+            hold_id, offered_at, offer_expires_at, created_at
+        "#,
+        event_id,
+        tier_id,
+        user_id,
+        quantity
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or(WaitlistError::AlreadyWaiting(event_id))?;
+
+    let offers = make_offers(pg_pool, event_id).await?;
+    if offers.iter().any(|offer| offer.entry_id == entry.id) {
+        return Ok(entry_by_id(pg_pool, entry.id).await?);
+    }
+    Ok(entry)
+}
+
+/// Takes `user_id` off the waitlist for `event_id`, giving up any offer
+/// they have not paid for yet.
+pub async fn leave(pg_pool: &PgPool, user_id: i32, event_id: i32) -> Result<(), WaitlistError> {
+    let entry = sqlx::query!(
+        r#"
+        UPDATE waitlist_entries
+        SET status = 'Left', updated_at = NOW()
+        WHERE event_id = $1 AND user_id = $2 AND status IN ('Waiting', 'Offered')
+        RETURNING hold_id
+        "#,
+        event_id,
+        user_id
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or(WaitlistError::NotWaiting(event_id))?;
+
+    if let Some(hold_id) = entry.hold_id {
+        match checkout::release(pg_pool, user_id, hold_id).await {
+            Ok(()) | Err(PurchaseError::HoldUnavailable(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+async fn entry_by_id(pg_pool: &PgPool, entry_id: i32) -> Result<WaitlistEntry, sqlx::Error> {
+    sqlx::query_as!(
+        WaitlistEntry,
+        r#"
+        SELECT
+            id, event_id, tier_id, user_id, quantity,
+            status as "status: WaitlistStatus",
+            hold_id, offered_at, offer_expires_at, created_at
+        FROM waitlist_entries
+        WHERE id = $1
+        "#,
+        entry_id
+    )
+    .fetch_one(pg_pool)
+    .await
+}
+
+/// Offers whatever is on sale for `event_id` to its waitlist, in the order
+/// people joined. Each offer is a hold in the buyer's name for
+/// `offer_ttl()`, announced on `OFFERS_CHANNEL` once it commits. An entry
+/// that does not fit in what is left keeps its place, and later entries
+/// for the same tier wait behind it.
+pub async fn make_offers(pg_pool: &PgPool, event_id: i32) -> Result<Vec<Offer>, PurchaseError> {
+    let waiting = sqlx::query!(
+        r#"
+        SELECT id, tier_id, user_id, quantity
+        FROM waitlist_entries
+        WHERE event_id = $1 AND status = 'Waiting'
+        ORDER BY created_at, id
+        "#,
+        event_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let mut offers = Vec::new();
+    let mut blocked = Vec::new();
+    for entry in waiting {
+        if blocked.contains(&entry.tier_id) {
+            continue;
+        }
+
+        let hold = match checkout::reserve(
+            pg_pool,
+            entry.user_id,
+            event_id,
+            entry.tier_id,
+            entry.quantity,
+            None,
+            offer_ttl(),
+        )
+        .await
+        {
+            Ok(hold) => hold,
+            Err(PurchaseError::InsufficientSupply { .. } | PurchaseError::TierNotOnSale(_)) => {
+                blocked.push(entry.tier_id);
+                continue;
+            }
+            Err(PurchaseError::EventNotFound(_) | PurchaseError::EventNotOnSale(_)) => break,
+            Err(
+                e @ (PurchaseError::NotInvited(_)
+                | PurchaseError::TierNotFound(_)
+                | PurchaseError::TierRequired(_)
+                | PurchaseError::OrderLimit(_)),
+            ) => {
+                log::info!("dropping waitlist entry {}: {}", entry.id, e);
+                sqlx::query!(
+                    r#"
+                    UPDATE waitlist_entries
+                    SET status = 'Expired', updated_at = NOW()
+                    WHERE id = $1 AND status = 'Waiting'
+                    "#,
+                    entry.id
+                )
+                .execute(pg_pool)
+                .await?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let offer = Offer {
+            entry_id: entry.id,
+            user_id: entry.user_id,
+            event_id,
+            event_name: hold.event_name,
+            tier_id: hold.tier_id,
+            hold_id: hold.hold_id,
+            ticket_ids: hold.ticket_ids,
+            unit_price: hold.unit_price,
+            total: hold.total,
+            expires_at: hold.expires_at,
+        };
+
+        let mut tx = pg_pool.begin().await?;
+        if offer_entry(&mut tx, &offer).await? {
+            tx.commit().await?;
+            offers.push(offer);
+        } else {
+            // The buyer left while the tickets were being held.
+            tx.rollback().await?;
+            checkout::return_hold(pg_pool, offer.user_id, offer.hold_id).await?;
+        }
+    }
+
+    Ok(offers)
+}
+
+async fn offer_entry(
+    tx: &mut Transaction<'_, Postgres>,
+    offer: &Offer,
+) -> Result<bool, sqlx::Error> {
+    let offered = sqlx::query!(
+        r#"
+        UPDATE waitlist_entries
+        SET status = 'Offered', hold_id = $2, offered_at = NOW(), offer_expires_at = $3,
+            updated_at = NOW()
+        WHERE id = $1 AND status = 'Waiting'
+        "#,
+        offer.entry_id,
+        offer.hold_id,
+        offer.expires_at
+    )
+    .execute(&mut *tx)
+    .await?;
+    if offered.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let payload = serde_json::to_string(offer).expect("offers serialize to JSON");
+    sqlx::query!("SELECT pg_notify($1, $2)", OFFERS_CHANNEL, payload)
+        .execute(&mut *tx)
+        .await?;
+    Ok(true)
+}
+
+/// `make_offers` for every event in `event_ids`, for callers that have
+/// just put tickets back on sale and should not fail because of it.
+pub async fn offer_returned(pg_pool: &PgPool, event_ids: &[i32]) {
+    for &event_id in event_ids {
+        match make_offers(pg_pool, event_id).await {
+            Ok(offers) if offers.is_empty() => {}
+            Ok(offers) => log::info!(
+                "offered returned tickets of event {} to {} waitlisted buyer(s)",
+                event_id,
+                offers.len()
+            ),
+            Err(e) => log::error!("failed to offer event {} to its waitlist: {}", event_id, e),
+        }
+    }
+}
+
+/// Marks the offer that `hold_id` came from as taken up. Runs in the
+/// transaction that sells the hold.
+pub(crate) async fn fulfil(
+    tx: &mut Transaction<'_, Postgres>,
+    hold_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE waitlist_entries
+        SET status = 'Purchased', updated_at = NOW()
+        WHERE hold_id = $1 AND status = 'Offered'
+        "#,
+        hold_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Offers `user_id` has not paid for or let lapse yet, oldest first. This is
+/// how offers made while the buyer was offline reach them.
+pub async fn pending_offers(pg_pool: &PgPool, user_id: i32) -> Result<Vec<Offer>, WaitlistError> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            w.id,
+            w.event_id,
+            e.name as event_name,
+            w.tier_id,
+            w.hold_id as "hold_id!",
+            w.offer_expires_at as "expires_at!",
+            ARRAY_AGG(t.id ORDER BY t.id) as "ticket_ids!",
+            t.price_cents,
+            t.currency as "currency: Currency"
+        FROM waitlist_entries w
+        JOIN events e ON e.id = w.event_id
+        JOIN tickets t ON t.hold_id = w.hold_id AND t.status = 'Reserved'
+        WHERE w.user_id = $1 AND w.status = 'Offered' AND w.offer_expires_at > NOW()
+        GROUP BY w.id, e.id, t.price_cents, t.currency
+        ORDER BY w.offered_at
+        "#,
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let unit_price = Money::new(row.price_cents, row.currency);
+            let total = unit_price
+                .checked_mul(row.ticket_ids.len() as i64)
+                .map_err(PurchaseError::from)?;
+            Ok(Offer {
+                entry_id: row.id,
+                user_id,
+                event_id: row.event_id,
+                event_name: row.event_name,
+                tier_id: row.tier_id,
+                hold_id: row.hold_id,
+                total,
+                ticket_ids: row.ticket_ids,
+                unit_price,
+                expires_at: row.expires_at,
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub struct JoinWaitlistRequest {
+    #[serde(default)]
+    pub tier_id: Option<i32>,
+    pub quantity: i64,
+}
+
+pub async fn join_waitlist(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+    Json(req): Json<JoinWaitlistRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let entry = join(&pg_pool, user.id, event_id, req.tier_id, req.quantity).await?;
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+pub async fn leave_waitlist(
+    user: AuthUser,
+    Path(event_id): Path<i32>,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    leave(&pg_pool, user.id, event_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn offers(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(pending_offers(&pg_pool, user.id).await?))
+}
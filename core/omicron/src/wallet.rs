@@ -0,0 +1,122 @@
+use crate::auth::jwt::AuthUser;
+use crate::credentials::{self, Credential};
+use crate::error::ApiError;
+use crate::models::event::EventStatus;
+use crate::models::ticket::TicketStatus;
+use crate::money::{Currency, Money};
+use axum::{extract::State, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+/// A user's tickets to one event.
+#[derive(Debug, Serialize)]
+pub struct EventTickets {
+    pub event_id: i32,
+    pub event_name: String,
+    pub event_status: EventStatus,
+    pub location: String,
+    pub address: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub tickets: Vec<WalletTicket>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletTicket {
+    pub ticket_id: i32,
+    pub status: TicketStatus,
+    pub seat: Option<String>,
+    pub tier_id: Option<i32>,
+    pub tier: Option<String>,
+    pub price: Money,
+    pub order_id: Option<i32>,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    /// The signed QR payload to show at the door; only tickets that can
+    /// still be scanned have one.
+    pub credential: Option<String>,
+}
+
+/// Every ticket `user_id` holds or has had refunded, grouped by event,
+/// soonest event first.
+pub async fn tickets_of(pg_pool: &PgPool, user_id: i32) -> Result<Vec<EventTickets>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            t.id,
+            t.event_id,
+            e.name as event_name,
+            e.status as "event_status: EventStatus",
+            e.location,
+            e.address,
+            e.start_time,
+            e.end_time,
+            t.status as "status: TicketStatus",
+            t.seat,
+            t.tier_id,
+            tt.name as "tier?",
+            t.price_cents,
+            t.currency as "currency: Currency",
+            t.order_id,
+            t.checked_in_at,
+            t.credential_nonce
+        FROM tickets t
+        JOIN events e ON e.id = t.event_id
+        LEFT JOIN ticket_tiers tt ON tt.id = t.tier_id
+        WHERE t.user_id = $1 AND t.status IN ('Sold', 'CheckedIn', 'Cancelled')
+        ORDER BY e.start_time, e.id, t.id
+        "#,
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let mut wallet: Vec<EventTickets> = Vec::new();
+    for row in rows {
+        let credential = match row.status {
+            TicketStatus::Sold | TicketStatus::CheckedIn => Some(
+                Credential {
+                    ticket_id: row.id,
+                    event_id: row.event_id,
+                    holder_id: user_id,
+                    nonce: row.credential_nonce,
+                }
+                .sign(&credentials::signing_key(row.event_id)),
+            ),
+            _ => None,
+        };
+        let ticket = WalletTicket {
+            ticket_id: row.id,
+            status: row.status,
+            seat: row.seat,
+            tier_id: row.tier_id,
+            tier: row.tier,
+            price: Money::new(row.price_cents, row.currency),
+            order_id: row.order_id,
+            checked_in_at: row.checked_in_at,
+            credential,
+        };
+
+        match wallet.last_mut() {
+            Some(event) if event.event_id == row.event_id => event.tickets.push(ticket),
+            _ => wallet.push(EventTickets {
+                event_id: row.event_id,
+                event_name: row.event_name,
+                event_status: row.event_status,
+                location: row.location,
+                address: row.address,
+                start_time: row.start_time,
+                end_time: row.end_time,
+                tickets: vec![ticket],
+            }),
+        }
+    }
+    Ok(wallet)
+}
+
+pub async fn my_tickets(
+    user: AuthUser,
+    State(pg_pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(tickets_of(&pg_pool, user.id).await?))
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use axum::extract::FromRequestParts;
+use axum::http::{header::AUTHORIZATION, Request};
+use chrono::{Duration, Utc};
+use common::secrets;
+use omicron::auth::jwt::{encode_token, AuthUser, Claims, ISSUER};
+use omicron::error::ApiError;
+
+fn claims(user_id: i32, issuer: &str, expires_in: Duration) -> Claims {
+    Claims {
+        sub: user_id.to_string(),
+        exp: (Utc::now() + expires_in).timestamp() as usize,
+        iss: issuer.to_string(),
+        email: "ada@halo.test".to_string(),
+        name: "Ada".to_string(),
+    }
+}
+
+async fn extract(authorization: Option<String>) -> Result<AuthUser, ApiError> {
+    secrets();
+    let mut request = Request::builder();
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    let (mut parts, _) = request.body(()).unwrap().into_parts();
+    AuthUser::from_request_parts(&mut parts, &()).await
+}
+
+async fn extract_token(claims: &Claims) -> Result<AuthUser, ApiError> {
+    secrets();
+    let token = encode_token(claims).unwrap();
+    extract(Some(format!("Bearer {}", token))).await
+}
+
+#[tokio::test]
+async fn issued_tokens_authenticate_their_user() {
+    let user = extract_token(&claims(42, ISSUER, Duration::minutes(15)))
+        .await
+        .unwrap();
+    assert_eq!(user.id, 42);
+    assert_eq!(user.email, "ada@halo.test");
+    assert_eq!(user.name, "Ada");
+}
+
+#[tokio::test]
+async fn tokens_from_other_issuers_or_past_expiry_are_rejected() {
+    assert!(matches!(
+        extract_token(&claims(42, "someone-else", Duration::minutes(15))).await,
+        Err(ApiError::Unauthorized(_))
+    ));
+    // Well past the validator's leeway for clock skew.
+    assert!(matches!(
+        extract_token(&claims(42, ISSUER, -Duration::hours(1))).await,
+        Err(ApiError::Unauthorized(_))
+    ));
+
+    let mut not_a_user = claims(42, ISSUER, Duration::minutes(15));
+    not_a_user.sub = "admin".to_string();
+    assert!(matches!(
+        extract_token(&not_a_user).await,
+        Err(ApiError::Unauthorized(_))
+    ));
+}
+
+#[tokio::test]
+async fn requests_need_a_bearer_token() {
+    assert!(matches!(
+        extract(None).await,
+        Err(ApiError::Unauthorized(_))
+    ));
+    assert!(matches!(
+        extract(Some("Basic YWRhOnB3".to_string())).await,
+        Err(ApiError::Unauthorized(_))
+    ));
+    assert!(matches!(
+        extract(Some("Bearer not-a-jwt".to_string())).await,
+        Err(ApiError::Unauthorized(_))
+    ));
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{buy, event_starting, pool, secrets, usd, user, PROVIDER};
+use omicron::checkin::{self, CheckInError, Scan, ScanOutcome};
+use omicron::credentials::{self, Credential, CredentialError, Verifier};
+use omicron::models::ticket::TicketStatus;
+use omicron::{refunds, resale};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A free event starting within the hour, with one buyer holding
+/// `quantity` tickets.
+async fn seed(pg_pool: &PgPool, quantity: i64) -> (i32, i32) {
+    let user_id = user(pg_pool).await;
+    let event_id = event_starting(
+        pg_pool,
+        "Check-in Test",
+        10,
+        0,
+        Utc::now() + Duration::hours(1),
+    )
+    .await;
+    buy(pg_pool, user_id, event_id, quantity).await;
+    (user_id, event_id)
+}
+
+#[test]
+fn verifier_accepts_only_untampered_credentials_for_its_event() {
+    secrets();
+    let credential = Credential {
+        ticket_id: 7,
+        event_id: 3,
+        holder_id: 11,
+        nonce: Uuid::new_v4(),
+    };
+    let token = credential.sign(&credentials::signing_key(3));
+
+    let public_key = credentials::encode_public_key(&credentials::signing_key(3).verifying_key());
+    let verifier = Verifier::from_public_key(3, &public_key).unwrap();
+    assert_eq!(verifier.verify(&token), Ok(credential));
+
+    assert_eq!(
+        Verifier::from_public_key(4, &public_key)
+            .unwrap()
+            .verify(&token),
+        Err(CredentialError::WrongEvent {
+            expected: 4,
+            found: 3
+        })
+    );
+
+    let forged = Credential {
+        holder_id: 12,
+        ..credential
+    }
+    .sign(&credentials::signing_key(99));
+    assert_eq!(
+        verifier.verify(&forged),
+        Err(CredentialError::InvalidSignature)
+    );
+    assert_eq!(
+        verifier.verify("not a credential"),
+        Err(CredentialError::Malformed)
+    );
+}
+
+#[tokio::test]
+async fn check_in_admits_each_ticket_once() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 2).await;
+
+    let issued = checkin::credentials_for(&pg_pool, user_id, event_id)
+        .await
+        .unwrap();
+    assert_eq!(issued.len(), 2);
+
+    let admitted = checkin::admit(&pg_pool, event_id, &issued[0].credential, None, Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(admitted.ticket_id, issued[0].ticket_id);
+    assert_eq!(admitted.holder_id, user_id);
+
+    assert!(matches!(
+        checkin::admit(&pg_pool, event_id, &issued[0].credential, None, Utc::now()).await,
+        Err(CheckInError::AlreadyCheckedIn(id, ..)) if id == issued[0].ticket_id
+    ));
+
+    checkin::admit(&pg_pool, event_id, &issued[1].credential, None, Utc::now())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn check_in_rejects_credentials_for_a_previous_holder() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 1).await;
+
+    let issued = checkin::credentials_for(&pg_pool, user_id, event_id)
+        .await
+        .unwrap();
+    let new_holder = user(&pg_pool).await;
+    sqlx::query!(
+        "UPDATE tickets SET user_id = $2 WHERE id = $1",
+        issued[0].ticket_id,
+        new_holder
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        checkin::admit(&pg_pool, event_id, &issued[0].credential, None, Utc::now()).await,
+        Err(CheckInError::Revoked(_))
+    ));
+}
+
+#[tokio::test]
+async fn resold_tickets_stop_admitting_the_seller() {
+    let pg_pool = pool().await;
+    let (seller, event_id) = seed(&pg_pool, 1).await;
+    let buyer = user(&pg_pool).await;
+
+    let sold = checkin::credentials_for(&pg_pool, seller, event_id)
+        .await
+        .unwrap();
+    let listing = resale::create_listing(&pg_pool, seller, sold[0].ticket_id, usd(1000))
+        .await
+        .unwrap();
+    resale::purchase_listing(&pg_pool, &*PROVIDER, buyer, listing.id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        checkin::admit(&pg_pool, event_id, &sold[0].credential, None, Utc::now()).await,
+        Err(CheckInError::Revoked(_))
+    ));
+    // Offline doors only have the manifest's nonce to go on.
+    let old_nonce = credentials::verifier(event_id)
+        .verify(&sold[0].credential)
+        .unwrap()
+        .nonce;
+    let manifest = checkin::manifest(&pg_pool, event_id).await.unwrap();
+    assert_ne!(manifest.tickets[0].nonce, old_nonce);
+
+    let reissued = checkin::credentials_for(&pg_pool, buyer, event_id)
+        .await
+        .unwrap();
+    checkin::admit(
+        &pg_pool,
+        event_id,
+        &reissued[0].credential,
+        None,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn refunded_tickets_stay_in_the_manifest_as_cancelled() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 1).await;
+    let issued = checkin::credentials_for(&pg_pool, user_id, event_id)
+        .await
+        .unwrap();
+
+    refunds::refund_event(&pg_pool, &*PROVIDER, event_id)
+        .await
+        .unwrap();
+
+    let manifest = checkin::manifest(&pg_pool, event_id).await.unwrap();
+    assert_eq!(manifest.tickets.len(), 1);
+    assert_eq!(manifest.tickets[0].ticket_id, issued[0].ticket_id);
+    assert_eq!(manifest.tickets[0].status, TicketStatus::Cancelled);
+    assert!(
+        checkin::admit(&pg_pool, event_id, &issued[0].credential, None, Utc::now())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn reconcile_flags_tickets_scanned_at_two_doors() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 1).await;
+
+    let issued = checkin::credentials_for(&pg_pool, user_id, event_id)
+        .await
+        .unwrap();
+    let scans = vec![Scan {
+        credential: issued[0].credential.clone(),
+        scanned_at: Utc::now(),
+    }];
+
+    let north = checkin::reconcile(&pg_pool, event_id, "north", &scans)
+        .await
+        .unwrap();
+    assert!(matches!(&north[..], [ScanOutcome::Admitted(_)]));
+
+    // Retrying the same batch is not a conflict.
+    let retried = checkin::reconcile(&pg_pool, event_id, "north", &scans)
+        .await
+        .unwrap();
+    assert!(matches!(&retried[..], [ScanOutcome::Admitted(_)]));
+
+    let south = checkin::reconcile(&pg_pool, event_id, "south", &scans)
+        .await
+        .unwrap();
+    assert!(matches!(
+        &south[..],
+        [ScanOutcome::Conflict { door: Some(door), .. }] if door == "north"
+    ));
+}
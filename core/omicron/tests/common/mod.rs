@@ -0,0 +1,196 @@
+//! Fixtures shared by the integration tests. Every test seeds its own users
+//! and events, so tests running side by side never touch each other's rows.
+#![allow(dead_code)]
+
+use axum::response::IntoResponse;
+use chrono::{DateTime, Duration, Utc};
+use omicron::auth::jwt::AuthUser;
+use omicron::internal::{purchase_tickets, TicketPurchase};
+use omicron::models::tier::{self, TicketTier};
+use omicron::money::{Currency, Money};
+use omicron::payments::fake::FakeProvider;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::sync::Once;
+
+pub static PROVIDER: Lazy<FakeProvider> = Lazy::new(|| FakeProvider::new("test-secret"));
+
+static SECRETS: Once = Once::new();
+
+/// Sets the signing secrets the server refuses to start without. Call it
+/// before anything signs or verifies a token or credential.
+pub fn secrets() {
+    SECRETS.call_once(|| {
+        std::env::set_var("JWT_SECRET", "test-jwt-secret");
+        std::env::set_var("TICKET_SIGNING_SECRET", "test-ticket-secret");
+    });
+}
+
+pub async fn pool() -> PgPool {
+    secrets();
+    dotenv::dotenv().ok();
+    let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to run tests");
+    PgPoolOptions::new()
+        .max_connections(16)
+        .connect(&db_url)
+        .await
+        .expect("connection error...")
+}
+
+pub async fn user(pg_pool: &PgPool) -> i32 {
+    sqlx::query_scalar!(
+        "INSERT INTO users (name, email) VALUES ('user', $1) RETURNING id",
+        format!("user-{}@halo.test", uuid::Uuid::new_v4())
+    )
+    .fetch_one(pg_pool)
+    .await
+    .unwrap()
+}
+
+/// The caller as handlers see it once their bearer token checks out.
+pub fn auth_user(id: i32) -> AuthUser {
+    AuthUser {
+        id,
+        email: "user@halo.test".to_string(),
+        name: "user".to_string(),
+    }
+}
+
+/// Decodes a handler's JSON response body.
+pub async fn body<T: DeserializeOwned>(response: impl IntoResponse) -> T {
+    let body = response.into_response().into_body();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+/// A published event selling `capacity` tickets at `price_cents`, starting
+/// a day from now.
+pub async fn event(pg_pool: &PgPool, capacity: i64, price_cents: i64) -> i32 {
+    event_starting(
+        pg_pool,
+        "Test Event",
+        capacity,
+        price_cents,
+        Utc::now() + Duration::days(1),
+    )
+    .await
+}
+
+/// A published event named `name` that starts at `start_time` and runs for
+/// a day.
+pub async fn event_starting(
+    pg_pool: &PgPool,
+    name: &str,
+    capacity: i64,
+    price_cents: i64,
+    start_time: DateTime<Utc>,
+) -> i32 {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO events (
+            name, location, address, category, capacity, available, price_cents, status,
+            start_time, end_time
+        )
+        VALUES ($1, 'Warehouse', '1 Main St', 'Concert', $2, $2, $3, 'Published', $4, $5)
+        RETURNING id
+        "#,
+        name,
+        capacity,
+        price_cents,
+        start_time,
+        start_time + Duration::days(1)
+    )
+    .fetch_one(pg_pool)
+    .await
+    .unwrap()
+}
+
+pub async fn add_tier(
+    pg_pool: &PgPool,
+    event_id: i32,
+    name: &str,
+    cents: i64,
+    quantity: i64,
+) -> i32 {
+    let tier = TicketTier::new(
+        event_id,
+        name.to_string(),
+        None,
+        usd(cents),
+        quantity,
+        None,
+        None,
+        1,
+        None,
+    );
+    tier::store::insert(pg_pool, &tier).await.unwrap()
+}
+
+pub fn request(user_id: i32, event_id: i32, tier_id: Option<i32>, quantity: i64) -> TicketPurchase {
+    TicketPurchase {
+        user_id,
+        event_id,
+        tier_id,
+        quantity,
+        promo_code: None,
+    }
+}
+
+/// Buys `quantity` general admission tickets and returns their ids.
+pub async fn buy(pg_pool: &PgPool, user_id: i32, event_id: i32, quantity: i64) -> Vec<i32> {
+    purchase_tickets(
+        pg_pool,
+        &*PROVIDER,
+        &request(user_id, event_id, None, quantity),
+    )
+    .await
+    .unwrap()
+    .ticket_ids
+}
+
+/// Tickets `event_id` has left to sell.
+pub async fn available(pg_pool: &PgPool, event_id: i32) -> i64 {
+    sqlx::query_scalar!("SELECT available FROM events WHERE id = $1", event_id)
+        .fetch_one(pg_pool)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+/// Makes inserts into `table` that match `condition` raise, to simulate a
+/// database failure partway through a transaction. Returns the trigger name
+/// for `allow_inserts`.
+pub async fn fail_inserts(pg_pool: &PgPool, table: &str, condition: &str) -> String {
+    let name = format!("fail_{}", uuid::Uuid::new_v4().simple());
+    sqlx::query(&format!(
+        "CREATE FUNCTION {0}() RETURNS trigger AS $$ BEGIN RAISE EXCEPTION '{0}'; END $$ LANGUAGE plpgsql",
+        name
+    ))
+    .execute(pg_pool)
+    .await
+    .unwrap();
+    sqlx::query(&format!(
+        "CREATE TRIGGER {0} BEFORE INSERT ON {1} FOR EACH ROW WHEN ({2}) EXECUTE FUNCTION {0}()",
+        name, table, condition
+    ))
+    .execute(pg_pool)
+    .await
+    .unwrap();
+    name
+}
+
+pub async fn allow_inserts(pg_pool: &PgPool, table: &str, name: &str) {
+    sqlx::query(&format!("DROP TRIGGER {} ON {}", name, table))
+        .execute(pg_pool)
+        .await
+        .unwrap();
+    sqlx::query(&format!("DROP FUNCTION {}", name))
+        .execute(pg_pool)
+        .await
+        .unwrap();
+}
+
+pub fn usd(cents: i64) -> Money {
+    Money::new(cents, Currency::USD)
+}
@@ -0,0 +1,320 @@
+mod common;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{Duration, Utc};
+use common::{auth_user, body, event_starting, pool, user};
+use omicron::error::ApiError;
+use omicron::events::{self, CreateEventRequest, UpdateEventRequest};
+use omicron::models::event::{self as event_model, Event, EventCategory, EventStatus};
+use omicron::money::{Currency, Money};
+use omicron::public;
+use sqlx::PgPool;
+
+fn create_request(title: &str) -> Json<CreateEventRequest> {
+    let start_time = Utc::now() + Duration::days(7);
+    Json(CreateEventRequest {
+        title: title.to_string(),
+        description: None,
+        location: "Warehouse".to_string(),
+        address: "1 Main St".to_string(),
+        category: EventCategory::Club,
+        start_time,
+        end_time: start_time + Duration::hours(6),
+        capacity: 100,
+        price: None,
+        is_private: false,
+        tags: vec![],
+        image_url: None,
+    })
+}
+
+fn patch() -> UpdateEventRequest {
+    UpdateEventRequest {
+        title: None,
+        description: None,
+        location: None,
+        address: None,
+        category: None,
+        start_time: None,
+        end_time: None,
+        capacity: None,
+        price: None,
+        is_private: None,
+        image_url: None,
+        add_tags: vec![],
+        remove_tags: vec![],
+    }
+}
+
+/// A published public event starting at `start_time`, moved to `status`
+/// and made private if asked.
+async fn listed_candidate(
+    pg_pool: &PgPool,
+    start_time: chrono::DateTime<Utc>,
+    status: EventStatus,
+    is_private: bool,
+) -> i32 {
+    let event_id = event_starting(pg_pool, "Listing Test", 10, 1000, start_time).await;
+    sqlx::query!(
+        "UPDATE events SET status = $2, is_private = $3 WHERE id = $1",
+        event_id,
+        status as EventStatus,
+        is_private
+    )
+    .execute(pg_pool)
+    .await
+    .unwrap();
+    event_id
+}
+
+async fn create(pg_pool: &PgPool, owner: i32) -> Event {
+    let response = events::create(
+        auth_user(owner),
+        State(pg_pool.clone()),
+        create_request("Launch Party"),
+    )
+    .await
+    .unwrap();
+    body(response).await
+}
+
+async fn update(
+    pg_pool: &PgPool,
+    user_id: i32,
+    event_id: i32,
+    req: UpdateEventRequest,
+) -> Result<Event, ApiError> {
+    let response = events::update(
+        auth_user(user_id),
+        Path(event_id),
+        State(pg_pool.clone()),
+        Json(req),
+    )
+    .await?;
+    Ok(body(response).await)
+}
+
+#[tokio::test]
+async fn organizers_create_drafts_edit_and_publish_them() {
+    let pg_pool = pool().await;
+    let owner = user(&pg_pool).await;
+
+    let event = create(&pg_pool, owner).await;
+    assert_eq!(event.status, EventStatus::Draft);
+    assert_eq!(event.creator_id, Some(owner));
+    assert_eq!(event.available, Some(100));
+
+    let mut req = patch();
+    req.title = Some("Launch Party II".to_string());
+    req.capacity = Some(120);
+    req.add_tags = vec!["techno".to_string()];
+    let event = update(&pg_pool, owner, event.id, req).await.unwrap();
+    assert_eq!(event.title, "Launch Party II");
+    assert_eq!((event.capacity, event.available), (120, Some(120)));
+    assert_eq!(event.tags, vec!["techno".to_string()]);
+    assert_eq!(event.status, EventStatus::Draft);
+
+    let response = events::publish(auth_user(owner), Path(event.id), State(pg_pool.clone()))
+        .await
+        .unwrap();
+    let event: Event = body(response).await;
+    assert_eq!(event.status, EventStatus::Published);
+
+    assert!(matches!(
+        events::publish(auth_user(owner), Path(event.id), State(pg_pool.clone())).await,
+        Err(ApiError::Conflict(_))
+    ));
+}
+
+#[tokio::test]
+async fn invalid_edits_are_rejected() {
+    let pg_pool = pool().await;
+    let owner = user(&pg_pool).await;
+    let event = create(&pg_pool, owner).await;
+
+    let mut req = patch();
+    req.end_time = Some(event.start_time - Duration::hours(1));
+    assert!(matches!(
+        update(&pg_pool, owner, event.id, req).await,
+        Err(ApiError::ValidationFailed(_))
+    ));
+
+    let mut invalid = create_request("");
+    invalid.capacity = 0;
+    assert!(matches!(
+        events::create(auth_user(owner), State(pg_pool.clone()), invalid).await,
+        Err(ApiError::ValidationFailed(_))
+    ));
+}
+
+#[tokio::test]
+async fn only_the_creator_can_manage_an_event() {
+    let pg_pool = pool().await;
+    let (owner, other) = (user(&pg_pool).await, user(&pg_pool).await);
+    let event = create(&pg_pool, owner).await;
+
+    let mut req = patch();
+    req.title = Some("Mine now".to_string());
+    assert!(matches!(
+        update(&pg_pool, other, event.id, req).await,
+        Err(ApiError::Forbidden(_))
+    ));
+    assert!(matches!(
+        events::publish(auth_user(other), Path(event.id), State(pg_pool.clone())).await,
+        Err(ApiError::Forbidden(_))
+    ));
+    assert!(matches!(
+        events::cancel(auth_user(other), Path(event.id), State(pg_pool.clone())).await,
+        Err(ApiError::Forbidden(_))
+    ));
+    assert!(matches!(
+        update(&pg_pool, owner, -1, patch()).await,
+        Err(ApiError::NotFound(_))
+    ));
+
+    let event = event_model::store::fetch(&pg_pool, event.id).await.unwrap();
+    assert_eq!(event.title, "Launch Party");
+    assert_eq!(event.status, EventStatus::Draft);
+}
+
+#[tokio::test]
+async fn cancelled_events_are_frozen() {
+    let pg_pool = pool().await;
+    let owner = user(&pg_pool).await;
+    let event = create(&pg_pool, owner).await;
+
+    let response = events::cancel(auth_user(owner), Path(event.id), State(pg_pool.clone()))
+        .await
+        .unwrap();
+    let cancelled: serde_json::Value = body(response).await;
+    assert_eq!(cancelled["event"]["status"], "Cancelled");
+
+    assert!(matches!(
+        update(&pg_pool, owner, event.id, patch()).await,
+        Err(ApiError::Conflict(_))
+    ));
+    assert!(matches!(
+        events::publish(auth_user(owner), Path(event.id), State(pg_pool.clone())).await,
+        Err(ApiError::Conflict(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn edits_never_undo_a_concurrent_cancellation() {
+    let pg_pool = pool().await;
+    let owner = user(&pg_pool).await;
+    let mut event = create(&pg_pool, owner).await;
+
+    // Cancel inside an open transaction, then edit while it is still open.
+    let mut tx = pg_pool.begin().await.unwrap();
+    event_model::store::lock(&mut tx, event.id).await.unwrap();
+    event.cancel().unwrap();
+    event_model::store::set_status(&mut tx, &event)
+        .await
+        .unwrap();
+
+    let mut req = patch();
+    req.title = Some("Renamed".to_string());
+    let edit = tokio::spawn({
+        let pg_pool = pg_pool.clone();
+        async move { update(&pg_pool, owner, event.id, req).await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    tx.commit().await.unwrap();
+
+    assert!(matches!(edit.await.unwrap(), Err(ApiError::Conflict(_))));
+    let event = event_model::store::fetch(&pg_pool, event.id).await.unwrap();
+    assert_eq!(event.status, EventStatus::Cancelled);
+    assert_eq!(event.title, "Launch Party");
+}
+
+#[tokio::test]
+async fn events_round_trip_through_the_store() {
+    let pg_pool = pool().await;
+    let owner = user(&pg_pool).await;
+    let start_time = Utc::now() + Duration::days(3);
+    let draft = Event::new(
+        "Rooftop".to_string(),
+        Some("Sunset set".to_string()),
+        "Roof".to_string(),
+        "2 High St".to_string(),
+        EventCategory::Concert,
+        start_time,
+        start_time + Duration::hours(4),
+        50,
+        Money::new(4200, Currency::EUR),
+        true,
+        owner,
+        vec!["outdoor".to_string()],
+        Some("https://img.halo.test/roof.png".to_string()),
+    );
+
+    let inserted = event_model::store::insert(&pg_pool, &draft).await.unwrap();
+    let fetched = event_model::store::fetch(&pg_pool, inserted.id)
+        .await
+        .unwrap();
+    assert_eq!(fetched.title, "Rooftop");
+    assert_eq!(fetched.description.as_deref(), Some("Sunset set"));
+    assert_eq!(fetched.category, EventCategory::Concert);
+    assert_eq!(fetched.status, EventStatus::Draft);
+    assert_eq!(fetched.capacity, 50);
+    assert_eq!(fetched.available, Some(50));
+    assert_eq!(fetched.price, Money::new(4200, Currency::EUR));
+    assert!(fetched.is_private);
+    assert_eq!(fetched.creator_id, Some(owner));
+    assert_eq!(fetched.tags, vec!["outdoor".to_string()]);
+    assert_eq!(
+        fetched.image_url.as_deref(),
+        Some("https://img.halo.test/roof.png")
+    );
+
+    assert!(matches!(
+        event_model::store::fetch(&pg_pool, -1).await,
+        Err(sqlx::Error::RowNotFound)
+    ));
+}
+
+#[tokio::test]
+async fn listings_show_public_events_that_left_draft_soonest_first() {
+    let pg_pool = pool().await;
+    let soon = Utc::now() + Duration::days(400);
+    let later = listed_candidate(
+        &pg_pool,
+        soon + Duration::days(2),
+        EventStatus::Published,
+        false,
+    )
+    .await;
+    let sooner = listed_candidate(&pg_pool, soon, EventStatus::Published, false).await;
+    let cancelled = listed_candidate(
+        &pg_pool,
+        soon + Duration::days(1),
+        EventStatus::Cancelled,
+        false,
+    )
+    .await;
+    let draft = listed_candidate(&pg_pool, soon, EventStatus::Draft, false).await;
+    let private = listed_candidate(&pg_pool, soon, EventStatus::Published, true).await;
+    let ours = [later, sooner, cancelled, draft, private];
+
+    let listed: Vec<i32> = event_model::store::fetch_listed(&pg_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|event| event.id)
+        .filter(|id| ours.contains(id))
+        .collect();
+    assert_eq!(listed, vec![sooner, cancelled, later]);
+
+    let response = public::events(State(pg_pool.clone())).await.unwrap();
+    let served: Vec<Event> = body(response).await;
+    let served: Vec<i32> = served
+        .into_iter()
+        .map(|event| event.id)
+        .filter(|id| ours.contains(id))
+        .collect();
+    assert_eq!(served, listed);
+}
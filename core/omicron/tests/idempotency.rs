@@ -0,0 +1,141 @@
+mod common;
+
+use common::{event, pool, request, user, PROVIDER};
+use omicron::idempotency::{self, PurchaseIntent};
+use omicron::internal::{purchase_tickets, PurchaseError, TicketPurchase};
+use sqlx::PgPool;
+
+const PRICE_CENTS: i64 = 1500;
+
+async fn seed(pg_pool: &PgPool, available: i64) -> (i32, i32) {
+    (
+        user(pg_pool).await,
+        event(pg_pool, available, PRICE_CENTS).await,
+    )
+}
+
+fn intent(request: &TicketPurchase) -> PurchaseIntent {
+    PurchaseIntent {
+        event_id: request.event_id,
+        tier_id: request.tier_id,
+        quantity: request.quantity,
+        promo_code: request.promo_code.clone(),
+    }
+}
+
+async fn buy(
+    pg_pool: &PgPool,
+    key: Option<&str>,
+    request: &TicketPurchase,
+) -> Result<Vec<i32>, PurchaseError> {
+    idempotency::purchase_once(
+        pg_pool,
+        request.user_id,
+        key,
+        &intent(request),
+        purchase_tickets(pg_pool, &*PROVIDER, request),
+    )
+    .await
+    .map(|purchase| purchase.ticket_ids)
+}
+
+async fn issued(pg_pool: &PgPool, event_id: i32) -> i64 {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM tickets WHERE event_id = $1"#,
+        event_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn retries_get_the_first_purchase_back() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+    let request = request(user_id, event_id, None, 2);
+
+    let first = buy(&pg_pool, Some("checkout-1"), &request).await.unwrap();
+    let retry = buy(&pg_pool, Some("checkout-1"), &request).await.unwrap();
+    assert_eq!(retry, first);
+    assert_eq!(issued(&pg_pool, event_id).await, 2);
+
+    let payments = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM payments WHERE event_id = $1"#,
+        event_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(payments, 1);
+
+    // Without a key, or with a new one, it is a new purchase.
+    buy(&pg_pool, None, &request).await.unwrap();
+    buy(&pg_pool, Some("checkout-2"), &request).await.unwrap();
+    assert_eq!(issued(&pg_pool, event_id).await, 6);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_retries_buy_once() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+
+    let attempts = (0..8).map(|_| {
+        let pg_pool = pg_pool.clone();
+        tokio::spawn(async move {
+            let request = request(user_id, event_id, None, 3);
+            buy(&pg_pool, Some("double-tap"), &request).await
+        })
+    });
+
+    let mut bought = Vec::new();
+    for attempt in attempts.collect::<Vec<_>>() {
+        match attempt.await.unwrap() {
+            Ok(ticket_ids) => bought.push(ticket_ids),
+            Err(PurchaseError::PurchaseInProgress(_)) => {}
+            Err(e) => panic!("unexpected purchase error: {}", e),
+        }
+    }
+    assert!(!bought.is_empty());
+    assert!(bought.iter().all(|ticket_ids| *ticket_ids == bought[0]));
+    assert_eq!(issued(&pg_pool, event_id).await, 3);
+}
+
+#[tokio::test]
+async fn keys_are_tied_to_one_purchase() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 2).await;
+
+    // A purchase that fails leaves the key free for another try.
+    assert!(matches!(
+        buy(
+            &pg_pool,
+            Some("order-7"),
+            &request(user_id, event_id, None, 5)
+        )
+        .await,
+        Err(PurchaseError::InsufficientSupply { .. })
+    ));
+    buy(
+        &pg_pool,
+        Some("order-7"),
+        &request(user_id, event_id, None, 1),
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        buy(
+            &pg_pool,
+            Some("order-7"),
+            &request(user_id, event_id, None, 2)
+        )
+        .await,
+        Err(PurchaseError::IdempotencyKeyReused(_))
+    ));
+    assert!(matches!(
+        buy(&pg_pool, Some(""), &request(user_id, event_id, None, 1)).await,
+        Err(PurchaseError::InvalidIdempotencyKey(_))
+    ));
+    assert_eq!(issued(&pg_pool, event_id).await, 1);
+}
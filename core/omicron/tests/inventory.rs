@@ -0,0 +1,116 @@
+mod common;
+
+use chrono::Duration;
+use common::{add_tier, event, pool, usd, user};
+use omicron::checkout;
+use omicron::inventory::{self, INVENTORY_CHANNEL};
+use sqlx::postgres::PgListener;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn availability_reflects_holds_tiers_and_demand_pricing() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+    let event_id = event(&pg_pool, 4, 2500).await;
+    let ga = add_tier(&pg_pool, event_id, "GA", 2500, 3).await;
+    let vip = add_tier(&pg_pool, event_id, "VIP", 9000, 1).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO pricing_rules (event_id, tier_id, kind, adjustment_bps, available_below_bps)
+        VALUES ($1, $2, 'DemandStep', 2000, 5000)
+        "#,
+        event_id,
+        ga
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    let before = inventory::availability(&pg_pool, event_id).await.unwrap();
+    assert_eq!(before.available, 4);
+    assert_eq!(before.unit_price, None);
+    assert_eq!(before.tiers[0].unit_price, usd(2500));
+
+    checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        Some(ga),
+        2,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        Some(vip),
+        1,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+
+    let after = inventory::availability(&pg_pool, event_id).await.unwrap();
+    assert_eq!(after.available, 1);
+    assert!(!after.sold_out);
+    let (ga, vip) = (&after.tiers[0], &after.tiers[1]);
+    assert_eq!((ga.available, ga.sold_out), (1, false));
+    assert_eq!(ga.unit_price, usd(3000));
+    assert_eq!((vip.available, vip.sold_out), (0, true));
+}
+
+/// Waits for `event_id` to be announced, skipping other tests' events.
+async fn announced(listener: &mut PgListener, event_id: i32) {
+    loop {
+        let notification = tokio::time::timeout(std::time::Duration::from_secs(5), listener.recv())
+            .await
+            .expect("no inventory notice")
+            .unwrap();
+        if notification.payload() == event_id.to_string() {
+            return;
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn stock_changes_are_announced_once_committed() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+    let event_id = event(&pg_pool, 2, 2500).await;
+
+    let mut listener = PgListener::connect_with(&pg_pool).await.unwrap();
+    listener.listen(INVENTORY_CHANNEL).await.unwrap();
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        2,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    announced(&mut listener, event_id).await;
+    assert!(
+        inventory::availability(&pg_pool, event_id)
+            .await
+            .unwrap()
+            .sold_out
+    );
+
+    checkout::release(&pg_pool, user_id, hold.hold_id)
+        .await
+        .unwrap();
+    announced(&mut listener, event_id).await;
+    assert_eq!(
+        inventory::availability(&pg_pool, event_id)
+            .await
+            .unwrap()
+            .available,
+        2
+    );
+}
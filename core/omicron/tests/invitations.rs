@@ -0,0 +1,243 @@
+mod common;
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use common::{auth_user, body, buy, event, pool, request, usd, user, PROVIDER};
+use omicron::error::ApiError;
+use omicron::internal::{purchase_tickets, PurchaseError};
+use omicron::invitations::{self, CreateAccessCodeRequest, InviteRequest, RedeemRequest};
+use omicron::models::event::store;
+use omicron::pricing::{self, QuoteQuery};
+use omicron::resale::{self, ResaleError};
+use omicron::{inventory, public, tiers};
+use sqlx::PgPool;
+
+/// A private event created by `owner`.
+async fn private_event(pg_pool: &PgPool, owner: i32) -> i32 {
+    let event_id = event(pg_pool, 10, 2500).await;
+    sqlx::query!(
+        "UPDATE events SET is_private = TRUE, creator_id = $2 WHERE id = $1",
+        event_id,
+        owner
+    )
+    .execute(pg_pool)
+    .await
+    .unwrap();
+    event_id
+}
+
+async fn email(pg_pool: &PgPool, user_id: i32) -> String {
+    sqlx::query_scalar!("SELECT email FROM users WHERE id = $1", user_id)
+        .fetch_one(pg_pool)
+        .await
+        .unwrap()
+}
+
+async fn invite(pg_pool: &PgPool, owner: i32, event_id: i32, emails: Vec<String>) {
+    invitations::invite(
+        auth_user(owner),
+        Path(event_id),
+        State(pg_pool.clone()),
+        Json(InviteRequest { emails }),
+    )
+    .await
+    .unwrap();
+}
+
+async fn redeem(pg_pool: &PgPool, user_id: i32, code: &str) -> Result<(), ApiError> {
+    invitations::redeem(
+        auth_user(user_id),
+        State(pg_pool.clone()),
+        Json(RedeemRequest {
+            code: code.to_string(),
+        }),
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Whether the read paths serve `event_id` to `user_id`. They must all
+/// agree, and hide the event behind the same 404 as a missing one.
+async fn sees(pg_pool: &PgPool, user_id: Option<i32>, event_id: i32) -> bool {
+    let results = [
+        tiers::tiers(
+            user_id.map(auth_user),
+            Path(event_id),
+            State(pg_pool.clone()),
+        )
+        .await
+        .map(|_| ()),
+        pricing::get_quote(
+            user_id.map(auth_user),
+            Path(event_id),
+            Query(QuoteQuery { tier_id: None }),
+            State(pg_pool.clone()),
+        )
+        .await
+        .map(|_| ()),
+        inventory::get_availability(
+            user_id.map(auth_user),
+            Path(event_id),
+            State(pg_pool.clone()),
+        )
+        .await
+        .map(|_| ()),
+        public::tickets(
+            user_id.map(auth_user),
+            Path(event_id),
+            State(pg_pool.clone()),
+        )
+        .await
+        .map(|_| ()),
+        resale::listings(
+            user_id.map(auth_user),
+            Path(event_id),
+            State(pg_pool.clone()),
+        )
+        .await
+        .map(|_| ()),
+    ];
+    let visible = results.iter().filter(|result| result.is_ok()).count();
+    assert!(results
+        .iter()
+        .all(|result| matches!(result, Ok(()) | Err(ApiError::NotFound(_)))));
+    assert!(visible == 0 || visible == results.len());
+    visible > 0
+}
+
+#[tokio::test]
+async fn private_events_sell_only_to_invited_users() {
+    let pg_pool = pool().await;
+    let (owner, guest) = (user(&pg_pool).await, user(&pg_pool).await);
+    let event_id = private_event(&pg_pool, owner).await;
+
+    assert!(invitations::has_access(&pg_pool, event_id, owner)
+        .await
+        .unwrap());
+    assert!(!invitations::has_access(&pg_pool, event_id, guest)
+        .await
+        .unwrap());
+    assert!(matches!(
+        purchase_tickets(&pg_pool, &*PROVIDER, &request(guest, event_id, None, 1)).await,
+        Err(PurchaseError::NotInvited(_))
+    ));
+
+    // Invitations match emails case-insensitively.
+    let address = email(&pg_pool, guest).await.to_uppercase();
+    invite(&pg_pool, owner, event_id, vec![address]).await;
+    assert!(invitations::has_access(&pg_pool, event_id, guest)
+        .await
+        .unwrap());
+    assert_eq!(buy(&pg_pool, guest, event_id, 1).await.len(), 1);
+}
+
+#[tokio::test]
+async fn access_codes_admit_up_to_their_limit() {
+    let pg_pool = pool().await;
+    let owner = user(&pg_pool).await;
+    let (guest, late) = (user(&pg_pool).await, user(&pg_pool).await);
+    let event_id = private_event(&pg_pool, owner).await;
+
+    let response = invitations::create_access_code(
+        auth_user(owner),
+        Path(event_id),
+        State(pg_pool.clone()),
+        Json(CreateAccessCodeRequest {
+            max_uses: Some(1),
+            expires_at: None,
+        }),
+    )
+    .await
+    .unwrap();
+    let access_code: serde_json::Value = body(response).await;
+    let code = access_code["code"].as_str().unwrap();
+
+    assert!(matches!(
+        redeem(&pg_pool, guest, "NOSUCHCD").await,
+        Err(ApiError::NotFound(_))
+    ));
+    redeem(&pg_pool, guest, &code.to_lowercase()).await.unwrap();
+    assert!(invitations::has_access(&pg_pool, event_id, guest)
+        .await
+        .unwrap());
+    // Redeeming again does not use the code up a second time.
+    redeem(&pg_pool, guest, code).await.unwrap();
+
+    assert!(matches!(
+        redeem(&pg_pool, late, code).await,
+        Err(ApiError::Conflict(_))
+    ));
+    assert!(!invitations::has_access(&pg_pool, event_id, late)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn private_events_are_hidden_from_everyone_else() {
+    let pg_pool = pool().await;
+    let (owner, guest, stranger) = (
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+    );
+    let event_id = private_event(&pg_pool, owner).await;
+    invite(
+        &pg_pool,
+        owner,
+        event_id,
+        vec![email(&pg_pool, guest).await],
+    )
+    .await;
+
+    assert!(sees(&pg_pool, Some(owner), event_id).await);
+    assert!(sees(&pg_pool, Some(guest), event_id).await);
+    assert!(!sees(&pg_pool, Some(stranger), event_id).await);
+    assert!(!sees(&pg_pool, None, event_id).await);
+
+    let listed = store::fetch_listed(&pg_pool).await.unwrap();
+    assert!(listed.iter().all(|event| event.id != event_id));
+
+    let public_event = event(&pg_pool, 10, 2500).await;
+    assert!(sees(&pg_pool, None, public_event).await);
+}
+
+#[tokio::test]
+async fn private_event_tickets_resell_only_among_invited_users() {
+    let pg_pool = pool().await;
+    let (owner, seller, stranger) = (
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+    );
+    let event_id = private_event(&pg_pool, owner).await;
+    invite(
+        &pg_pool,
+        owner,
+        event_id,
+        vec![email(&pg_pool, seller).await],
+    )
+    .await;
+    let tickets = buy(&pg_pool, seller, event_id, 2).await;
+
+    let listing = resale::create_listing(&pg_pool, seller, tickets[0], usd(3000))
+        .await
+        .unwrap();
+    assert!(matches!(
+        resale::purchase_listing(&pg_pool, &*PROVIDER, stranger, listing.id).await,
+        Err(ResaleError::NotInvited(_))
+    ));
+
+    sqlx::query!(
+        "DELETE FROM event_invitations WHERE event_id = $1",
+        event_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+    assert!(matches!(
+        resale::create_listing(&pg_pool, seller, tickets[1], usd(3000)).await,
+        Err(ResaleError::NotInvited(_))
+    ));
+}
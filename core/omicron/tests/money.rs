@@ -0,0 +1,73 @@
+use omicron::money::{Currency, Money, MoneyError};
+
+#[test]
+fn formats_with_symbol_separators_and_minor_units() {
+    assert_eq!(Money::new(123_450, Currency::USD).to_string(), "$1,234.50");
+    assert_eq!(Money::new(-300, Currency::EUR).to_string(), "-€3.00");
+    assert_eq!(Money::new(5, Currency::GBP).to_string(), "£0.05");
+    assert_eq!(Money::new(5_000, Currency::JPY).to_string(), "¥5,000");
+}
+
+#[test]
+fn serializes_cents_and_display() {
+    let json = serde_json::to_value(Money::new(2500, Currency::USD)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"cents": 2500, "currency": "USD", "display": "$25.00"})
+    );
+
+    let parsed: Money = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, Money::new(2500, Currency::USD));
+}
+
+#[test]
+fn arithmetic_is_checked() {
+    let price = Money::new(2500, Currency::USD);
+    assert_eq!(price.checked_mul(3), Ok(Money::new(7500, Currency::USD)));
+    assert_eq!(
+        price.checked_add(Money::new(100, Currency::EUR)),
+        Err(MoneyError::CurrencyMismatch(Currency::USD, Currency::EUR))
+    );
+    assert_eq!(
+        Money::new(i64::MAX, Currency::USD).checked_add(Money::new(1, Currency::USD)),
+        Err(MoneyError::Overflow)
+    );
+    assert_eq!(price.checked_mul(i64::MAX), Err(MoneyError::Overflow));
+}
+
+#[test]
+fn percentages_round_half_away_from_zero() {
+    // 2.5% of $0.99 is 2.475 cents.
+    let amount = Money::new(99, Currency::USD);
+    assert_eq!(
+        amount.checked_percent(250),
+        Ok(Money::new(2, Currency::USD))
+    );
+    // 10% of $0.05 is half a cent.
+    let amount = Money::new(5, Currency::USD);
+    assert_eq!(
+        amount.checked_percent(1_000),
+        Ok(Money::new(1, Currency::USD))
+    );
+    let amount = Money::new(-5, Currency::USD);
+    assert_eq!(
+        amount.checked_percent(1_000),
+        Ok(Money::new(-1, Currency::USD))
+    );
+}
+
+#[test]
+fn sums_totals_in_one_currency() {
+    let prices = [
+        Money::new(1000, Currency::CAD),
+        Money::new(250, Currency::CAD),
+    ];
+    assert_eq!(
+        Money::checked_sum(Currency::CAD, prices),
+        Ok(Money::new(1250, Currency::CAD))
+    );
+    assert_eq!(
+        Money::checked_sum(Currency::USD, prices),
+        Err(MoneyError::CurrencyMismatch(Currency::USD, Currency::CAD))
+    );
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{add_tier, event, pool, usd, user, PROVIDER};
+use omicron::internal::{purchase_tickets, TicketPurchase};
+use omicron::models::order::{self, GENERAL_ADMISSION};
+use omicron::payments::PaymentStatus;
+use omicron::refunds;
+use sqlx::PgPool;
+
+const PRICE_CENTS: i64 = 3000;
+
+async fn seed(pg_pool: &PgPool) -> (i32, i32) {
+    (user(pg_pool).await, event(pg_pool, 20, PRICE_CENTS).await)
+}
+
+#[tokio::test]
+async fn purchases_are_recorded_as_orders() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool).await;
+    let vip = add_tier(&pg_pool, event_id, "VIP", 8000, 5).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO promo_codes (event_id, code, kind, percent_bps)
+        VALUES ($1, 'HALF', 'Percent', 5000)
+        "#,
+        event_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    let request = TicketPurchase {
+        user_id,
+        event_id,
+        tier_id: Some(vip),
+        quantity: 3,
+        promo_code: Some("HALF".to_string()),
+    };
+    let purchase = purchase_tickets(&pg_pool, &*PROVIDER, &request)
+        .await
+        .unwrap();
+    assert_eq!(purchase.total, usd(12000));
+
+    let order = order::store::fetch(&pg_pool, purchase.order_id)
+        .await
+        .unwrap();
+    assert_eq!(order.user_id, user_id);
+    assert_eq!(order.event_name, "Test Event");
+    assert_eq!(order.items.len(), 1);
+    assert_eq!(order.items[0].tier_id, Some(vip));
+    assert_eq!(order.items[0].description, "VIP");
+    assert_eq!(order.items[0].quantity, 3);
+    assert_eq!(order.items[0].unit_price, usd(8000));
+    assert_eq!(order.items[0].ticket_ids, purchase.ticket_ids);
+    assert_eq!(order.subtotal, usd(24000));
+    assert_eq!(order.discount, usd(12000));
+    assert_eq!(order.fees, usd(0));
+    assert_eq!(order.total, purchase.total);
+    assert_eq!(order.promo_code.as_deref(), Some("HALF"));
+    assert_eq!(order.payment_status, Some(PaymentStatus::Succeeded));
+}
+
+#[tokio::test]
+async fn orders_follow_their_refunds() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool).await;
+    let request = |quantity| TicketPurchase {
+        user_id,
+        event_id,
+        tier_id: None,
+        quantity,
+        promo_code: None,
+    };
+
+    let first = purchase_tickets(&pg_pool, &*PROVIDER, &request(2))
+        .await
+        .unwrap();
+    let second = purchase_tickets(&pg_pool, &*PROVIDER, &request(1))
+        .await
+        .unwrap();
+
+    let orders = order::store::fetch_for_user(&pg_pool, user_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        orders.iter().map(|order| order.id).collect::<Vec<_>>(),
+        vec![second.order_id, first.order_id]
+    );
+    assert_eq!(orders[1].items[0].description, GENERAL_ADMISSION);
+    assert_eq!(orders[1].total, usd(2 * PRICE_CENTS));
+
+    // Cancelling the event refunds every order in full.
+    sqlx::query!(
+        "UPDATE events SET status = 'Cancelled' WHERE id = $1",
+        event_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+    refunds::refund_event(&pg_pool, &*PROVIDER, event_id)
+        .await
+        .unwrap();
+
+    let refunded = order::store::fetch(&pg_pool, first.order_id).await.unwrap();
+    assert_eq!(refunded.refunded, refunded.total);
+    assert_eq!(refunded.payment_status, Some(PaymentStatus::Refunded));
+    assert!(refunded.updated_at > refunded.created_at);
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use common::{event, pool, user};
+use omicron::checkout;
+use omicron::internal::PurchaseError;
+use omicron::money::{Currency, Money};
+use omicron::pricing::{
+    self, Clock, FixedClock, Market, PricingRule, PricingRuleKind, SystemClock, Trigger,
+};
+
+fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2027, 6, 1, 20, 0, 0).unwrap()
+}
+
+fn market(available: i64) -> Market {
+    Market {
+        base_price: Money::new(10_000, Currency::USD),
+        quantity: 100,
+        available,
+        starts_at: start(),
+    }
+}
+
+fn rule(tier_id: Option<i32>, trigger: Trigger, adjustment_bps: i32) -> PricingRule {
+    PricingRule {
+        tier_id,
+        trigger,
+        adjustment_bps,
+    }
+}
+
+fn rules() -> Vec<PricingRule> {
+    vec![
+        rule(
+            None,
+            Trigger::EarlyBird {
+                ends_at: start() - Duration::days(30),
+            },
+            -2_000,
+        ),
+        rule(
+            None,
+            Trigger::DemandStep {
+                available_below_bps: 5_000,
+            },
+            1_000,
+        ),
+        rule(
+            None,
+            Trigger::DemandStep {
+                available_below_bps: 1_000,
+            },
+            3_000,
+        ),
+        rule(
+            None,
+            Trigger::TimeToEvent {
+                hours_before_start: 24,
+            },
+            1_500,
+        ),
+    ]
+}
+
+fn unit_price_at(
+    rules: &[PricingRule],
+    tier_id: Option<i32>,
+    available: i64,
+    clock: &dyn Clock,
+) -> i64 {
+    pricing::price(rules, tier_id, &market(available), clock.now())
+        .unwrap()
+        .unit_price
+        .cents
+}
+
+#[test]
+fn early_bird_discount_applies_until_its_window_closes() {
+    let rules = rules();
+    let early = FixedClock(start() - Duration::days(60));
+    let late = FixedClock(start() - Duration::days(10));
+
+    let price = pricing::price(&rules, None, &market(100), early.now()).unwrap();
+    assert_eq!(price.unit_price.cents, 8_000);
+    assert_eq!(price.adjustments.len(), 1);
+    assert_eq!(price.adjustments[0].kind, PricingRuleKind::EarlyBird);
+
+    assert_eq!(unit_price_at(&rules, None, 100, &late), 10_000);
+}
+
+#[test]
+fn demand_steps_take_the_steepest_threshold_crossed() {
+    let rules = rules();
+    let clock = FixedClock(start() - Duration::days(10));
+
+    assert_eq!(unit_price_at(&rules, None, 50, &clock), 10_000);
+    assert_eq!(unit_price_at(&rules, None, 49, &clock), 11_000);
+    assert_eq!(unit_price_at(&rules, None, 9, &clock), 13_000);
+}
+
+#[test]
+fn adjustments_of_different_kinds_add_up() {
+    let rules = rules();
+    let clock = FixedClock(start() - Duration::hours(2));
+
+    // 10% demand step plus the 15% last-day surcharge.
+    assert_eq!(unit_price_at(&rules, None, 20, &clock), 12_500);
+}
+
+#[test]
+fn tier_rules_replace_event_wide_rules_of_the_same_kind() {
+    let mut rules = rules();
+    rules.push(rule(
+        Some(7),
+        Trigger::TimeToEvent {
+            hours_before_start: 48,
+        },
+        -10_000,
+    ));
+    let clock = FixedClock(start() - Duration::hours(36));
+
+    // Tier 7 is free in the last two days; everyone else pays full price
+    // until the last day.
+    assert_eq!(unit_price_at(&rules, Some(7), 100, &clock), 0);
+    assert_eq!(unit_price_at(&rules, Some(8), 100, &clock), 10_000);
+
+    let rule = rule(
+        None,
+        Trigger::DemandStep {
+            available_below_bps: 0,
+        },
+        500,
+    );
+    assert!(rule.validate().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn reservations_keep_the_quoted_price() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+    let event_id = event(&pg_pool, 10, 4000).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO pricing_rules (event_id, kind, adjustment_bps, available_below_bps)
+        VALUES ($1, 'DemandStep', 5000, 5000)
+        "#,
+        event_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    let quote = pricing::quote(&pg_pool, &SystemClock, event_id, None)
+        .await
+        .unwrap();
+    assert_eq!(quote.price.unit_price.cents, 4000);
+
+    // Selling past the threshold raises the price for new buyers...
+    let first = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        6,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(first.unit_price.cents, 4000);
+    let second = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        1,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(second.unit_price.cents, 6000);
+
+    // ...but not for the one holding an earlier quote.
+    let quoted = checkout::reserve_quoted(&pg_pool, user_id, &quote, 1, None, Duration::minutes(5))
+        .await
+        .unwrap();
+    assert_eq!(quoted.unit_price.cents, 4000);
+
+    let stale = pricing::quote(
+        &pg_pool,
+        &FixedClock(Utc::now() - Duration::hours(1)),
+        event_id,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        checkout::reserve_quoted(&pg_pool, user_id, &stale, 1, None, Duration::minutes(5)).await,
+        Err(PurchaseError::QuoteExpired(_))
+    ));
+}
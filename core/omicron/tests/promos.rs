@@ -0,0 +1,242 @@
+mod common;
+
+use chrono::Duration;
+use common::{add_tier, event, pool, user, PROVIDER};
+use omicron::checkout;
+use omicron::internal::{purchase_tickets, PurchaseError, TicketPurchase};
+use omicron::money::{Currency, Money};
+use omicron::promos::Discount;
+use sqlx::PgPool;
+
+fn request(user_id: i32, event_id: i32, tier_id: Option<i32>, code: &str) -> TicketPurchase {
+    TicketPurchase {
+        user_id,
+        event_id,
+        tier_id,
+        quantity: 2,
+        promo_code: Some(code.to_string()),
+    }
+}
+
+async fn uses(pg_pool: &PgPool, event_id: i32) -> (i32, i64) {
+    let uses = sqlx::query_scalar!("SELECT uses FROM promo_codes WHERE event_id = $1", event_id)
+        .fetch_one(pg_pool)
+        .await
+        .unwrap();
+    let redemptions = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM promo_redemptions r
+        JOIN promo_codes p ON p.id = r.promo_code_id
+        WHERE p.event_id = $1
+        "#,
+        event_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .unwrap();
+    (uses, redemptions)
+}
+
+#[test]
+fn discounts_never_go_below_zero() {
+    let price = Money::new(1500, Currency::USD);
+    let quarter = Discount::Percent { percent_bps: 2500 };
+    let fixed = Discount::Fixed {
+        amount: Money::new(2000, Currency::USD),
+    };
+
+    assert_eq!(
+        quarter.apply(price).unwrap(),
+        Money::new(1125, Currency::USD)
+    );
+    assert_eq!(fixed.apply(price).unwrap(), Money::zero(Currency::USD));
+    assert!(Discount::Percent { percent_bps: 0 }.validate().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn promo_codes_only_discount_their_tiers() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+    let event_id = event(&pg_pool, 20, 4000).await;
+    let ga = add_tier(&pg_pool, event_id, "GA", 4000, 10).await;
+    let vip = add_tier(&pg_pool, event_id, "VIP", 4000, 10).await;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO promo_codes (event_id, code, kind, percent_bps, tier_ids)
+        VALUES ($1, 'FRIENDS', 'Percent', 2500, $2)
+        "#,
+        event_id,
+        &[ga]
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    let purchase = purchase_tickets(
+        &pg_pool,
+        &*PROVIDER,
+        &request(user_id, event_id, Some(ga), "friends"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(purchase.total, Money::new(6000, Currency::USD));
+
+    let discount = sqlx::query_scalar!(
+        "SELECT discount_cents FROM promo_redemptions WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(discount, 2000);
+
+    assert!(matches!(
+        purchase_tickets(
+            &pg_pool,
+            &*PROVIDER,
+            &request(user_id, event_id, Some(vip), "FRIENDS")
+        )
+        .await,
+        Err(PurchaseError::PromoCodeRejected(_))
+    ));
+    assert!(matches!(
+        purchase_tickets(
+            &pg_pool,
+            &*PROVIDER,
+            &request(user_id, event_id, Some(ga), "NOPE")
+        )
+        .await,
+        Err(PurchaseError::PromoCodeNotFound(_))
+    ));
+    assert_eq!(uses(&pg_pool, event_id).await, (1, 1));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn promo_codes_enforce_usage_caps_and_windows() {
+    let pg_pool = pool().await;
+    let (alice, bob, carol) = (
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+    );
+    let event_id = event(&pg_pool, 20, 4000).await;
+    let later = event(&pg_pool, 20, 4000).await;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO promo_codes (
+            event_id, code, kind, amount_cents, currency, max_uses, max_uses_per_user
+        )
+        VALUES ($1, 'TENOFF', 'Fixed', 1000, 'USD', 2, 1)
+        "#,
+        event_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO promo_codes (event_id, code, kind, percent_bps, starts_at)
+        VALUES ($1, 'SOON', 'Percent', 5000, NOW() + INTERVAL '1 hour')
+        "#,
+        later
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    let purchase = purchase_tickets(
+        &pg_pool,
+        &*PROVIDER,
+        &request(alice, event_id, None, "TENOFF"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(purchase.total, Money::new(6000, Currency::USD));
+
+    // Once per buyer...
+    assert!(matches!(
+        purchase_tickets(
+            &pg_pool,
+            &*PROVIDER,
+            &request(alice, event_id, None, "TENOFF")
+        )
+        .await,
+        Err(PurchaseError::PromoCodeRejected(_))
+    ));
+    // ...and twice overall.
+    purchase_tickets(
+        &pg_pool,
+        &*PROVIDER,
+        &request(bob, event_id, None, "TENOFF"),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        purchase_tickets(
+            &pg_pool,
+            &*PROVIDER,
+            &request(carol, event_id, None, "TENOFF")
+        )
+        .await,
+        Err(PurchaseError::PromoCodeRejected(_))
+    ));
+
+    assert!(matches!(
+        purchase_tickets(&pg_pool, &*PROVIDER, &request(alice, later, None, "SOON")).await,
+        Err(PurchaseError::PromoCodeRejected(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn released_holds_give_their_promo_use_back() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+    let event_id = event(&pg_pool, 20, 4000).await;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO promo_codes (event_id, code, kind, percent_bps, max_uses)
+        VALUES ($1, 'ONCE', 'Percent', 10000, 1)
+        "#,
+        event_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        2,
+        Some("once"),
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(hold.total, Money::zero(Currency::USD));
+    assert_eq!(hold.discount, Some(Money::new(8000, Currency::USD)));
+    assert_eq!(uses(&pg_pool, event_id).await, (1, 1));
+
+    checkout::release(&pg_pool, user_id, hold.hold_id)
+        .await
+        .unwrap();
+    assert_eq!(uses(&pg_pool, event_id).await, (0, 0));
+
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        1,
+        Some("ONCE"),
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(hold.promo_code.as_deref(), Some("ONCE"));
+}
@@ -0,0 +1,334 @@
+mod common;
+
+use axum::{extract::State, http::HeaderMap, Json};
+use chrono::Duration;
+use common::{
+    allow_inserts, auth_user, available, body, event, fail_inserts, pool, request, user, PROVIDER,
+};
+use omicron::checkout;
+use omicron::internal::{self, purchase_tickets, PurchaseError};
+use omicron::models::ticket::TicketStatus;
+use omicron::payments::fake::FakeProvider;
+use omicron::payments::PaymentStatus;
+use sqlx::PgPool;
+
+const PRICE_CENTS: i64 = 2500;
+
+async fn seed(pg_pool: &PgPool, available: i64) -> (i32, i32) {
+    (
+        user(pg_pool).await,
+        event(pg_pool, available, PRICE_CENTS).await,
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn purchase_issues_one_ticket_per_unit() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+
+    let request = request(user_id, event_id, None, 3);
+    let purchase = purchase_tickets(&pg_pool, &*PROVIDER, &request)
+        .await
+        .unwrap();
+    assert_eq!(purchase.ticket_ids.len(), 3);
+
+    assert_eq!(available(&pg_pool, event_id).await, 7);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_purchases_never_oversell() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+
+    let buyers = (0..40).map(|_| {
+        let pg_pool = pg_pool.clone();
+        tokio::spawn(async move {
+            let request = request(user_id, event_id, None, 2);
+            purchase_tickets(&pg_pool, &*PROVIDER, &request).await
+        })
+    });
+
+    let mut sold = 0;
+    for buyer in buyers.collect::<Vec<_>>() {
+        match buyer.await.unwrap() {
+            Ok(purchase) => sold += purchase.ticket_ids.len(),
+            Err(PurchaseError::InsufficientSupply { .. }) => {}
+            Err(e) => panic!("unexpected purchase error: {}", e),
+        }
+    }
+    assert_eq!(sold, 10);
+
+    let issued = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM tickets WHERE event_id = $1"#,
+        event_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(issued, 10);
+
+    assert_eq!(available(&pg_pool, event_id).await, 0);
+}
+
+#[tokio::test]
+async fn purchase_rejects_unknown_event_and_bad_quantity() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 1).await;
+
+    let unknown = request(user_id, -1, None, 1);
+    assert!(matches!(
+        purchase_tickets(&pg_pool, &*PROVIDER, &unknown).await,
+        Err(PurchaseError::EventNotFound(-1))
+    ));
+
+    let zero = request(user_id, event_id, None, 0);
+    assert!(matches!(
+        purchase_tickets(&pg_pool, &*PROVIDER, &zero).await,
+        Err(PurchaseError::InvalidQuantity(0))
+    ));
+}
+
+#[tokio::test]
+async fn purchases_are_made_for_the_signed_in_user() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+    let someone_else = user(&pg_pool).await;
+
+    // A `user_id` in the body is not part of the request and changes nothing.
+    let request = serde_json::from_value(serde_json::json!({
+        "user_id": someone_else,
+        "event_id": event_id,
+        "quantity": 2,
+    }))
+    .unwrap();
+    let response = internal::purchase_ticket(
+        auth_user(user_id),
+        State(pg_pool.clone()),
+        HeaderMap::new(),
+        Json(request),
+    )
+    .await
+    .unwrap();
+    let purchase: serde_json::Value = body(response).await;
+    let ticket_ids: Vec<i32> = serde_json::from_value(purchase["ticket_ids"].clone()).unwrap();
+
+    let holders = sqlx::query_scalar!(
+        "SELECT user_id FROM tickets WHERE id = ANY($1)",
+        &ticket_ids
+    )
+    .fetch_all(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(holders, vec![Some(user_id); 2]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn paid_hold_sells_its_tickets() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        2,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    let purchase = checkout::pay(&pg_pool, &*PROVIDER, user_id, hold.hold_id)
+        .await
+        .unwrap();
+    assert_eq!(purchase.ticket_ids, hold.ticket_ids);
+    assert_eq!(purchase.total, hold.total);
+    assert_eq!(purchase.total.cents, 2 * PRICE_CENTS);
+
+    let payment = sqlx::query!(
+        r#"SELECT amount_cents, status as "status: PaymentStatus" FROM payments WHERE hold_id = $1"#,
+        hold.hold_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(payment.amount_cents, 2 * PRICE_CENTS);
+    assert_eq!(payment.status, PaymentStatus::Succeeded);
+
+    let sold = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM tickets WHERE event_id = $1 AND status = 'Sold'"#,
+        event_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(sold, 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn releasing_a_hold_never_undoes_a_concurrent_sale() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        2,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+
+    // Sell the tickets the way `confirm` does, keeping them locked while
+    // the release reads them as still reserved.
+    let mut sale = pg_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        UPDATE tickets SET status = 'Sold', hold_id = NULL, reserved_until = NULL
+        WHERE id = ANY($1)
+        "#,
+        &hold.ticket_ids
+    )
+    .execute(&mut sale)
+    .await
+    .unwrap();
+
+    let release = tokio::spawn({
+        let pg_pool = pg_pool.clone();
+        async move { checkout::release(&pg_pool, user_id, hold.hold_id).await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    sale.commit().await.unwrap();
+    release.await.unwrap().unwrap();
+
+    let statuses = sqlx::query_scalar!(
+        r#"SELECT status as "status: TicketStatus" FROM tickets WHERE id = ANY($1)"#,
+        &hold.ticket_ids
+    )
+    .fetch_all(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(statuses, vec![TicketStatus::Sold; 2]);
+    assert_eq!(available(&pg_pool, event_id).await, 8);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn expired_hold_returns_tickets_to_stock() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        4,
+        None,
+        Duration::seconds(-1),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        checkout::pay(&pg_pool, &*PROVIDER, user_id, hold.hold_id).await,
+        Err(PurchaseError::HoldUnavailable(_))
+    ));
+
+    assert!(checkout::release_expired(&pg_pool).await.unwrap() >= 4);
+    assert_eq!(available(&pg_pool, event_id).await, 10);
+
+    // Released rows are sold again before any new ones are minted.
+    let request = request(user_id, event_id, None, 10);
+    let purchase = purchase_tickets(&pg_pool, &*PROVIDER, &request)
+        .await
+        .unwrap();
+    assert!(hold
+        .ticket_ids
+        .iter()
+        .all(|id| purchase.ticket_ids.contains(id)));
+
+    let issued = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM tickets WHERE event_id = $1"#,
+        event_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(issued, 10);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn declined_capture_releases_inventory() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+    let provider = FakeProvider::new("test-secret");
+    provider.fail_captures(true);
+
+    let request = request(user_id, event_id, None, 3);
+    assert!(matches!(
+        purchase_tickets(&pg_pool, &provider, &request).await,
+        Err(PurchaseError::PaymentFailed(_))
+    ));
+
+    assert_eq!(available(&pg_pool, event_id).await, 10);
+
+    let sold = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM tickets WHERE event_id = $1 AND status <> 'Available'"#,
+        event_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(sold, 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn failed_sale_after_capture_is_refunded() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        None,
+        2,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    // Fails recording the order, after the card was captured.
+    let trigger = fail_inserts(
+        &pg_pool,
+        "orders",
+        &format!("NEW.hold_id = '{}'", hold.hold_id),
+    )
+    .await;
+    let result = checkout::pay(&pg_pool, &*PROVIDER, user_id, hold.hold_id).await;
+    allow_inserts(&pg_pool, "orders", &trigger).await;
+    assert!(matches!(result, Err(PurchaseError::Database(_))));
+
+    let payment = sqlx::query!(
+        r#"SELECT intent_id, status as "status: PaymentStatus" FROM payments WHERE hold_id = $1"#,
+        hold.hold_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(payment.status, PaymentStatus::Refunded);
+    assert_eq!(
+        PROVIDER.intent(&payment.intent_id).unwrap().status,
+        PaymentStatus::Refunded
+    );
+
+    let sold = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM tickets WHERE event_id = $1 AND status = 'Sold'"#,
+        event_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(sold, 0);
+}
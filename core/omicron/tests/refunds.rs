@@ -0,0 +1,192 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{available, event, pool, request, usd, user};
+use omicron::internal::purchase_tickets;
+use omicron::money::{Currency, Money};
+use omicron::payments::fake::FakeProvider;
+use omicron::refunds::{self, RefundError, RefundPolicy, RefundPolicyKind};
+use omicron::resale;
+use sqlx::PgPool;
+
+const PRICE_CENTS: i64 = 4000;
+
+/// A published event with `policy` and one buyer holding `quantity` tickets.
+async fn seed(
+    pg_pool: &PgPool,
+    provider: &FakeProvider,
+    policy: Option<RefundPolicy>,
+    quantity: i64,
+) -> (i32, i32, Vec<i32>) {
+    let user_id = user(pg_pool).await;
+    let event_id = event(pg_pool, 10, PRICE_CENTS).await;
+
+    if let Some(policy) = policy {
+        sqlx::query!(
+            r#"
+            INSERT INTO event_refund_policies (event_id, kind, refund_bps, deadline)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            event_id,
+            policy.kind as RefundPolicyKind,
+            policy.refund_bps,
+            policy.deadline
+        )
+        .execute(pg_pool)
+        .await
+        .unwrap();
+    }
+
+    let purchase = purchase_tickets(
+        pg_pool,
+        provider,
+        &request(user_id, event_id, None, quantity),
+    )
+    .await
+    .unwrap();
+
+    (user_id, event_id, purchase.ticket_ids)
+}
+
+#[test]
+fn policy_applies_percentage_and_deadline() {
+    let paid = Money::new(PRICE_CENTS, Currency::USD);
+    let now = Utc::now();
+
+    let partial = RefundPolicy {
+        kind: RefundPolicyKind::Partial,
+        refund_bps: 7_500,
+        deadline: Some(now + Duration::days(1)),
+    };
+    assert_eq!(
+        partial.refund_for(paid, now).unwrap(),
+        Money::new(3000, Currency::USD)
+    );
+    assert!(matches!(
+        partial.refund_for(paid, now + Duration::days(2)),
+        Err(RefundError::DeadlinePassed(_))
+    ));
+
+    assert!(matches!(
+        RefundPolicy::default().refund_for(paid, now),
+        Err(RefundError::NotAllowed)
+    ));
+}
+
+#[tokio::test]
+async fn requested_refund_follows_policy_and_restores_inventory() {
+    let pg_pool = pool().await;
+    let provider = FakeProvider::new("test-secret");
+    let policy = RefundPolicy {
+        kind: RefundPolicyKind::Partial,
+        refund_bps: 5_000,
+        deadline: None,
+    };
+    let (user_id, event_id, ticket_ids) = seed(&pg_pool, &provider, Some(policy), 2).await;
+    assert_eq!(available(&pg_pool, event_id).await, 8);
+
+    let refund = refunds::request_refund(&pg_pool, &provider, user_id, ticket_ids[0])
+        .await
+        .unwrap();
+    assert_eq!(refund.amount, Money::new(PRICE_CENTS / 2, Currency::USD));
+    assert_eq!(available(&pg_pool, event_id).await, 9);
+
+    assert!(matches!(
+        refunds::request_refund(&pg_pool, &provider, user_id, ticket_ids[0]).await,
+        Err(RefundError::NotRefundable(_))
+    ));
+}
+
+#[tokio::test]
+async fn refund_is_refused_without_a_policy() {
+    let pg_pool = pool().await;
+    let provider = FakeProvider::new("test-secret");
+    let (user_id, event_id, ticket_ids) = seed(&pg_pool, &provider, None, 1).await;
+
+    assert!(matches!(
+        refunds::request_refund(&pg_pool, &provider, user_id, ticket_ids[0]).await,
+        Err(RefundError::NotAllowed)
+    ));
+    assert_eq!(available(&pg_pool, event_id).await, 9);
+}
+
+#[tokio::test]
+async fn cancelled_event_refunds_every_ticket_in_full() {
+    let pg_pool = pool().await;
+    let provider = FakeProvider::new("test-secret");
+    let (_, event_id, ticket_ids) = seed(&pg_pool, &provider, None, 3).await;
+
+    let summary = refunds::refund_event(&pg_pool, &provider, event_id)
+        .await
+        .unwrap();
+    assert_eq!(summary.failed, 0);
+    assert_eq!(summary.refunds.len(), 1);
+    assert_eq!(summary.refunds[0].ticket_ids.len(), ticket_ids.len());
+    assert_eq!(
+        summary.refunds[0].amount,
+        Money::new(3 * PRICE_CENTS, Currency::USD)
+    );
+    assert_eq!(available(&pg_pool, event_id).await, 10);
+
+    let payment_status = sqlx::query_scalar!(
+        "SELECT status::TEXT FROM payments WHERE event_id = $1",
+        event_id
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(payment_status.as_deref(), Some("Refunded"));
+
+    let again = refunds::refund_event(&pg_pool, &provider, event_id)
+        .await
+        .unwrap();
+    assert!(again.refunds.is_empty());
+}
+
+#[tokio::test]
+async fn cancelled_event_refunds_resold_tickets_to_their_buyer() {
+    let pg_pool = pool().await;
+    let provider = FakeProvider::new("test-secret");
+    let (seller, event_id, ticket_ids) = seed(&pg_pool, &provider, None, 2).await;
+    let buyer = user(&pg_pool).await;
+
+    let listing = resale::create_listing(&pg_pool, seller, ticket_ids[0], usd(5500))
+        .await
+        .unwrap();
+    resale::purchase_listing(&pg_pool, &provider, buyer, listing.id)
+        .await
+        .unwrap();
+
+    let summary = refunds::refund_event(&pg_pool, &provider, event_id)
+        .await
+        .unwrap();
+    assert_eq!(summary.failed, 0);
+    assert_eq!(summary.refunds.len(), 2);
+    assert_eq!(available(&pg_pool, event_id).await, 10);
+
+    // The seller only gets back the ticket they kept; the buyer gets back
+    // what they paid on resale.
+    let refunded = sqlx::query!(
+        r#"
+        SELECT r.user_id, r.amount_cents, p.listing_id, p.status::TEXT as "status!"
+        FROM refunds r
+        JOIN payments p ON p.id = r.payment_id
+        WHERE r.event_id = $1
+        ORDER BY r.user_id
+        "#,
+        event_id
+    )
+    .fetch_all(&pg_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.user_id, r.amount_cents, r.listing_id, r.status))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        refunded,
+        vec![
+            (seller, PRICE_CENTS, None, "Succeeded".to_string()),
+            (buyer, 5500, Some(listing.id), "Refunded".to_string()),
+        ]
+    );
+}
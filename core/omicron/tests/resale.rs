@@ -0,0 +1,185 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{allow_inserts, buy, event, fail_inserts, pool, usd, user, PROVIDER};
+use omicron::checkout;
+use omicron::models::ticket::TicketStatus;
+use omicron::payments::fake::FakeProvider;
+use omicron::payments::PaymentStatus;
+use omicron::resale::{self, ResaleError};
+use sqlx::PgPool;
+
+/// A seller holding one sold ticket, and a buyer.
+async fn seed(pg_pool: &PgPool) -> (i32, i32, i32) {
+    let (seller, buyer) = (user(pg_pool).await, user(pg_pool).await);
+    let event_id = event(pg_pool, 10, 3000).await;
+    let ticket_id = buy(pg_pool, seller, event_id, 1).await[0];
+    (seller, buyer, ticket_id)
+}
+
+async fn holder(pg_pool: &PgPool, ticket_id: i32) -> Option<i32> {
+    sqlx::query_scalar!("SELECT user_id FROM tickets WHERE id = $1", ticket_id)
+        .fetch_one(pg_pool)
+        .await
+        .unwrap()
+}
+
+/// The buyer's payment for `listing_id`.
+async fn payment(pg_pool: &PgPool, listing_id: i32) -> (i64, PaymentStatus) {
+    let payment = sqlx::query!(
+        r#"
+        SELECT amount_cents, status as "status: PaymentStatus"
+        FROM payments
+        WHERE listing_id = $1
+        "#,
+        listing_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .unwrap();
+    (payment.amount_cents, payment.status)
+}
+
+#[tokio::test]
+async fn buyers_pay_the_asking_price_for_the_ticket() {
+    let pg_pool = pool().await;
+    let (seller, buyer, ticket_id) = seed(&pg_pool).await;
+
+    let listing = resale::create_listing(&pg_pool, seller, ticket_id, usd(4500))
+        .await
+        .unwrap();
+    let transfer = resale::purchase_listing(&pg_pool, &*PROVIDER, buyer, listing.id)
+        .await
+        .unwrap();
+    assert_eq!(transfer.from_user_id, Some(seller));
+    assert_eq!(transfer.to_user_id, buyer);
+    assert_eq!(transfer.price, Some(usd(4500)));
+    assert_eq!(holder(&pg_pool, ticket_id).await, Some(buyer));
+    assert_eq!(
+        payment(&pg_pool, listing.id).await,
+        (4500, PaymentStatus::Succeeded)
+    );
+
+    let other = user(&pg_pool).await;
+    assert!(matches!(
+        resale::purchase_listing(&pg_pool, &*PROVIDER, other, listing.id).await,
+        Err(ResaleError::ListingUnavailable(_))
+    ));
+}
+
+#[tokio::test]
+async fn only_sold_tickets_can_be_listed() {
+    let pg_pool = pool().await;
+    let (seller, _, ticket_id) = seed(&pg_pool).await;
+
+    let hold = checkout::reserve(
+        &pg_pool,
+        seller,
+        event(&pg_pool, 10, 3000).await,
+        None,
+        1,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        resale::create_listing(&pg_pool, seller, hold.ticket_ids[0], usd(4500)).await,
+        Err(ResaleError::NotResellable(_))
+    ));
+
+    for status in [TicketStatus::CheckedIn, TicketStatus::Cancelled] {
+        sqlx::query!(
+            "UPDATE tickets SET status = $2, checked_in_at = $3 WHERE id = $1",
+            ticket_id,
+            status as TicketStatus,
+            (status == TicketStatus::CheckedIn).then(Utc::now)
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        assert!(matches!(
+            resale::create_listing(&pg_pool, seller, ticket_id, usd(4500)).await,
+            Err(ResaleError::NotResellable(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn declined_payment_leaves_the_listing_up() {
+    let pg_pool = pool().await;
+    let (seller, buyer, ticket_id) = seed(&pg_pool).await;
+    let provider = FakeProvider::new("test-secret");
+    provider.fail_captures(true);
+
+    let listing = resale::create_listing(&pg_pool, seller, ticket_id, usd(4500))
+        .await
+        .unwrap();
+    assert!(matches!(
+        resale::purchase_listing(&pg_pool, &provider, buyer, listing.id).await,
+        Err(ResaleError::PaymentFailed(_))
+    ));
+    assert_eq!(holder(&pg_pool, ticket_id).await, Some(seller));
+    assert_eq!(
+        payment(&pg_pool, listing.id).await,
+        (4500, PaymentStatus::Failed)
+    );
+
+    provider.fail_captures(false);
+    resale::purchase_listing(&pg_pool, &provider, buyer, listing.id)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn buyers_of_a_ticket_refunded_after_listing_get_their_money_back() {
+    let pg_pool = pool().await;
+    let (seller, buyer, ticket_id) = seed(&pg_pool).await;
+
+    let listing = resale::create_listing(&pg_pool, seller, ticket_id, usd(4500))
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE tickets SET status = 'Cancelled' WHERE id = $1",
+        ticket_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        resale::purchase_listing(&pg_pool, &*PROVIDER, buyer, listing.id).await,
+        Err(ResaleError::ListingUnavailable(_))
+    ));
+    assert_eq!(holder(&pg_pool, ticket_id).await, Some(seller));
+    assert_eq!(
+        payment(&pg_pool, listing.id).await,
+        (4500, PaymentStatus::Refunded)
+    );
+}
+
+#[tokio::test]
+async fn buyers_are_refunded_when_the_transfer_fails_after_payment() {
+    let pg_pool = pool().await;
+    let (seller, buyer, ticket_id) = seed(&pg_pool).await;
+
+    let listing = resale::create_listing(&pg_pool, seller, ticket_id, usd(4500))
+        .await
+        .unwrap();
+    // Fails the last write of the transfer, after the card was captured.
+    let trigger = fail_inserts(
+        &pg_pool,
+        "ticket_transfers",
+        &format!("NEW.listing_id = {}", listing.id),
+    )
+    .await;
+    let result = resale::purchase_listing(&pg_pool, &*PROVIDER, buyer, listing.id).await;
+    allow_inserts(&pg_pool, "ticket_transfers", &trigger).await;
+
+    assert!(matches!(result, Err(ResaleError::Database(_))));
+    assert_eq!(holder(&pg_pool, ticket_id).await, Some(seller));
+    assert_eq!(
+        payment(&pg_pool, listing.id).await,
+        (4500, PaymentStatus::Refunded)
+    );
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use axum::{extract::State, Json};
+use common::{auth_user, pool, user};
+use omicron::auth::handlers::{self, RefreshRequest};
+use omicron::auth::sessions::{self, Rotation};
+use omicron::error::ApiError;
+use sqlx::PgPool;
+
+/// Rotates `token` and returns its replacement.
+async fn rotated(pg_pool: &PgPool, token: &str) -> String {
+    match sessions::rotate(pg_pool, token).await.unwrap() {
+        Rotation::Rotated { refresh_token, .. } => refresh_token,
+        _ => panic!("refresh token was not rotated"),
+    }
+}
+
+fn refresh_request(refresh_token: &str) -> Json<RefreshRequest> {
+    Json(RefreshRequest {
+        refresh_token: refresh_token.to_string(),
+    })
+}
+
+#[tokio::test]
+async fn refresh_tokens_rotate_once_each() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+
+    let first = sessions::issue(&pg_pool, user_id).await.unwrap();
+    let second = match sessions::rotate(&pg_pool, &first).await.unwrap() {
+        Rotation::Rotated {
+            user_id: rotated_for,
+            refresh_token,
+        } => {
+            assert_eq!(rotated_for, user_id);
+            refresh_token
+        }
+        _ => panic!("refresh token was not rotated"),
+    };
+    assert_ne!(second, first);
+    rotated(&pg_pool, &second).await;
+
+    assert!(matches!(
+        sessions::rotate(&pg_pool, "not-a-token").await.unwrap(),
+        Rotation::Unknown
+    ));
+}
+
+#[tokio::test]
+async fn replaying_a_used_token_revokes_its_family() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+
+    let stolen = sessions::issue(&pg_pool, user_id).await.unwrap();
+    let second = rotated(&pg_pool, &stolen).await;
+    let current = rotated(&pg_pool, &second).await;
+    let other_session = sessions::issue(&pg_pool, user_id).await.unwrap();
+
+    assert!(matches!(
+        sessions::rotate(&pg_pool, &stolen).await.unwrap(),
+        Rotation::Reused
+    ));
+    // The legitimate holder is logged out too, but not their other sessions.
+    assert!(matches!(
+        sessions::rotate(&pg_pool, &current).await.unwrap(),
+        Rotation::Reused
+    ));
+    rotated(&pg_pool, &other_session).await;
+}
+
+#[tokio::test]
+async fn expired_tokens_do_not_rotate() {
+    let pg_pool = pool().await;
+    let user_id = user(&pg_pool).await;
+    let token = sessions::issue(&pg_pool, user_id).await.unwrap();
+
+    sqlx::query!(
+        "UPDATE refresh_tokens SET expires_at = NOW() - INTERVAL '1 second' WHERE user_id = $1",
+        user_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        sessions::rotate(&pg_pool, &token).await.unwrap(),
+        Rotation::Expired
+    ));
+}
+
+#[tokio::test]
+async fn logout_ends_one_session_and_logout_all_ends_every_one() {
+    let pg_pool = pool().await;
+    let (user_id, other) = (user(&pg_pool).await, user(&pg_pool).await);
+    let phone = sessions::issue(&pg_pool, user_id).await.unwrap();
+    let laptop = sessions::issue(&pg_pool, user_id).await.unwrap();
+    let tablet = sessions::issue(&pg_pool, user_id).await.unwrap();
+
+    // Nobody can log someone else out.
+    assert!(matches!(
+        handlers::logout(
+            auth_user(other),
+            State(pg_pool.clone()),
+            refresh_request(&phone)
+        )
+        .await,
+        Err(ApiError::Unauthorized(_))
+    ));
+
+    assert!(handlers::logout(
+        auth_user(user_id),
+        State(pg_pool.clone()),
+        refresh_request(&phone)
+    )
+    .await
+    .is_ok());
+    assert!(!matches!(
+        sessions::rotate(&pg_pool, &phone).await.unwrap(),
+        Rotation::Rotated { .. }
+    ));
+    let laptop = rotated(&pg_pool, &laptop).await;
+
+    assert!(
+        handlers::logout_all(auth_user(user_id), State(pg_pool.clone()))
+            .await
+            .is_ok()
+    );
+    for token in [laptop, tablet] {
+        assert!(!matches!(
+            sessions::rotate(&pg_pool, &token).await.unwrap(),
+            Rotation::Rotated { .. }
+        ));
+    }
+}
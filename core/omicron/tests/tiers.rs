@@ -0,0 +1,193 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{add_tier, event, pool, request, usd, user, PROVIDER};
+use omicron::checkout;
+use omicron::internal::{purchase_tickets, PurchaseError};
+use omicron::models::tier::{self, TicketTier};
+use sqlx::PgPool;
+
+async fn seed(pg_pool: &PgPool, capacity: i64) -> (i32, i32) {
+    (user(pg_pool).await, event(pg_pool, capacity, 1000).await)
+}
+
+/// What the event and `tier_id` each have left.
+async fn available(pg_pool: &PgPool, event_id: i32, tier_id: i32) -> (i64, i64) {
+    let tier = tier::store::fetch(pg_pool, tier_id).await.unwrap();
+    (common::available(pg_pool, event_id).await, tier.available)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn tiers_sell_at_their_own_price_and_allocation() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+    let ga = add_tier(&pg_pool, event_id, "GA", 2000, 8).await;
+    let vip = add_tier(&pg_pool, event_id, "VIP", 9000, 2).await;
+
+    assert!(matches!(
+        purchase_tickets(&pg_pool, &*PROVIDER, &request(user_id, event_id, None, 1)).await,
+        Err(PurchaseError::TierRequired(_))
+    ));
+
+    let purchase = purchase_tickets(
+        &pg_pool,
+        &*PROVIDER,
+        &request(user_id, event_id, Some(vip), 2),
+    )
+    .await
+    .unwrap();
+    assert_eq!(purchase.total, usd(18000));
+    assert_eq!(available(&pg_pool, event_id, vip).await, (8, 0));
+
+    // The event still has seats, but none of them are VIP.
+    assert!(matches!(
+        purchase_tickets(
+            &pg_pool,
+            &*PROVIDER,
+            &request(user_id, event_id, Some(vip), 1)
+        )
+        .await,
+        Err(PurchaseError::InsufficientSupply { available: 0, .. })
+    ));
+
+    purchase_tickets(
+        &pg_pool,
+        &*PROVIDER,
+        &request(user_id, event_id, Some(ga), 3),
+    )
+    .await
+    .unwrap();
+    assert_eq!(available(&pg_pool, event_id, ga).await, (5, 5));
+
+    let tiers = sqlx::query_scalar!(
+        r#"SELECT tier_id as "tier_id!" FROM tickets WHERE event_id = $1 ORDER BY id"#,
+        event_id
+    )
+    .fetch_all(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(tiers, vec![vip, vip, ga, ga, ga]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn tiers_enforce_sale_window_and_order_limits() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 20).await;
+    let (_, other_event_id) = seed(&pg_pool, 5).await;
+    let foreign = add_tier(&pg_pool, other_event_id, "GA", 1000, 5).await;
+
+    let mut early = TicketTier::new(
+        event_id,
+        "Early Bird".to_string(),
+        None,
+        usd(500),
+        5,
+        None,
+        Some(Utc::now() - Duration::hours(1)),
+        1,
+        None,
+    );
+    early.id = tier::store::insert(&pg_pool, &early).await.unwrap();
+
+    let mut tables = TicketTier::new(
+        event_id,
+        "Table".to_string(),
+        None,
+        usd(5000),
+        12,
+        None,
+        None,
+        4,
+        Some(6),
+    );
+    tables.id = tier::store::insert(&pg_pool, &tables).await.unwrap();
+
+    assert!(matches!(
+        purchase_tickets(
+            &pg_pool,
+            &*PROVIDER,
+            &request(user_id, event_id, Some(early.id), 1)
+        )
+        .await,
+        Err(PurchaseError::TierNotOnSale(_))
+    ));
+    for quantity in [2, 7] {
+        assert!(matches!(
+            purchase_tickets(
+                &pg_pool,
+                &*PROVIDER,
+                &request(user_id, event_id, Some(tables.id), quantity)
+            )
+            .await,
+            Err(PurchaseError::OrderLimit(_))
+        ));
+    }
+    assert!(matches!(
+        purchase_tickets(
+            &pg_pool,
+            &*PROVIDER,
+            &request(user_id, event_id, Some(foreign), 1)
+        )
+        .await,
+        Err(PurchaseError::TierNotFound(_))
+    ));
+
+    purchase_tickets(
+        &pg_pool,
+        &*PROVIDER,
+        &request(user_id, event_id, Some(tables.id), 4),
+    )
+    .await
+    .unwrap();
+    assert_eq!(available(&pg_pool, event_id, tables.id).await, (16, 8));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn released_hold_returns_tickets_to_its_tier() {
+    let pg_pool = pool().await;
+    let (user_id, event_id) = seed(&pg_pool, 10).await;
+    let ga = add_tier(&pg_pool, event_id, "GA", 2000, 6).await;
+    let vip = add_tier(&pg_pool, event_id, "VIP", 9000, 4).await;
+
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        Some(vip),
+        3,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(hold.tier_id, Some(vip));
+    assert_eq!(available(&pg_pool, event_id, vip).await, (7, 1));
+
+    checkout::release(&pg_pool, user_id, hold.hold_id)
+        .await
+        .unwrap();
+    assert_eq!(available(&pg_pool, event_id, vip).await, (10, 4));
+    assert_eq!(available(&pg_pool, event_id, ga).await, (10, 6));
+
+    // Rows given back are only reused within the same tier.
+    let hold = checkout::reserve(
+        &pg_pool,
+        user_id,
+        event_id,
+        Some(ga),
+        3,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    let reused = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM tickets WHERE id = ANY($1) AND tier_id = $2"#,
+        &hold.ticket_ids,
+        ga
+    )
+    .fetch_one(&pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(reused, 3);
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use chrono::Duration;
+use common::{pool, user, PROVIDER};
+use omicron::checkout;
+use omicron::internal::PurchaseError;
+use omicron::waitlist::{self, WaitlistError, WaitlistStatus};
+use sqlx::PgPool;
+
+async fn event(pg_pool: &PgPool, capacity: i64) -> i32 {
+    common::event(pg_pool, capacity, 3000).await
+}
+
+async fn status(pg_pool: &PgPool, event_id: i32, user_id: i32) -> WaitlistStatus {
+    sqlx::query_scalar!(
+        r#"
+        SELECT status as "status: WaitlistStatus"
+        FROM waitlist_entries
+        WHERE event_id = $1 AND user_id = $2
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+        event_id,
+        user_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .unwrap()
+}
+
+async fn hold(pg_pool: &PgPool, user_id: i32, event_id: i32, quantity: i64) -> checkout::Hold {
+    checkout::reserve(
+        pg_pool,
+        user_id,
+        event_id,
+        None,
+        quantity,
+        None,
+        Duration::minutes(5),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn released_tickets_are_offered_to_the_waitlist() {
+    let pg_pool = pool().await;
+    let (buyer, fan) = (user(&pg_pool).await, user(&pg_pool).await);
+    let event_id = event(&pg_pool, 2).await;
+
+    let taken = hold(&pg_pool, buyer, event_id, 2).await;
+    assert!(matches!(
+        checkout::reserve(&pg_pool, fan, event_id, None, 1, None, Duration::minutes(5)).await,
+        Err(PurchaseError::InsufficientSupply { .. })
+    ));
+
+    let entry = waitlist::join(&pg_pool, fan, event_id, None, 2)
+        .await
+        .unwrap();
+    assert_eq!(entry.status, WaitlistStatus::Waiting);
+
+    checkout::release(&pg_pool, buyer, taken.hold_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        status(&pg_pool, event_id, fan).await,
+        WaitlistStatus::Offered
+    );
+
+    // The offer waits for the fan even if they were offline when it was made,
+    // and nobody else can buy its tickets meanwhile.
+    let offers = waitlist::pending_offers(&pg_pool, fan).await.unwrap();
+    assert_eq!(offers.len(), 1);
+    assert_eq!(offers[0].ticket_ids.len(), 2);
+    assert!(matches!(
+        checkout::reserve(
+            &pg_pool,
+            buyer,
+            event_id,
+            None,
+            1,
+            None,
+            Duration::minutes(5)
+        )
+        .await,
+        Err(PurchaseError::InsufficientSupply { .. })
+    ));
+
+    let purchase = checkout::pay(&pg_pool, &*PROVIDER, fan, offers[0].hold_id)
+        .await
+        .unwrap();
+    assert_eq!(purchase.ticket_ids, offers[0].ticket_ids);
+    assert_eq!(
+        status(&pg_pool, event_id, fan).await,
+        WaitlistStatus::Purchased
+    );
+    assert!(waitlist::pending_offers(&pg_pool, fan)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn offers_too_large_to_total_are_an_error() {
+    let pg_pool = pool().await;
+    let (buyer, fan) = (user(&pg_pool).await, user(&pg_pool).await);
+    let event_id = event(&pg_pool, 2).await;
+
+    let taken = hold(&pg_pool, buyer, event_id, 2).await;
+    waitlist::join(&pg_pool, fan, event_id, None, 2)
+        .await
+        .unwrap();
+    checkout::release(&pg_pool, buyer, taken.hold_id)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE tickets SET price_cents = $2 WHERE event_id = $1",
+        event_id,
+        i64::MAX
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        waitlist::pending_offers(&pg_pool, fan).await,
+        Err(WaitlistError::Purchase(PurchaseError::Pricing(_)))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn lapsed_offers_pass_to_the_next_in_line() {
+    let pg_pool = pool().await;
+    let (buyer, first, second) = (
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+    );
+    let event_id = event(&pg_pool, 1).await;
+
+    let taken = hold(&pg_pool, buyer, event_id, 1).await;
+    waitlist::join(&pg_pool, first, event_id, None, 1)
+        .await
+        .unwrap();
+    waitlist::join(&pg_pool, second, event_id, None, 1)
+        .await
+        .unwrap();
+
+    checkout::release(&pg_pool, buyer, taken.hold_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        status(&pg_pool, event_id, first).await,
+        WaitlistStatus::Offered
+    );
+    assert_eq!(
+        status(&pg_pool, event_id, second).await,
+        WaitlistStatus::Waiting
+    );
+
+    let offer = waitlist::pending_offers(&pg_pool, first).await.unwrap();
+    sqlx::query!(
+        "UPDATE tickets SET reserved_until = NOW() - INTERVAL '1 second' WHERE hold_id = $1",
+        offer[0].hold_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+    checkout::release_expired(&pg_pool).await.unwrap();
+
+    assert_eq!(
+        status(&pg_pool, event_id, first).await,
+        WaitlistStatus::Expired
+    );
+    assert_eq!(
+        status(&pg_pool, event_id, second).await,
+        WaitlistStatus::Offered
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn buyers_queue_once_and_can_step_out() {
+    let pg_pool = pool().await;
+    let (buyer, big, small) = (
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+        user(&pg_pool).await,
+    );
+    let event_id = event(&pg_pool, 2).await;
+
+    let taken = hold(&pg_pool, buyer, event_id, 1).await;
+    hold(&pg_pool, buyer, event_id, 1).await;
+    waitlist::join(&pg_pool, big, event_id, None, 2)
+        .await
+        .unwrap();
+    assert!(matches!(
+        waitlist::join(&pg_pool, big, event_id, None, 1).await,
+        Err(WaitlistError::AlreadyWaiting(_))
+    ));
+    waitlist::join(&pg_pool, small, event_id, None, 1)
+        .await
+        .unwrap();
+
+    // One ticket back is not enough for the first in line, and the second
+    // does not get to jump ahead.
+    checkout::release(&pg_pool, buyer, taken.hold_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        status(&pg_pool, event_id, big).await,
+        WaitlistStatus::Waiting
+    );
+    assert_eq!(
+        status(&pg_pool, event_id, small).await,
+        WaitlistStatus::Waiting
+    );
+
+    waitlist::leave(&pg_pool, big, event_id).await.unwrap();
+    assert!(matches!(
+        waitlist::leave(&pg_pool, big, event_id).await,
+        Err(WaitlistError::NotWaiting(_))
+    ));
+    waitlist::make_offers(&pg_pool, event_id).await.unwrap();
+    assert_eq!(
+        status(&pg_pool, event_id, small).await,
+        WaitlistStatus::Offered
+    );
+}
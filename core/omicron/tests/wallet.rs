@@ -0,0 +1,82 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{buy, pool, user, PROVIDER};
+use omicron::credentials;
+use omicron::models::ticket::TicketStatus;
+use omicron::refunds;
+use omicron::wallet;
+use sqlx::PgPool;
+
+/// An event starting in `days` days.
+async fn event(pg_pool: &PgPool, name: &str, days: i64) -> i32 {
+    common::event_starting(pg_pool, name, 10, 2500, Utc::now() + Duration::days(days)).await
+}
+
+#[tokio::test]
+async fn tickets_are_grouped_by_event_with_credentials() {
+    let pg_pool = pool().await;
+    let holder = user(&pg_pool).await;
+    let other = user(&pg_pool).await;
+    let later = event(&pg_pool, "Later", 9).await;
+    let sooner = event(&pg_pool, "Sooner", 3).await;
+
+    let later_tickets = buy(&pg_pool, holder, later, 1).await;
+    let sooner_tickets = buy(&pg_pool, holder, sooner, 2).await;
+    buy(&pg_pool, other, sooner, 3).await;
+
+    let events = wallet::tickets_of(&pg_pool, holder).await.unwrap();
+    assert_eq!(
+        events.iter().map(|e| e.event_id).collect::<Vec<_>>(),
+        vec![sooner, later]
+    );
+    assert_eq!(events[0].event_name, "Sooner");
+    assert_eq!(
+        events[0]
+            .tickets
+            .iter()
+            .map(|t| t.ticket_id)
+            .collect::<Vec<_>>(),
+        sooner_tickets
+    );
+    assert_eq!(events[1].tickets[0].ticket_id, later_tickets[0]);
+
+    for event in &events {
+        for ticket in &event.tickets {
+            assert_eq!(ticket.status, TicketStatus::Sold);
+            assert!(ticket.order_id.is_some());
+            let credential = credentials::verifier(event.event_id)
+                .verify(ticket.credential.as_deref().unwrap())
+                .unwrap();
+            assert_eq!(credential.ticket_id, ticket.ticket_id);
+            assert_eq!(credential.holder_id, holder);
+        }
+    }
+}
+
+#[tokio::test]
+async fn refunded_tickets_have_no_credential() {
+    let pg_pool = pool().await;
+    let holder = user(&pg_pool).await;
+    let event_id = event(&pg_pool, "Called Off", 5).await;
+    buy(&pg_pool, holder, event_id, 2).await;
+
+    sqlx::query!(
+        "UPDATE events SET status = 'Cancelled' WHERE id = $1",
+        event_id
+    )
+    .execute(&pg_pool)
+    .await
+    .unwrap();
+    refunds::refund_event(&pg_pool, &*PROVIDER, event_id)
+        .await
+        .unwrap();
+
+    let events = wallet::tickets_of(&pg_pool, holder).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].tickets.len(), 2);
+    for ticket in &events[0].tickets {
+        assert_eq!(ticket.status, TicketStatus::Cancelled);
+        assert_eq!(ticket.credential, None);
+    }
+}
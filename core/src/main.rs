@@ -1,10 +1,9 @@
 use colored::*;
-use mu;
-use omicron;
 use std::thread;
 
 fn main() {
     println!("{}", "Initializing services...".green().bold());
+    omicron::check_secrets();
 
     let mu_thread = thread::spawn(|| {
         mu::run().expect("mu failed");